@@ -56,5 +56,6 @@ impl api::GoBot for DummyBot {
 
 fn main() {
     let mut mybot = DummyBot;
-    gtprust::main_loop(&mut mybot);
+    // try `dummybot --help` for the available options
+    gtprust::launcher::launch(&mut mybot);
 }
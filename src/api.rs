@@ -153,6 +153,14 @@ pub trait GoBot {
         Err(NotImplemented)
     }
 
+    /// Time left to the player, in seconds, with the number of stones
+    /// left in its byo-yomi period (0 during the main time).
+    /// Should never fail.
+    #[allow(unused_variable)]
+    fn gtp_time_left(&mut self, player: Colour, time: uint, stones: uint) -> Result<(), GTPError> {
+        Err(NotImplemented)
+    }
+
     /// Returns a vector of stones of both color in the given status,
     /// in the opinion of the bot.
     /// Should never fail.
@@ -177,6 +185,24 @@ pub trait GoBot {
         Err(NotImplemented)
     }
 
+    /// Analyses the position for the chosen player, without playing.
+    /// Intermediate results are reported as single lines through `report`
+    /// (ex: "info move D4 visits 120 winrate 5342"), which returns `false`
+    /// once the controller sent another command or is no longer listening.
+    /// The analysis should go on until then.
+    /// Should never fail.
+    #[allow(unused_variable)]
+    fn gtp_analyze(&mut self, player: Colour, report: |&str| -> bool) -> Result<(), GTPError> {
+        Err(NotImplemented)
+    }
+
+    /// Returns true if `gtp_analyze` is implemented. Unlike the other
+    /// optional commands, it is not probed by calling it, which would
+    /// run an analysis.
+    fn can_analyze(&self) -> bool {
+        false
+    }
+
     /// Allow you to handle custom commands. Returns (succes, output).
     #[allow(unused_variable)]
     fn gtp_custom_command(&mut self, command: &str, args: &str) -> (bool, String) {
@@ -194,6 +220,24 @@ pub trait GoBot {
     fn gtp_list_custom_commands(&self) -> Vec<String> {
         Vec::new()
     }
+
+    // Lifecycle hooks, called by the library, they do nothing by default.
+
+    /// Called once, when the library is ready to receive commands.
+    fn on_start(&mut self) {}
+
+    /// Called when a new game starts, after `gtp_clear_board`.
+    fn on_new_game(&mut self) {}
+
+    /// Called once per game when it is over : after two consecutive
+    /// passes or a resignation. Scoring commands do not end the game,
+    /// as controllers may ask for the score in the middle of it.
+    fn on_game_over(&mut self) {}
+
+    /// Called before the library stops, on `quit`, end of input or
+    /// termination signal (SIGINT, SIGTERM). Last chance to flush
+    /// caches, save logs and stop worker threads.
+    fn on_quit(&mut self) {}
 }
 
 // Vertex implementation for messing with strings
@@ -0,0 +1,127 @@
+// Runs files of GTP commands non-interactively, for instance
+// scripts reproducing a position.
+// Comments, empty lines and command ids follow the usual GTP rules.
+// The analysis of `lz-analyze` stops after `bothandler::ANALYSIS_LINES`
+// lines, nothing coming to interrupt it.
+// The launcher exposes it with `--batch FILE`, the commands being
+// recorded in its transcript, if any.
+
+use std::io;
+use std::io::{IoResult, File, BufferedReader, Buffer, Writer};
+use time;
+use api;
+use bothandler;
+use parsing;
+use transcript;
+
+/// Outcome of a script.
+#[deriving(PartialEq, Show)]
+pub struct BatchResult {
+    /// Number of commands run.
+    pub commands: uint,
+    /// Number of commands answered with `?`.
+    pub failures: uint,
+    /// True if the script ended early, on failure or `quit`.
+    pub interrupted: bool
+}
+
+impl BatchResult {
+    /// The exit status reflecting this result:
+    /// 0 if every command succeeded, 1 otherwise.
+    pub fn exit_status(&self) -> int {
+        if self.failures == 0 { 0 } else { 1 }
+    }
+}
+
+pub fn run_with_handler<T: api::GoBot, R: Buffer, W: Writer>(handler: &bothandler::BotHandler, bot: &mut T,
+                                                             script: &mut R, output: &mut W,
+                                                             transcript: &mut Option<transcript::Transcript>,
+                                                             stop_on_failure: bool) -> IoResult<BatchResult> {
+    let mut result = BatchResult{ commands: 0, failures: 0, interrupted: false };
+    loop {
+        let line = match parsing::read_line_lossy(script) {
+            Ok(l) => l,
+            Err(ref e) if e.kind == io::EndOfFile => break,
+            Err(e) => return Err(e)
+        };
+        if parsing::parse_command(line.as_slice()).is_none() {
+            // comment or empty line
+            continue;
+        }
+        let start = time::precise_time_ns();
+        let (continue_loop, response) = handler.handle_command(bot, line.as_slice());
+        match *transcript {
+            Some(ref mut t) => {
+                let _ = t.record(line.as_slice(), response.as_slice(), time::precise_time_ns() - start);
+            },
+            None => {}
+        }
+        try!(output.write_str(response.append("\n\n").as_slice()));
+        result.commands += 1;
+        let failed = response.as_slice().starts_with("?");
+        if failed {
+            result.failures += 1;
+        }
+        if !continue_loop || (failed && stop_on_failure) {
+            result.interrupted = true;
+            break;
+        }
+    }
+    try!(output.flush());
+    Ok(result)
+}
+
+/// Feeds the commands of a script to the bot, writing the responses to
+/// `output` as `main_loop` would. Stops at the first failure
+/// if `stop_on_failure` is true.
+pub fn run_script<T: api::GoBot, W: Writer>(bot: &mut T, path: &Path, output: &mut W,
+                                            stop_on_failure: bool) -> IoResult<BatchResult> {
+    let mut script = BufferedReader::new(try!(File::open(path)));
+    let handler = bothandler::BotHandler::from_bot(bot);
+    run_with_handler(&handler, bot, &mut script, output, &mut None, stop_on_failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, MemWriter};
+    use api;
+    use bothandler;
+
+    struct Stub;
+
+    impl api::GoBot for Stub {
+        fn gtp_name(&self) -> String { String::from_str("Stub") }
+        fn gtp_version(&self) -> String { String::from_str("1") }
+        fn gtp_clear_board(&mut self) {}
+        #[allow(unused_variable)]
+        fn gtp_komi(&mut self, komi: f32) {}
+        fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+            if size > 19 { Err(api::InvalidBoardSize) } else { Ok(()) }
+        }
+        #[allow(unused_variable)]
+        fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> { Ok(()) }
+        #[allow(unused_variable)]
+        fn gtp_genmove(&mut self, player: api::Colour) -> api::Move { api::Pass }
+    }
+
+    fn run(script: &str, stop_on_failure: bool) -> (super::BatchResult, String) {
+        let mut bot = Stub;
+        let handler = bothandler::BotHandler::from_bot(&mut bot);
+        let mut input = BufReader::new(script.as_bytes());
+        let mut output = MemWriter::new();
+        let result = super::run_with_handler(&handler, &mut bot, &mut input, &mut output, &mut None,
+                                             stop_on_failure).unwrap();
+        (result, String::from_utf8(output.unwrap()).unwrap())
+    }
+
+    #[test]
+    fn run_script() {
+        let script = "# setup\n1 boardsize 25\n\n2 boardsize 9\ngenmove b\n";
+        let (result, output) = run(script, false);
+        assert_eq!(result, super::BatchResult{ commands: 3, failures: 1, interrupted: false });
+        assert_eq!(output.as_slice(), "?1 invalid board size\n\n=2 \n\n= pass\n\n");
+        let (result, _) = run(script, true);
+        assert_eq!(result, super::BatchResult{ commands: 1, failures: 1, interrupted: true });
+        assert_eq!(result.exit_status(), 1);
+    }
+}
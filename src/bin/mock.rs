@@ -0,0 +1,69 @@
+//! A mock GTP engine answering from a script, to test controllers.
+//!
+//! Usage: gtp-mock [options] [SCRIPT]
+//!
+//! Without script, every command gets its default answer (see
+//! `gtprust::mock`). The exit status is 1 when the script makes the
+//! engine crash.
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::io::stdio;
+use std::os;
+use gtprust::mock;
+
+fn fail_with(message: &str) {
+    let _ = stdio::stderr().write_line(message);
+    os::set_exit_status(2);
+}
+
+fn main() {
+    let args = os::args();
+    let opts = [
+        getopts::optopt("c", "crash-after", "stop after answering N commands, overriding the script", "N"),
+        getopts::optflag("h", "help", "print this help")
+    ];
+    let brief = format!("Usage: {} [options] [SCRIPT]", args[0]);
+    let matches = match getopts::getopts(args.tail(), opts) {
+        Ok(m) => m,
+        Err(f) => {
+            fail_with(format!("{}\n{}", f, getopts::usage(brief.as_slice(), opts)).as_slice());
+            return;
+        }
+    };
+    if matches.opt_present("help") || matches.free.len() > 1 {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        return;
+    }
+    let mut script = match matches.free.as_slice().get(0) {
+        Some(path) => match mock::Script::load(&Path::new(path.as_slice())) {
+            Ok(s) => s,
+            Err(e) => {
+                fail_with(e.as_slice());
+                return;
+            }
+        },
+        None => mock::Script::new()
+    };
+    match matches.opt_str("crash-after") {
+        Some(text) => match from_str::<uint>(text.as_slice()) {
+            Some(n) => script.crash_after(n),
+            None => {
+                fail_with(format!("invalid value for --crash-after: {}", text).as_slice());
+                return;
+            }
+        },
+        None => {}
+    }
+
+    let engine = mock::MockEngine::new(script);
+    match engine.run(&mut stdio::stdin(), &mut stdio::stdout()) {
+        Ok(false) => {},
+        Ok(true) => os::set_exit_status(1),
+        Err(e) => {
+            let _ = stdio::stderr().write_line(format!("gtp-mock: {}", e).as_slice());
+            os::set_exit_status(1);
+        }
+    }
+}
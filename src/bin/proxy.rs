@@ -0,0 +1,134 @@
+//! Sits between a GUI and a GTP engine, logging and rewriting the traffic.
+//!
+//! Usage: gtp-proxy [options] --engine COMMAND
+//!
+//! The engine command is split on spaces (ex: "gnugo --mode gtp").
+//! Exchanges with the engine are logged with timestamps on stderr, or
+//! in the file given with `--log`; `--transcript` records the GUI side.
+//! Commands are translated between dialects when the engine only knows
+//! their equivalents (ex: `kgs-time_settings` and `time_settings`).
+//! Rules are given with `--rule`, or one per line in the file given with
+//! `--rules`, `#` starting comments (see `gtprust::proxy`):
+//!
+//! ```text
+//! rename kgs-genmove_cleanup genmove
+//! drop time_left
+//! after clear_board komi 6.5
+//! ```
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::io::{File, BufferedReader};
+use std::io::stdio;
+use std::os;
+use gtprust::client::Client;
+use gtprust::launcher;
+use gtprust::proxy;
+
+fn load_rules(path: &Path) -> Result<Vec<proxy::Rule>, String> {
+    let file = try!(File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e)));
+    let mut rules = Vec::new();
+    for line in BufferedReader::new(file).lines() {
+        let line = try!(line.map_err(|e| format!("cannot read {}: {}", path.display(), e)));
+        let content = line.as_slice().splitn('#', 1).next().unwrap().trim();
+        if !content.is_empty() {
+            rules.push(try!(proxy::Rule::parse(content)));
+        }
+    }
+    Ok(rules)
+}
+
+fn fail_with(message: &str) {
+    let _ = stdio::stderr().write_line(message);
+    os::set_exit_status(2);
+}
+
+fn main() {
+    let args = os::args();
+    let opts = [
+        getopts::reqopt("e", "engine", "command of the engine", "COMMAND"),
+        getopts::optmulti("R", "rule", "a rewriting rule, can be repeated", "RULE"),
+        getopts::optopt("r", "rules", "read rules from FILE", "FILE"),
+        getopts::optopt("L", "log", "log the exchanges with the engine in FILE instead of stderr", "FILE"),
+        getopts::optopt("A", "analysis-time", "run analysis commands for MS milliseconds (default 2000)", "MS"),
+        getopts::optopt("l", "transcript", "record a transcript of the session with the GUI in FILE", "FILE"),
+        getopts::optflag("h", "help", "print this help")
+    ];
+    let brief = format!("Usage: {} [options] --engine COMMAND", args[0]);
+    if args.iter().any(|a| a.as_slice() == "-h" || a.as_slice() == "--help") {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        return;
+    }
+    let matches = match getopts::getopts(args.tail(), opts) {
+        Ok(m) => m,
+        Err(f) => {
+            fail_with(format!("{}\n{}", f, getopts::usage(brief.as_slice(), opts)).as_slice());
+            return;
+        }
+    };
+    let mut rules = match matches.opt_str("rules") {
+        Some(path) => match load_rules(&Path::new(path)) {
+            Ok(r) => r,
+            Err(e) => {
+                fail_with(e.as_slice());
+                return;
+            }
+        },
+        None => Vec::new()
+    };
+    for text in matches.opt_strs("rule").iter() {
+        match proxy::Rule::parse(text.as_slice()) {
+            Ok(r) => rules.push(r),
+            Err(e) => {
+                fail_with(e.as_slice());
+                return;
+            }
+        }
+    }
+    let log: Box<Writer + 'static> = match matches.opt_str("log") {
+        Some(path) => match File::create(&Path::new(path.as_slice())) {
+            Ok(f) => box f,
+            Err(e) => {
+                fail_with(format!("cannot open {}: {}", path, e).as_slice());
+                return;
+            }
+        },
+        None => box stdio::stderr()
+    };
+
+    let command = matches.opt_str("engine").unwrap();
+    let words: Vec<String> = command.as_slice().words().map(|w| String::from_str(w)).collect();
+    if words.is_empty() {
+        fail_with("empty engine command");
+        return;
+    }
+    let client = match Client::spawn(words[0].as_slice(), words.tail()) {
+        Ok(c) => c,
+        Err(e) => {
+            fail_with(format!("cannot start {}: {}", command, e).as_slice());
+            return;
+        }
+    };
+    let mut bot = match proxy::Proxy::new(client, rules, Some(log)) {
+        Ok(p) => p,
+        Err(e) => {
+            fail_with(format!("cannot talk to {}: {}", command, e).as_slice());
+            return;
+        }
+    };
+    match matches.opt_str("analysis-time") {
+        Some(text) => match from_str::<u64>(text.as_slice()) {
+            Some(ms) => bot.set_analysis_time(ms),
+            None => {
+                fail_with(format!("invalid value for --analysis-time: {}", text).as_slice());
+                return;
+            }
+        },
+        None => {}
+    }
+    let mut options = launcher::Options::new();
+    options.transcript = matches.opt_str("transcript").map(|t| Path::new(t));
+    launcher::run(&mut bot, &options);
+    let _ = bot.unwrap().quit();
+}
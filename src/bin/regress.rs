@@ -0,0 +1,75 @@
+//! Runs GNU Go style regression suites against an external engine.
+//!
+//! Usage: gtp-regress [options] FILE.tst... -- ENGINE [ARGS...]
+//!
+//! The engine is started once per suite, in the directory of the suite,
+//! so that relative paths given to `loadsgf` work. The exit status is 1
+//! if any test failed unexpectedly.
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::os;
+use gtprust::regression;
+
+fn main() {
+    let args = os::args();
+    let opts = [
+        getopts::optflag("q", "quiet", "only print the summary of each suite"),
+        getopts::optflag("h", "help", "print this help")
+    ];
+    let brief = format!("Usage: {} [options] FILE.tst... -- ENGINE [ARGS...]", args[0]);
+    let separator = args.iter().position(|a| a.as_slice() == "--");
+    let (own_args, command) = match separator {
+        Some(i) => (args.slice(1, i), args.slice_from(i + 1)),
+        None => (args.slice_from(1), [].as_slice())
+    };
+    let matches = match getopts::getopts(own_args, opts) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}\n{}", f, getopts::usage(brief.as_slice(), opts));
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    if matches.opt_present("h") || command.is_empty() || matches.free.is_empty() {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        if !matches.opt_present("h") {
+            os::set_exit_status(2);
+        }
+        return;
+    }
+    let quiet = matches.opt_present("q");
+    let mut success = true;
+    for file in matches.free.iter() {
+        let path = Path::new(file.as_slice());
+        let cwd = path.dir_path();
+        let mut engine = match regression::ProcessEngine::spawn(command[0].as_slice(), command.tail(), &cwd) {
+            Ok(e) => e,
+            Err(e) => {
+                println!("cannot start {}: {}", command[0], e);
+                os::set_exit_status(2);
+                return;
+            }
+        };
+        match regression::run_file(&mut engine, &path) {
+            Ok(report) => {
+                if quiet {
+                    println!("{}: {} tests, {} unexpected failures, {} unexpected passes",
+                             report.name, report.results.len(),
+                             report.count(regression::Failure), report.count(regression::UnexpectedPass));
+                } else {
+                    print!("{}", report);
+                }
+                success = success && report.success();
+            },
+            Err(e) => {
+                println!("{}: {}", file, e);
+                success = false;
+            }
+        }
+    }
+    if !success {
+        os::set_exit_status(1);
+    }
+}
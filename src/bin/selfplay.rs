@@ -0,0 +1,116 @@
+//! Generates self-play games of a GTP engine, for training.
+//!
+//! Usage: gtp-selfplay [options] --engine COMMAND --output DIR
+//!
+//! The engine command is split on spaces (ex: "katago gtp -config
+//! selfplay.cfg"). Commands given with `--command` are sent before each
+//! game, to set the temperature or the noise of the engine (ex:
+//! `--command "kata-set-param chosenMoveTemperature 0.8"`). Moves are
+//! analysed with `lz-analyze` or `kata-analyze`, as the engine knows.
+//! Each game is written to `game-NNNNN.sgf`, with the statistics of its
+//! moves in `game-NNNNN.moves` (see `gtprust::selfplay`).
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::io::stdio;
+use std::os;
+use gtprust::client::Client;
+use gtprust::matchrunner;
+use gtprust::proxy;
+use gtprust::selfplay;
+
+fn fail_with(message: &str) {
+    let _ = stdio::stderr().write_line(message);
+    os::set_exit_status(2);
+}
+
+fn main() {
+    let args = os::args();
+    let opts = [
+        getopts::reqopt("e", "engine", "command of the engine", "COMMAND"),
+        getopts::reqopt("o", "output", "directory for the games", "DIR"),
+        getopts::optopt("n", "games", "number of games (default 1)", "N"),
+        getopts::optopt("s", "size", "board size (default 19)", "SIZE"),
+        getopts::optopt("k", "komi", "komi (default 7.5)", "KOMI"),
+        getopts::optopt("R", "random-moves", "open games with up to N random moves (default 4)", "N"),
+        getopts::optmulti("c", "command", "a command sent before each game, can be repeated", "COMMAND"),
+        getopts::optopt("A", "analysis-time", "analyse each move for MS milliseconds, 0 to disable (default 1000)",
+                        "MS"),
+        getopts::optopt("m", "max-moves", "games reaching this number of moves have no result (default 1000)", "N"),
+        getopts::optflag("h", "help", "print this help")
+    ];
+    let brief = format!("Usage: {} [options] --engine COMMAND --output DIR", args[0]);
+    if args.iter().any(|a| a.as_slice() == "-h" || a.as_slice() == "--help") {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        return;
+    }
+    let matches = match getopts::getopts(args.tail(), opts) {
+        Ok(m) => m,
+        Err(f) => {
+            fail_with(format!("{}\n{}", f, getopts::usage(brief.as_slice(), opts)).as_slice());
+            return;
+        }
+    };
+    let mut settings = selfplay::Settings::new();
+    let numbers = (matchrunner::number_opt(&matches, "games", settings.games),
+                   matchrunner::number_opt(&matches, "size", settings.boardsize),
+                   matchrunner::number_opt(&matches, "komi", settings.komi),
+                   matchrunner::number_opt(&matches, "random-moves", settings.random_moves),
+                   matchrunner::number_opt(&matches, "analysis-time", settings.analysis_time),
+                   matchrunner::number_opt(&matches, "max-moves", settings.max_moves));
+    match numbers {
+        (Ok(games), Ok(size), Ok(komi), Ok(random_moves), Ok(analysis_time), Ok(max_moves)) => {
+            settings.games = games;
+            settings.boardsize = size;
+            settings.komi = komi;
+            settings.random_moves = random_moves;
+            settings.analysis_time = analysis_time;
+            settings.max_moves = max_moves;
+        },
+        (Err(e), _, _, _, _, _) | (_, Err(e), _, _, _, _) | (_, _, Err(e), _, _, _) |
+        (_, _, _, Err(e), _, _) | (_, _, _, _, Err(e), _) | (_, _, _, _, _, Err(e)) => {
+            fail_with(e.as_slice());
+            return;
+        }
+    }
+    if settings.boardsize < 1 || settings.boardsize > 25 {
+        fail_with("the board size must be between 1 and 25");
+        return;
+    }
+    settings.commands = matches.opt_strs("command");
+    settings.output = matches.opt_str("output").map(|d| Path::new(d));
+
+    let command = matches.opt_str("engine").unwrap();
+    let words: Vec<String> = command.as_slice().words().map(|w| String::from_str(w)).collect();
+    if words.is_empty() {
+        fail_with("empty engine command");
+        return;
+    }
+    let client = match Client::spawn(words[0].as_slice(), words.tail()) {
+        Ok(c) => c,
+        Err(e) => {
+            fail_with(format!("cannot start {}: {}", command, e).as_slice());
+            return;
+        }
+    };
+    let mut bot = match proxy::Proxy::new(client, Vec::new(), None) {
+        Ok(p) => p,
+        Err(e) => {
+            fail_with(format!("cannot talk to {}: {}", command, e).as_slice());
+            return;
+        }
+    };
+    bot.set_analysis_time(settings.analysis_time);
+    let result = selfplay::run(&mut bot, &settings, |number, game| {
+        println!("game {:5u} {:8s} {:4u} moves", number, game.result.to_string(), game.record.moves.len());
+    });
+    match result {
+        Ok(()) => {},
+        Err(e) => {
+            let _ = stdio::stderr().write_line(format!("self-play stopped: {}", e).as_slice());
+            os::set_exit_status(1);
+        }
+    }
+    let _ = bot.unwrap().quit();
+}
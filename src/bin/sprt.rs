@@ -0,0 +1,134 @@
+//! Tests a candidate engine against a baseline with an SPRT.
+//!
+//! Usage: gtp-sprt [options] --candidate COMMAND --baseline COMMAND
+//!
+//! Engine commands are split on spaces (ex: "gnugo --mode gtp --level 3").
+//! The counts and the log-likelihood ratio are printed after each game.
+//! The exit status gives the verdict, for scripts:
+//!
+//! * 0: H1 accepted, the candidate passes
+//! * 1: H0 accepted, the candidate fails
+//! * 2: invalid options, or an engine failed to start
+//! * 3: the game limit was reached without a verdict
+//! * 4: the test was aborted by an engine failure
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::os;
+use gtprust::matchrunner;
+use gtprust::sprt;
+
+fn start_engine(id: uint, command: &str) -> Result<matchrunner::EnginePlayer, String> {
+    matchrunner::Player::spawn(id, command).map_err(|e| format!("cannot start {}: {}", command, e))
+}
+
+fn main() {
+    let args = os::args();
+    let mut opts = vec!(
+        getopts::reqopt("c", "candidate", "command of the engine under test", "COMMAND"),
+        getopts::reqopt("b", "baseline", "command of the reference engine", "COMMAND"),
+        getopts::optopt("", "elo0", "Elo difference of H0 (default 0)", "ELO"),
+        getopts::optopt("", "elo1", "Elo difference of H1 (default 5)", "ELO"),
+        getopts::optopt("", "alpha", "probability of accepting H1 when H0 holds (default 0.05)", "P"),
+        getopts::optopt("", "beta", "probability of accepting H0 when H1 holds (default 0.05)", "P"),
+        getopts::optopt("n", "max-games", "stop without a verdict after this number of games", "N"),
+        getopts::optopt("r", "referee", "command of an engine checking moves and scoring games", "COMMAND"),
+        getopts::optflag("h", "help", "print this help")
+    );
+    opts.push_all(matchrunner::option_groups().as_slice());
+    let opts = opts.as_slice();
+    let brief = format!("Usage: {} [options] --candidate COMMAND --baseline COMMAND", args[0]);
+    if args.iter().any(|a| a.as_slice() == "-h" || a.as_slice() == "--help") {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        return;
+    }
+    let matches = match getopts::getopts(args.tail(), opts) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}\n{}", f, getopts::usage(brief.as_slice(), opts));
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let mut test = sprt::Settings::new();
+    let numbers = (matchrunner::parse_settings(&matches),
+                   matchrunner::number_opt(&matches, "elo0", test.elo0),
+                   matchrunner::number_opt(&matches, "elo1", test.elo1),
+                   matchrunner::number_opt(&matches, "alpha", test.alpha),
+                   matchrunner::number_opt(&matches, "beta", test.beta),
+                   matchrunner::number_opt(&matches, "max-games", 0u));
+    let settings = match numbers {
+        (Ok(settings), Ok(elo0), Ok(elo1), Ok(alpha), Ok(beta), Ok(max_games)) => {
+            test.elo0 = elo0;
+            test.elo1 = elo1;
+            test.alpha = alpha;
+            test.beta = beta;
+            test.max_games = if max_games > 0 { Some(max_games) } else { None };
+            settings
+        },
+        (Err(e), _, _, _, _, _) | (_, Err(e), _, _, _, _) | (_, _, Err(e), _, _, _) |
+        (_, _, _, Err(e), _, _) | (_, _, _, _, Err(e), _) | (_, _, _, _, _, Err(e)) => {
+            println!("{}", e);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    if test.elo1 <= test.elo0 || !(test.alpha > 0.0 && test.alpha < 1.0) || !(test.beta > 0.0 && test.beta < 1.0) {
+        println!("elo1 must be greater than elo0, alpha and beta must be between 0 and 1");
+        os::set_exit_status(2);
+        return;
+    }
+
+    let candidate = matches.opt_str("c").unwrap();
+    let baseline = matches.opt_str("b").unwrap();
+    let (mut candidate, mut baseline) = match (start_engine(0, candidate.as_slice()),
+                                               start_engine(1, baseline.as_slice())) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let mut referee = match matches.opt_str("r") {
+        Some(command) => match start_engine(2, command.as_slice()) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                println!("{}", e);
+                os::set_exit_status(2);
+                return;
+            }
+        },
+        None => None
+    };
+    let (lower, upper) = test.bounds();
+    println!("{} against {}, H0: {} Elo, H1: {} Elo, LLR bounds [{:.2f}, {:.2f}]",
+             candidate.name, baseline.name, test.elo0, test.elo1, lower, upper);
+    let result = sprt::run(&mut candidate, &mut baseline, referee.as_mut(), &settings, &test, |number, game, status| {
+        println!("game {:4u} {:8s} W {} D {} L {} LLR {:.2f}", number, game.result.to_string(),
+                 status.wins, status.draws, status.losses, status.llr);
+    });
+    match result {
+        Ok((verdict, status)) => {
+            let (text, code) = match verdict {
+                sprt::AcceptH1 => ("H1 accepted, the candidate passes", 0),
+                sprt::AcceptH0 => ("H0 accepted, the candidate fails", 1),
+                sprt::Inconclusive => ("game limit reached, no verdict", 3)
+            };
+            println!("\n{} after {} games (W {} D {} L {}, LLR {:.2f})", text, status.games(),
+                     status.wins, status.draws, status.losses, status.llr);
+            os::set_exit_status(code);
+        },
+        Err(e) => {
+            println!("test aborted: {}", e);
+            os::set_exit_status(4);
+        }
+    }
+    let _ = candidate.client.quit();
+    let _ = baseline.client.quit();
+    match referee {
+        Some(ref mut r) => { let _ = r.client.quit(); },
+        None => {}
+    }
+}
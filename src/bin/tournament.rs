@@ -0,0 +1,108 @@
+//! Plays a tournament between GTP engines.
+//!
+//! Usage: gtp-tournament [options] --state FILE --engine NAME=COMMAND --engine NAME=COMMAND...
+//!
+//! Engine commands are split on spaces (ex: "gnu=gnugo --mode gtp --level 3").
+//! Games are printed as they finish, then the standings with Elo estimates.
+//! Running again with the same engines and state file resumes an
+//! interrupted tournament.
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::os;
+use gtprust::matchrunner;
+use gtprust::tournament;
+
+fn parse_entrant(text: &str) -> Option<tournament::Entrant> {
+    let parts: Vec<&str> = text.splitn('=', 1).collect();
+    match parts.as_slice() {
+        [name, command] if name.trim().len() > 0 && command.trim().len() > 0 => {
+            Some(tournament::Entrant{ name: String::from_str(name.trim()), command: String::from_str(command.trim()) })
+        },
+        _ => None
+    }
+}
+
+fn main() {
+    let args = os::args();
+    let mut opts = vec!(
+        getopts::optmulti("e", "engine", "an engine of the tournament, at least two are needed", "NAME=COMMAND"),
+        getopts::reqopt("S", "state", "file keeping the progress of the tournament", "FILE"),
+        getopts::optopt("F", "format", "round-robin (default) or swiss", "FORMAT"),
+        getopts::optopt("n", "rounds", "number of cycles of the round robin, or of Swiss rounds (default 1)", "N"),
+        getopts::optopt("j", "jobs", "number of games played at the same time (default 1)", "N"),
+        getopts::optopt("r", "referee", "command of an engine checking moves and scoring games", "COMMAND"),
+        getopts::optflag("h", "help", "print this help")
+    );
+    opts.push_all(matchrunner::option_groups().as_slice());
+    let opts = opts.as_slice();
+    let brief = format!("Usage: {} [options] --state FILE --engine NAME=COMMAND --engine NAME=COMMAND...", args[0]);
+    if args.iter().any(|a| a.as_slice() == "-h" || a.as_slice() == "--help") {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        return;
+    }
+    let matches = match getopts::getopts(args.tail(), opts) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}\n{}", f, getopts::usage(brief.as_slice(), opts));
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let mut entrants = Vec::new();
+    for text in matches.opt_strs("engine").iter() {
+        match parse_entrant(text.as_slice()) {
+            Some(e) => entrants.push(e),
+            None => {
+                println!("invalid value for --engine: {}", text);
+                os::set_exit_status(2);
+                return;
+            }
+        }
+    }
+    if entrants.len() < 2 {
+        println!("at least two engines are needed\n{}", getopts::usage(brief.as_slice(), opts));
+        os::set_exit_status(2);
+        return;
+    }
+    let numbers = (matchrunner::parse_settings(&matches),
+                   matchrunner::number_opt(&matches, "rounds", 1u),
+                   matchrunner::number_opt(&matches, "jobs", 1u));
+    let (settings, rounds, jobs) = match numbers {
+        (Ok(settings), Ok(rounds), Ok(jobs)) => (settings, rounds, jobs),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            println!("{}", e);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let format = match matches.opt_str("format") {
+        None => tournament::RoundRobin(rounds),
+        Some(ref f) if f.as_slice() == "round-robin" => tournament::RoundRobin(rounds),
+        Some(ref f) if f.as_slice() == "swiss" => tournament::Swiss(rounds),
+        Some(f) => {
+            println!("invalid value for --format: {}", f);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let t = tournament::Tournament{ entrants: entrants, format: format, settings: settings,
+                                    referee: matches.opt_str("referee"), concurrency: jobs,
+                                    state: Path::new(matches.opt_str("state").unwrap()) };
+    let result = t.run(|record| {
+        println!("game {:4u} round {:2u}: {} (B) - {} (W): {}", record.pairing.number, record.pairing.round,
+                 t.entrants[record.pairing.black].name, t.entrants[record.pairing.white].name, record.result);
+    });
+    match result {
+        Ok(records) => {
+            let standings = tournament::Standings{ entrants: t.entrants.as_slice(),
+                                                   ratings: tournament::ratings(t.entrants.len(), records.as_slice()) };
+            println!("\n{}", standings);
+        },
+        Err(e) => {
+            println!("tournament aborted: {}", e);
+            os::set_exit_status(1);
+        }
+    }
+}
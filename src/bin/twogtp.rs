@@ -0,0 +1,110 @@
+//! Plays a match between two GTP engines.
+//!
+//! Usage: gtp-twogtp [options] --black COMMAND --white COMMAND
+//!
+//! Engine commands are split on spaces (ex: "gnugo --mode gtp --level 3").
+//! Engines swap colours after each game unless `--fixed-colours` is given.
+//! With `--referee`, a third engine checks the moves and scores the games.
+//! With `--adjudicate`, games whose result is clear end early, the lead
+//! being estimated as given by `--estimate` (ex: `--adjudicate 10:25:3
+//! --estimate analysis:500`).
+//! The results table is printed as games finish, and written with the
+//! games in the output directory, if any.
+
+extern crate getopts;
+extern crate gtprust;
+
+use std::os;
+use gtprust::matchrunner;
+
+fn start_engine(id: uint, command: &str) -> Result<matchrunner::EnginePlayer, String> {
+    matchrunner::Player::spawn(id, command).map_err(|e| format!("cannot start {}: {}", command, e))
+}
+
+fn main() {
+    let args = os::args();
+    let mut opts = vec!(
+        getopts::reqopt("b", "black", "command of the engine taking black first", "COMMAND"),
+        getopts::reqopt("w", "white", "command of the engine taking white first", "COMMAND"),
+        getopts::optopt("n", "games", "number of games (default 1)", "N"),
+        getopts::optopt("r", "referee", "command of an engine checking moves and scoring games", "COMMAND"),
+        getopts::optflag("f", "fixed-colours", "do not swap colours between games"),
+        getopts::optflag("h", "help", "print this help")
+    );
+    opts.push_all(matchrunner::option_groups().as_slice());
+    let opts = opts.as_slice();
+    let brief = format!("Usage: {} [options] --black COMMAND --white COMMAND", args[0]);
+    if args.iter().any(|a| a.as_slice() == "-h" || a.as_slice() == "--help") {
+        println!("{}", getopts::usage(brief.as_slice(), opts));
+        return;
+    }
+    let matches = match getopts::getopts(args.tail(), opts) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}\n{}", f, getopts::usage(brief.as_slice(), opts));
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let mut settings = match matchrunner::parse_settings(&matches) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("{}", e);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    settings.games = match matchrunner::number_opt(&matches, "games", settings.games) {
+        Ok(n) => n,
+        Err(e) => {
+            println!("{}", e);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    settings.alternate = !matches.opt_present("f");
+
+    let black = matches.opt_str("b").unwrap();
+    let white = matches.opt_str("w").unwrap();
+    let (mut first, mut second) = match (start_engine(0, black.as_slice()), start_engine(1, white.as_slice())) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("{}", e);
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let mut referee = match matches.opt_str("r") {
+        Some(command) => match start_engine(2, command.as_slice()) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                println!("{}", e);
+                os::set_exit_status(2);
+                return;
+            }
+        },
+        None => None
+    };
+    println!("{}", matchrunner::table_header());
+    let result = matchrunner::run_match(&mut first, &mut second, referee.as_mut(), &settings, |number, game| {
+        println!("{}", matchrunner::format_row(number, game));
+    });
+    match result {
+        Ok(games) => {
+            let games = games.as_slice();
+            let void = games.len() - matchrunner::wins(games, 0) - matchrunner::wins(games, 1);
+            println!("\n{}: {} wins\n{}: {} wins\nvoid or drawn: {}", first.name, matchrunner::wins(games, 0),
+                     second.name, matchrunner::wins(games, 1), void);
+        },
+        Err(e) => {
+            println!("match aborted: {}", e);
+            os::set_exit_status(1);
+        }
+    }
+    let _ = first.client.quit();
+    let _ = second.client.quit();
+    match referee {
+        Some(ref mut r) => { let _ = r.client.quit(); },
+        None => {}
+    }
+}
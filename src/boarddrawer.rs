@@ -29,7 +29,8 @@ pub fn draw_board(boardsize: uint,
         }
         output = output.append("\n");
     }
-    output = output.append("\n  ");
+    // no empty line, it would end the response
+    output = output.append("  ");
     for l in range(0, boardsize) {
         output.grow(1, ' ');
         output.grow(1, if l < 8 {
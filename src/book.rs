@@ -0,0 +1,546 @@
+//! An opening book, answering `genmove` with the moves of recorded games.
+//!
+//! The book is built from SGF files: for each game line up to `depth`
+//! moves, it counts the games continuing with each move and the games
+//! won by the player of that move. Lines are normalised over the eight
+//! symmetries of the board, so a game starting on the 4-4 point of one
+//! corner also answers for the three others, and the first moves in the
+//! four corners share their statistics. Transpositions are not
+//! merged: a position is reached by the moves played from the start.
+//!
+//! `OpeningBook` wraps a bot: `genmove` is answered from the book when
+//! the game is in it, the move being given to the wrapped bot with
+//! `gtp_play`; otherwise it is asked to the wrapped bot.
+//!
+//! ```ignore
+//! let mut book = Book::new(20);
+//! try!(book.load(&Path::new("openings/")));
+//! let mut bot = OpeningBook::new(MyBot::new(), book, Frequency);
+//! gtprust::launcher::launch(&mut bot);
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::io::fs;
+use std::io::File;
+use std::rand::{task_rng, Rng};
+use api;
+use diagnostics;
+use sgf;
+
+/// How a move is chosen among the book moves.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Weighting {
+    /// Always the move of the most games.
+    MostPlayed,
+    /// A random move, in proportion to its number of games.
+    Frequency,
+    /// A random move, in proportion to the number of games it won
+    /// plus one.
+    Wins,
+    /// A random move, all moves being equally likely.
+    Uniform
+}
+
+/// A move of the book, with its statistics.
+#[deriving(PartialEq, Show)]
+pub struct BookMove {
+    pub move: api::Move,
+    pub games: uint,
+    /// Games won by the player of the move.
+    pub wins: uint
+}
+
+// a move of the book in normalised coordinates
+struct Entry {
+    move: api::ColouredMove,
+    games: uint,
+    wins: uint
+}
+
+// the symmetries of the board: bit 0 mirrors the columns, bit 1 the
+// rows, bit 2 swaps columns and rows before
+fn transform(vertex: api::Vertex, size: uint, symmetry: uint) -> api::Vertex {
+    let (mut x, mut y) = vertex.to_coords();
+    if symmetry & 4 != 0 {
+        let column = x;
+        x = y;
+        y = column;
+    }
+    if symmetry & 1 != 0 {
+        x = size as u8 + 1 - x;
+    }
+    if symmetry & 2 != 0 {
+        y = size as u8 + 1 - y;
+    }
+    api::Vertex::from_coords(x, y).unwrap()
+}
+
+fn inverse(vertex: api::Vertex, size: uint, symmetry: uint) -> api::Vertex {
+    let (mut x, mut y) = vertex.to_coords();
+    if symmetry & 1 != 0 {
+        x = size as u8 + 1 - x;
+    }
+    if symmetry & 2 != 0 {
+        y = size as u8 + 1 - y;
+    }
+    if symmetry & 4 != 0 {
+        api::Vertex::from_coords(y, x).unwrap()
+    } else {
+        api::Vertex::from_coords(x, y).unwrap()
+    }
+}
+
+fn transform_move(move: api::Move, size: uint, symmetry: uint) -> api::Move {
+    match move {
+        api::Stone(v) => api::Stone(transform(v, size, symmetry)),
+        other => other
+    }
+}
+
+fn inverse_move(move: api::Move, size: uint, symmetry: uint) -> api::Move {
+    match move {
+        api::Stone(v) => api::Stone(inverse(v, size, symmetry)),
+        other => other
+    }
+}
+
+// the normalised key of a game line and the symmetries giving it,
+// several ones when the position is itself symmetric
+fn normalise(size: uint, handicap: &[api::Vertex], moves: &[api::ColouredMove]) -> (String, Vec<uint>) {
+    let mut best: Option<String> = None;
+    let mut symmetries = Vec::new();
+    for symmetry in range(0u, 8) {
+        let mut stones: Vec<String> = handicap.iter().map(|v| transform(*v, size, symmetry).to_string()).collect();
+        stones.sort();
+        let line: Vec<String> = moves.iter().map(|m| {
+            let colour = match m.player { api::Black => "B", api::White => "W" };
+            format!("{}{}", colour, transform_move(m.move, size, symmetry).to_string())
+        }).collect();
+        let key = format!("{}/{}/{}", size, stones.connect(","), line.connect(" "));
+        let (better, same) = match best {
+            Some(ref k) => (key < *k, key == *k),
+            None => (true, false)
+        };
+        if better {
+            best = Some(key);
+            symmetries = vec!(symmetry);
+        } else if same {
+            symmetries.push(symmetry);
+        }
+    }
+    (best.unwrap(), symmetries)
+}
+
+// the normalised form of a move played in a position normalised by
+// `symmetries`: the smallest of its images, so that the symmetric moves
+// of a symmetric position share their entry
+fn normalise_move(move: api::Move, size: uint, symmetries: &[uint]) -> api::Move {
+    let mut best = transform_move(move, size, symmetries[0]);
+    for symmetry in symmetries.iter().skip(1) {
+        let image = transform_move(move, size, *symmetry);
+        if image.to_string() < best.to_string() {
+            best = image;
+        }
+    }
+    best
+}
+
+// the winner of a game, from its result
+fn winner(game: &sgf::SgfGame) -> Option<api::Colour> {
+    match game.result {
+        Some(ref result) if result.as_slice().starts_with("B+") => Some(api::Black),
+        Some(ref result) if result.as_slice().starts_with("W+") => Some(api::White),
+        _ => None
+    }
+}
+
+/// The book: the moves played from the lines of recorded games.
+pub struct Book {
+    entries: HashMap<String, Vec<Entry>>,
+    depth: uint,
+    games: uint
+}
+
+impl Book {
+    /// An empty book, recording the first `depth` moves of the games.
+    pub fn new(depth: uint) -> Book {
+        Book{ entries: HashMap::new(), depth: depth, games: 0 }
+    }
+
+    /// The number of games added.
+    pub fn games(&self) -> uint {
+        self.games
+    }
+
+    /// Adds the first moves of a game, up to the first resignation.
+    pub fn add_game(&mut self, game: &sgf::SgfGame) {
+        let winner = winner(game);
+        let moves: Vec<api::ColouredMove> = game.moves.iter().map(|m| m.move).collect();
+        for i in range(0, moves.len()) {
+            if i >= self.depth || moves[i].move == api::Resign {
+                break;
+            }
+            let (key, symmetries) = normalise(game.size, game.handicap.as_slice(), moves.slice_to(i));
+            let move = api::ColouredMove{ player: moves[i].player,
+                                          move: normalise_move(moves[i].move, game.size, symmetries.as_slice()) };
+            let won = if winner == Some(move.player) { 1 } else { 0 };
+            let entries = self.entries.find_or_insert_with(key, |_| Vec::new());
+            match entries.iter().position(|e| e.move == move) {
+                Some(j) => {
+                    let entry = entries.get_mut(j);
+                    entry.games += 1;
+                    entry.wins += won;
+                },
+                None => entries.push(Entry{ move: move, games: 1, wins: won })
+            }
+        }
+        self.games += 1;
+    }
+
+    /// Adds the games of an SGF file, or of the `.sgf` files of a
+    /// directory. Returns the number of games added.
+    pub fn load(&mut self, path: &Path) -> Result<uint, String> {
+        let stat = try!(fs::stat(path).map_err(|e| format!("cannot open {}: {}", path.display(), e)));
+        if stat.kind == io::TypeDirectory {
+            let mut files = try!(fs::readdir(path).map_err(|e| format!("cannot read {}: {}", path.display(), e)));
+            files.sort_by(|a, b| a.as_vec().cmp(&b.as_vec()));
+            let mut count = 0;
+            for file in files.iter().filter(|f| f.extension_str() == Some("sgf")) {
+                count += try!(self.load(file));
+            }
+            return Ok(count);
+        }
+        let text = try!(File::open(path).read_to_string()
+                        .map_err(|e| format!("cannot read {}: {}", path.display(), e)));
+        let games = try!(sgf::read_collection(text.as_slice())
+                         .map_err(|e| format!("{}: {}", path.display(), e)));
+        for game in games.iter() {
+            self.add_game(game);
+        }
+        Ok(games.len())
+    }
+
+    /// The book moves of `player` after `moves`, in the coordinates of
+    /// the game. In a symmetric position, symmetric moves are given once.
+    pub fn lookup(&self, size: uint, handicap: &[api::Vertex], moves: &[api::ColouredMove],
+                  player: api::Colour) -> Vec<BookMove> {
+        let (key, symmetries) = normalise(size, handicap, moves);
+        // any of the symmetries brings the normalised moves back
+        let symmetry = symmetries[0];
+        match self.entries.find(&key) {
+            Some(entries) => entries.iter().filter(|e| e.move.player == player).map(|e| {
+                BookMove{ move: inverse_move(e.move.move, size, symmetry), games: e.games, wins: e.wins }
+            }).collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+fn weight(move: &BookMove, weighting: Weighting) -> uint {
+    match weighting {
+        MostPlayed | Frequency => move.games,
+        Wins => move.wins + 1,
+        Uniform => 1
+    }
+}
+
+/// Chooses a move among `moves` according to `weighting`.
+pub fn choose<R: Rng>(moves: &[BookMove], weighting: Weighting, rng: &mut R) -> Option<api::Move> {
+    if moves.is_empty() {
+        return None;
+    }
+    if weighting == MostPlayed {
+        let mut best = &moves[0];
+        for m in moves.iter() {
+            if m.games > best.games {
+                best = m;
+            }
+        }
+        return Some(best.move);
+    }
+    let total = moves.iter().fold(0, |sum, m| sum + weight(m, weighting));
+    let mut target = rng.gen_range(0, total);
+    for m in moves.iter() {
+        let w = weight(m, weighting);
+        if target < w {
+            return Some(m.move);
+        }
+        target -= w;
+    }
+    None
+}
+
+/// A bot playing from an opening book, see the module documentation.
+pub struct OpeningBook<B> {
+    bot: B,
+    book: Book,
+    /// How book moves are chosen.
+    pub weighting: Weighting,
+    size: uint,
+    handicap: Vec<api::Vertex>,
+    moves: Vec<api::ColouredMove>
+}
+
+impl<B: api::GoBot> OpeningBook<B> {
+    pub fn new(bot: B, book: Book, weighting: Weighting) -> OpeningBook<B> {
+        OpeningBook{ bot: bot, book: book, weighting: weighting, size: 19, handicap: Vec::new(), moves: Vec::new() }
+    }
+
+    /// Gives back the wrapped bot.
+    pub fn unwrap(self) -> B {
+        self.bot
+    }
+
+    fn book_moves(&self, player: api::Colour) -> Vec<BookMove> {
+        self.book.lookup(self.size, self.handicap.as_slice(), self.moves.as_slice(), player)
+    }
+
+    fn set_handicap(&mut self, stones: &[api::Vertex]) {
+        self.handicap = stones.iter().map(|v| *v).collect();
+    }
+}
+
+impl<B: api::GoBot> api::GoBot for OpeningBook<B> {
+    fn gtp_name(&self) -> String {
+        self.bot.gtp_name()
+    }
+
+    fn gtp_version(&self) -> String {
+        self.bot.gtp_version()
+    }
+
+    fn gtp_clear_board(&mut self) {
+        self.bot.gtp_clear_board();
+        self.handicap.clear();
+        self.moves.clear();
+    }
+
+    fn gtp_komi(&mut self, komi: f32) {
+        self.bot.gtp_komi(komi)
+    }
+
+    fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+        try!(self.bot.gtp_boardsize(size));
+        self.size = size;
+        self.handicap.clear();
+        self.moves.clear();
+        Ok(())
+    }
+
+    fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+        try!(self.bot.gtp_play(move));
+        self.moves.push(move);
+        Ok(())
+    }
+
+    fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
+        let candidates = self.book_moves(player);
+        match choose(candidates.as_slice(), self.weighting, &mut task_rng()) {
+            Some(move) => match self.bot.gtp_play(api::ColouredMove{ player: player, move: move }) {
+                Ok(()) => {
+                    self.moves.push(api::ColouredMove{ player: player, move: move });
+                    return move;
+                },
+                Err(_) => diagnostics::warning(format!("book move {} refused by the bot",
+                                                       move.to_string()).as_slice())
+            },
+            None => {}
+        }
+        let move = self.bot.gtp_genmove(player);
+        if move != api::Resign {
+            self.moves.push(api::ColouredMove{ player: player, move: move });
+        }
+        move
+    }
+
+    // the most played book move, if the wrapped bot knows the command
+    fn gtp_genmove_regression(&self, player: api::Colour) -> Result<api::Move, api::GTPError> {
+        let move = try!(self.bot.gtp_genmove_regression(player));
+        let candidates = self.book_moves(player);
+        Ok(choose(candidates.as_slice(), MostPlayed, &mut task_rng()).unwrap_or(move))
+    }
+
+    fn gtp_undo(&mut self) -> Result<(), api::GTPError> {
+        try!(self.bot.gtp_undo());
+        self.moves.pop();
+        Ok(())
+    }
+
+    fn gtp_fixed_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        let stones = try!(self.bot.gtp_fixed_handicap(number));
+        self.set_handicap(stones.as_slice());
+        Ok(stones)
+    }
+
+    fn gtp_place_free_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        let stones = try!(self.bot.gtp_place_free_handicap(number));
+        self.set_handicap(stones.as_slice());
+        Ok(stones)
+    }
+
+    fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+        try!(self.bot.gtp_set_free_handicap(stones));
+        self.set_handicap(stones);
+        Ok(())
+    }
+
+    fn gtp_time_settings(&mut self, main_time: uint, byoyomi_time: uint, byoyomi_stones: uint)
+                         -> Result<(), api::GTPError> {
+        self.bot.gtp_time_settings(main_time, byoyomi_time, byoyomi_stones)
+    }
+
+    fn gtp_time_left(&mut self, player: api::Colour, time: uint, stones: uint) -> Result<(), api::GTPError> {
+        self.bot.gtp_time_left(player, time, stones)
+    }
+
+    fn gtp_final_status_list(&self, status: api::StoneStatus) -> Result<Vec<api::Vertex>, api::GTPError> {
+        self.bot.gtp_final_status_list(status)
+    }
+
+    fn gtp_final_score(&self) -> Result<(f32, api::Colour), api::GTPError> {
+        self.bot.gtp_final_score()
+    }
+
+    fn gtp_showboard(&self) -> Result<(uint, Vec<api::Vertex>, Vec<api::Vertex>, uint, uint), api::GTPError> {
+        self.bot.gtp_showboard()
+    }
+
+    fn gtp_analyze(&mut self, player: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+        self.bot.gtp_analyze(player, report)
+    }
+
+    fn can_analyze(&self) -> bool {
+        self.bot.can_analyze()
+    }
+
+    fn gtp_custom_command(&mut self, command: &str, args: &str) -> (bool, String) {
+        self.bot.gtp_custom_command(command, args)
+    }
+
+    fn gtp_known_custom_command(&self, command: &str) -> bool {
+        self.bot.gtp_known_custom_command(command)
+    }
+
+    fn gtp_list_custom_commands(&self) -> Vec<String> {
+        self.bot.gtp_list_custom_commands()
+    }
+
+    fn on_start(&mut self) {
+        self.bot.on_start()
+    }
+
+    fn on_new_game(&mut self) {
+        self.bot.on_new_game()
+    }
+
+    fn on_game_over(&mut self) {
+        self.bot.on_game_over()
+    }
+
+    fn on_quit(&mut self) {
+        self.bot.on_quit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rand::{SeedableRng, XorShiftRng};
+    use api;
+    use api::GoBot;
+    use sgf;
+
+    fn stone(text: &str) -> api::Move {
+        api::Stone(api::Vertex::from_str(text).unwrap())
+    }
+
+    fn game(moves: &[&str], result: &str) -> sgf::SgfGame {
+        let mut game = sgf::SgfGame::new(9, 7.0);
+        game.result = Some(String::from_str(result));
+        for (i, m) in moves.iter().enumerate() {
+            let player = if i % 2 == 0 { api::Black } else { api::White };
+            game.push(api::ColouredMove{ player: player, move: stone(*m) }, None);
+        }
+        game
+    }
+
+    #[test]
+    fn symmetries() {
+        let v = api::Vertex::from_str("C7").unwrap();
+        for symmetry in range(0u, 8) {
+            assert_eq!(super::inverse(super::transform(v, 9, symmetry), 9, symmetry), v);
+        }
+        let (key, _) = super::normalise(9, [], [api::ColouredMove{ player: api::Black, move: stone("C3") }]);
+        for corner in ["C7", "G7", "G3"].iter() {
+            let (other, _) = super::normalise(9, [], [api::ColouredMove{ player: api::Black, move: stone(*corner) }]);
+            assert_eq!(other, key);
+        }
+        // the empty board is symmetric
+        let (_, symmetries) = super::normalise(9, [], []);
+        assert_eq!(symmetries.len(), 8);
+        assert_eq!(super::normalise_move(stone("G7"), 9, symmetries.as_slice()), stone("C3"));
+    }
+
+    #[test]
+    fn lookup() {
+        let mut book = super::Book::new(2);
+        book.add_game(&game(["C3", "G7", "E5"], "B+R"));
+        book.add_game(&game(["G3", "C7"], "W+2.5"));
+        let first = book.lookup(9, [], [], api::Black);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].games, 2);
+        assert_eq!(first[0].wins, 1);
+        // the second move after a stone in another corner
+        let replies = book.lookup(9, [], [api::ColouredMove{ player: api::Black, move: stone("G7") }], api::White);
+        assert_eq!(replies, vec!(super::BookMove{ move: stone("C3"), games: 2, wins: 1 }));
+        assert!(book.lookup(9, [], [api::ColouredMove{ player: api::Black, move: stone("G7") }], api::Black)
+                .is_empty());
+        // beyond the depth
+        let line = [api::ColouredMove{ player: api::Black, move: stone("C3") },
+                    api::ColouredMove{ player: api::White, move: stone("G7") }];
+        assert!(book.lookup(9, [], line, api::Black).is_empty());
+    }
+
+    #[test]
+    fn choose() {
+        let moves = [super::BookMove{ move: stone("C3"), games: 1, wins: 1 },
+                     super::BookMove{ move: stone("E5"), games: 5, wins: 0 }];
+        let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+        assert_eq!(super::choose(moves, super::MostPlayed, &mut rng), Some(stone("E5")));
+        assert_eq!(super::choose([], super::Uniform, &mut rng), None);
+        for _ in range(0u, 20) {
+            assert!(super::choose(moves, super::Wins, &mut rng).is_some());
+        }
+    }
+
+    struct Passer {
+        played: Vec<api::ColouredMove>
+    }
+
+    impl api::GoBot for Passer {
+        fn gtp_name(&self) -> String { String::from_str("Passer") }
+        fn gtp_version(&self) -> String { String::from_str("1.0") }
+        fn gtp_clear_board(&mut self) { self.played.clear(); }
+        fn gtp_komi(&mut self, _: f32) {}
+        fn gtp_boardsize(&mut self, _: uint) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+            self.played.push(move);
+            Ok(())
+        }
+        fn gtp_genmove(&mut self, _: api::Colour) -> api::Move { api::Pass }
+    }
+
+    #[test]
+    fn opening_book() {
+        let mut book = super::Book::new(10);
+        book.add_game(&game(["E5"], "B+R"));
+        let mut bot = super::OpeningBook::new(Passer{ played: Vec::new() }, book, super::Frequency);
+        bot.gtp_boardsize(9).unwrap();
+        bot.gtp_clear_board();
+        assert_eq!(bot.gtp_genmove(api::Black), stone("E5"));
+        assert_eq!(bot.gtp_genmove(api::White), api::Pass);
+        assert!(match bot.gtp_genmove_regression(api::Black) { Err(api::NotImplemented) => true, _ => false });
+        let passer = bot.unwrap();
+        assert_eq!(passer.played, vec!(api::ColouredMove{ player: api::Black, move: stone("E5") }));
+    }
+}
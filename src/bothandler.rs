@@ -1,8 +1,11 @@
-use std::ascii::Ascii;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::string::String;
 use api;
+use diagnostics;
 use parsing;
 use boarddrawer;
+use responses;
 
 // This struct is used to keep a record of which
 // optional commands have been implemented by
@@ -20,6 +23,10 @@ komi
 play
 genmove";
 
+// the number of analysis lines kept by handle_command
+// before the analysis is stopped
+pub static ANALYSIS_LINES: uint = 10;
+
 pub struct BotHandler {
     genmove_regression: bool,
     undo: bool,
@@ -27,9 +34,20 @@ pub struct BotHandler {
     place_free_handicap: bool,
     set_free_handicap: bool,
     time_settings: bool,
+    time_left: bool,
     final_status_list: bool,
     final_score: bool,
-    showboard: bool
+    showboard: bool,
+    analyze: bool,
+    // game state, to call the lifecycle hooks
+    last_was_pass: Cell<bool>,
+    game_over: Cell<bool>,
+    // the colour to ponder for, set by genmove until the next command
+    ponder_colour: Cell<Option<api::Colour>>,
+    // session settings, see launcher
+    aliases: HashMap<String, String>,
+    name: Option<String>,
+    ponder: bool
 }
 
 impl BotHandler {
@@ -41,9 +59,17 @@ impl BotHandler {
             place_free_handicap: false,
             set_free_handicap: false,
             time_settings: false,
+            time_left: false,
             final_status_list: false,
             final_score: false,
-            showboard: false
+            showboard: false,
+            analyze: false,
+            last_was_pass: Cell::new(false),
+            game_over: Cell::new(false),
+            ponder_colour: Cell::new(None),
+            aliases: HashMap::new(),
+            name: None,
+            ponder: false
         }
     }
 
@@ -72,6 +98,10 @@ impl BotHandler {
             Err(api::NotImplemented) => self.time_settings = false,
             _ => self.time_settings = true
         }
+        match bot.gtp_time_left(api::Black, 0, 0) {
+            Err(api::NotImplemented) => self.time_left = false,
+            _ => self.time_left = true
+        }
         match bot.gtp_final_status_list(api::Alive) {
             Err(api::NotImplemented) => self.final_status_list = false,
             _ => self.final_status_list = true
@@ -84,10 +114,31 @@ impl BotHandler {
             Err(api::NotImplemented) => self.showboard = false,
             _ => self.showboard = true
         }
+        self.analyze = bot.can_analyze();
         // lets reset the bot
         bot.gtp_clear_board();
     }
 
+    // keeps track of the end of the game: two passes in a row or a
+    // resignation, scoring requests may come in the middle of a game
+    fn record_move<T: api::GoBot>(&self, bot: &mut T, mv: api::Move) {
+        let over = match mv {
+            api::Resign => true,
+            api::Pass => self.last_was_pass.get(),
+            api::Stone(_) => {
+                // the game went on after its end, ex: after an undo
+                self.game_over.set(false);
+                false
+            }
+        };
+        self.last_was_pass.set(mv == api::Pass);
+        // on_game_over is called only once per end of game
+        if over && !self.game_over.get() {
+            self.game_over.set(true);
+            bot.on_game_over();
+        }
+    }
+
     // implementations of GTP commands
 
     fn cmd_list_commands<T: api::GoBot>(&self, bot: &T) -> String {
@@ -111,6 +162,9 @@ impl BotHandler {
         if self.time_settings {
             list = list.append("\ntime_settings");
         }
+        if self.time_left {
+            list = list.append("\ntime_left");
+        }
         if self.final_status_list {
             list = list.append("\nfinal_status_list");
         }
@@ -120,14 +174,23 @@ impl BotHandler {
         if self.showboard {
             list = list.append("\nshowboard");
         }
+        if self.analyze {
+            list = list.append("\nlz-analyze");
+        }
         for cmd in bot.gtp_list_custom_commands().iter() {
             list = list.append("\n").append(cmd.as_slice());
         }
+        // sorted, the list must not change from one run to the next
+        let mut aliases: Vec<&String> = self.aliases.keys().collect();
+        aliases.sort();
+        for alias in aliases.iter() {
+            list = list.append("\n").append(alias.as_slice());
+        }
         list
     }
 
-    fn cmd_known_command<T: api::GoBot>(&self, bot: &T, cmd: &[Ascii]) -> String {
-        format!("{:b}", match cmd.as_str_ascii() {
+    fn cmd_known_command<T: api::GoBot>(&self, bot: &T, cmd: &str) -> String {
+        format!("{:b}", match cmd {
             "protocol_version" | "name" | "version" |
             "known_command" | "list_commands" | "quit" |
             "boardsize" | "clear_board" | "komi" |
@@ -137,17 +200,21 @@ impl BotHandler {
             "reg_genmove" => self.genmove_regression,
             "undo" => self.undo,
             "place_free_handicap" => self.place_free_handicap,
-            "set_free_handiciap" | "fixed_handicap" => self.set_free_handicap,
+            "fixed_handicap" => self.fixed_handicap,
+            "set_free_handicap" => self.set_free_handicap,
             "time_settings" => self.time_settings,
+            "time_left" => self.time_left,
             "final_status_list" => self.final_status_list,
             "final_score" => self.final_score,
             "showboard" => self.showboard,
-            _ => bot.gtp_known_custom_command(cmd.as_str_ascii())
+            "lz-analyze" => self.analyze,
+            _ => self.aliases.find_equiv(&cmd).is_some()
+                 || bot.gtp_known_custom_command(cmd)
         })
     }
 
-    fn cmd_boardsize<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
-        match from_str::<uint>(args.as_str_ascii()) {
+    fn cmd_boardsize<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
+        match from_str::<uint>(args) {
             Some(n) => match bot.gtp_boardsize(n) {
                 Ok(()) => (true, String::new()),
                 Err(api::InvalidBoardSize) => (false, String::from_str("invalid board size")),
@@ -159,20 +226,26 @@ impl BotHandler {
 
     fn cmd_clear_board<T: api::GoBot>(&self, bot: &mut T) -> () {
         bot.gtp_clear_board();
+        self.last_was_pass.set(false);
+        self.game_over.set(false);
+        bot.on_new_game();
     }
 
-    fn cmd_komi<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
-        match from_str::<f32>(args.as_str_ascii()) {
+    fn cmd_komi<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
+        match from_str::<f32>(args) {
             Some(k) => {bot.gtp_komi(k); (true, String::new())},
             None => (false, String::from_str("syntax error"))
         }
     }
 
-    fn cmd_play<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
+    fn cmd_play<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
         match parsing::parse_args(args, [parsing::ColouredMoveArg]) {
             Some(vect) => match vect[0] {
                 parsing::ArgColouredMove(mv) => match bot.gtp_play(mv) {
-                    Ok(()) => (true, String::new()),
+                    Ok(()) => {
+                        self.record_move(bot, mv.move);
+                        (true, String::new())
+                    },
                     Err(api::InvalidMove) => (false, String::from_str("invalid move")),
                     _ => fail!("Unexpected error in gtp_play.")
                 },
@@ -182,9 +255,16 @@ impl BotHandler {
         }
     }
 
-    fn cmd_genmove<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
+    fn cmd_genmove<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
         match parsing::arg_parse_colour(args) {
-            Some(col) => (true, bot.gtp_genmove(col).to_string()),
+            Some(col) => {
+                let mv = bot.gtp_genmove(col);
+                self.record_move(bot, mv);
+                if mv != api::Resign {
+                    self.ponder_colour.set(Some(match col { api::Black => api::White, api::White => api::Black }));
+                }
+                (true, mv.to_string())
+            },
             None => (false, String::from_str("syntax error"))
         }
     }
@@ -192,12 +272,7 @@ impl BotHandler {
     // optional functions, should not be called
     // if the bot does not implement their conterpart
 
-    #[allow(unused_variable)]
-    fn cmd_loadsgf<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
-        fail!("Not Implemented.");
-    }
-
-    fn cmd_reg_genmove<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
+    fn cmd_reg_genmove<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
         match parsing::arg_parse_colour(args) {
             Some(col) => match bot.gtp_genmove_regression(col) {
                 Ok(mv) => (true, mv.to_string()),
@@ -207,16 +282,10 @@ impl BotHandler {
         }
     }
 
-    fn cmd_fixed_handicap<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
-        match from_str::<uint>(args.as_str_ascii()) {
+    fn cmd_fixed_handicap<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
+        match from_str::<uint>(args) {
             Some(n) if n >= 2 && n <= 9 => match bot.gtp_fixed_handicap(n) {
-                Ok(vec) => (true, {
-                    let mut it = vec.iter();
-                    let mut out = it.next().to_string();
-                    for &vrtx in it {
-                        out = out.append(" ").append(vrtx.to_string().as_slice());
-                    };
-                    out }),
+                Ok(vec) => (true, responses::format_vertex_list(vec.as_slice())),
                 Err(api::BoardNotEmpty) => (false, String::from_str("board not empty")),
                 _ => fail!("Unexpected error in gtp_boardsize.")
             },
@@ -225,16 +294,10 @@ impl BotHandler {
         }
     }
 
-    fn cmd_place_free_handicap<T: api::GoBot>(&self, bot: &mut T,  args: &[Ascii]) -> (bool, String) {
-        match from_str::<uint>(args.as_str_ascii()) {
+    fn cmd_place_free_handicap<T: api::GoBot>(&self, bot: &mut T,  args: &str) -> (bool, String) {
+        match from_str::<uint>(args) {
             Some(n) if n >= 2 => match bot.gtp_place_free_handicap(n) {
-                Ok(vec) => (true, {
-                    let mut it = vec.iter();
-                    let mut out = it.next().to_string();
-                    for &vrtx in it {
-                        out = out.append(" ").append(vrtx.to_string().as_slice());
-                    };
-                    out }),
+                Ok(vec) => (true, responses::format_vertex_list(vec.as_slice())),
                 Err(api::BoardNotEmpty) => (false, String::from_str("board not empty")),
                 _ => fail!("Unexpected error in gtp_boardsize.")
             },
@@ -243,9 +306,9 @@ impl BotHandler {
         }
     }
 
-    fn cmd_set_free_handicap<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
+    fn cmd_set_free_handicap<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
         let mut lst: Vec<api::Vertex> = Vec::new();
-        let mut it = args.split(|&c| { c == ' '.to_ascii()});
+        let mut it = args.split(' ');
         for elem in it {
             match parsing::arg_parse_vertex(elem) {
                 Some(vrtx) => { lst.push(vrtx); }
@@ -271,12 +334,12 @@ impl BotHandler {
         }
     }
 
-    fn cmd_time_settings<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool, String) {
-        let mut it = args.splitn(3, |&c| { c == ' '.to_ascii()});
+    fn cmd_time_settings<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
+        let mut it = args.splitn(' ', 3);
         match (it.next(), it.next(), it.next()) {
-            (Some(a), Some(b), Some(c)) => match (from_str::<uint>(a.as_str_ascii()),
-                                                  from_str::<uint>(b.as_str_ascii()),
-                                                  from_str::<uint>(c.as_str_ascii())) {
+            (Some(a), Some(b), Some(c)) => match (from_str::<uint>(a),
+                                                  from_str::<uint>(b),
+                                                  from_str::<uint>(c)) {
                 (Some(na), Some(nb), Some(nc)) => match bot.gtp_time_settings(na, nb, nc) {
                     Ok(()) => (true, String::new()),
                     Err(_) => fail!("Unexpected error in gtp_time_settings.")
@@ -287,16 +350,26 @@ impl BotHandler {
         }
     }
 
-    fn cmd_final_status_list<T: api::GoBot>(&self, bot: &mut T, args: &[Ascii]) -> (bool,String) {
+    fn cmd_time_left<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool, String) {
+        let mut it = args.splitn(' ', 2);
+        match (it.next(), it.next(), it.next()) {
+            (Some(a), Some(b), Some(c)) => match (parsing::arg_parse_colour(a),
+                                                  from_str::<uint>(b),
+                                                  from_str::<uint>(c)) {
+                (Some(col), Some(time), Some(stones)) => match bot.gtp_time_left(col, time, stones) {
+                    Ok(()) => (true, String::new()),
+                    Err(_) => fail!("Unexpected error in gtp_time_left.")
+                },
+                _ => (false, String::from_str("syntax error"))
+            },
+            _ => (false, String::from_str("syntax error"))
+        }
+    }
+
+    fn cmd_final_status_list<T: api::GoBot>(&self, bot: &mut T, args: &str) -> (bool,String) {
         match parsing::arg_parse_stone_status(args) {
             Some(st) => match bot.gtp_final_status_list(st) {
-                Ok(lst) => {
-                    let mut output = String::new();
-                    for &vrtx in lst.iter() {
-                        output = output.append(vrtx.to_string().as_slice());
-                    }
-                    (true, output)
-                },
+                Ok(lst) => (true, responses::format_vertex_list(lst.as_slice())),
                 _ => fail!("Unexpected error in gtp_final_status_list.")
             },
             None => (false, String::from_str("syntax error"))
@@ -305,11 +378,7 @@ impl BotHandler {
 
     fn cmd_final_score<T: api::GoBot>(&self, bot: &mut T) -> (bool, String) {
         match bot.gtp_final_score() {
-            Ok(val) => match val {
-                (0.0, _) => (true, String::from_str("0")),
-                (x, api::White) => (true, format!("w+{}", x)),
-                (x, api::Black) => (true, format!("b+{}", x))
-                },
+            Ok(val) => (true, responses::format_score(val)),
             Err(api::CannotScore) => (false, String::from_str("cannot score")),
             _ => fail!("Unexpected error in gtp_final_score.")
         }
@@ -322,12 +391,36 @@ impl BotHandler {
         }
     }
 
+    // the first line of the response is reported before the analysis,
+    // which goes on until `report` returns false, and the returned
+    // response is empty; only the colour is used, the reporting
+    // interval is left to the bot
+    fn cmd_lz_analyze<T: api::GoBot>(&self, bot: &mut T, id: &str, args: &str,
+                                     report: |&str| -> bool) -> String {
+        let colour = args.splitn(' ', 1).next().unwrap();
+        match parsing::arg_parse_colour(colour) {
+            Some(col) => {
+                if report(format!("={:s} ", id).as_slice()) {
+                    match bot.gtp_analyze(col, report) {
+                        Ok(()) => {},
+                        _ => fail!("Unexpected error in gtp_analyze.")
+                    }
+                }
+                String::new()
+            },
+            None => format!("?{:s} syntax error", id)
+        }
+    }
+
     // dispatcher
 
-    fn dispatch_cmd<T: api::GoBot>(&self, bot: &mut T, cmd: &[Ascii], args: &[Ascii]) -> (bool, String) {
-        match cmd.as_str_ascii() {
+    fn dispatch_cmd<T: api::GoBot>(&self, bot: &mut T, cmd: &str, args: &str) -> (bool, String) {
+        match cmd {
             "protocol_version" => (true, String::from_str("2")),
-            "name" => (true, bot.gtp_name()),
+            "name" => match self.name {
+                Some(ref n) => (true, n.clone()),
+                None => (true, bot.gtp_name())
+            },
             "version" => (true, bot.gtp_version()),
             "known_command" => (true, self.cmd_known_command(bot, args)),
             "list_commands" => (true, self.cmd_list_commands(bot)),
@@ -336,15 +429,12 @@ impl BotHandler {
             "komi" => self.cmd_komi(bot, args),
             "play" => self.cmd_play(bot, args),
             "genmove" => self.cmd_genmove(bot, args),
-            "loadsgf" => match self.genmove_regression {
-                true => self.cmd_loadsgf(bot, args),
-                false => (false, String::from_str("unknown command"))
-            },
+            // loadsgf is left to the custom commands of the bot
             "reg_genmove" => match self.genmove_regression {
                 true => self.cmd_reg_genmove(bot, args),
                 false => (false, String::from_str("unknown command"))
             },
-            "fixed_handicap" => match self.set_free_handicap {
+            "fixed_handicap" => match self.fixed_handicap {
                 true => self.cmd_fixed_handicap(bot, args),
                 false => (false, String::from_str("unknown command"))
             },
@@ -364,9 +454,10 @@ impl BotHandler {
                 true => self.cmd_time_settings(bot, args),
                 false => (false, String::from_str("unknown command"))
             },
-            "time_left" => match self.genmove_regression {
-                true => (true, String::new()), // noop for now
-                false => (false, String::from_str("unknown command"))
+            "time_left" => match (self.time_left, self.genmove_regression) {
+                (true, _) => self.cmd_time_left(bot, args),
+                (false, true) => (true, String::new()), // noop for bots without gtp_time_left
+                (false, false) => (false, String::from_str("unknown command"))
             },
             "final_status_list" => match self.final_status_list {
                 true => self.cmd_final_status_list(bot, args),
@@ -380,31 +471,124 @@ impl BotHandler {
                 true => (true, self.cmd_showboard(bot)),
                 false => (false, String::from_str("unknown command"))
             },
-            _ => bot.gtp_custom_command(cmd.as_str_ascii(), args.as_str_ascii())
+            // streamed by `execute` when available
+            "lz-analyze" => (false, String::from_str("unknown command")),
+            _ => bot.gtp_custom_command(cmd, args)
         }
     }
     // public functions
 
+    // makes `alias` behave as `target`, which is a command name
+    // optionally followed by arguments (ex: "genmove black")
+    // aliases must be ascii
+    pub fn add_alias(&mut self, alias: &str, target: &str) {
+        self.aliases.insert(String::from_str(alias), String::from_str(target));
+    }
+
+    // overrides the name returned by the bot
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(String::from_str(name));
+    }
+
+    // lets the bot analyse during the opponent's time, see ponder
+    pub fn set_ponder(&mut self, ponder: bool) {
+        self.ponder = ponder;
+    }
+
+    // to call between commands: if pondering is enabled and the last
+    // command was a genmove, analyses the opponent's move with
+    // gtp_analyze until `report` returns false, ex: once the next
+    // command came. The analysis lines are not sent to the controller.
+    pub fn ponder<T: api::GoBot>(&self, bot: &mut T, report: |&str| -> bool) {
+        match self.ponder_colour.get() {
+            Some(col) if self.ponder && self.analyze => {
+                self.ponder_colour.set(None);
+                let _ = bot.gtp_analyze(col, report);
+            },
+            _ => {}
+        }
+    }
+
     pub fn from_bot<T: api::GoBot>(bot: &mut T) -> BotHandler {
         let mut handler = BotHandler::new();
         handler.populate(bot);
+        bot.on_start();
         handler
     }
 
-    // handles content from ascii input
+    // handles content from utf-8 input
     // will parse and execute the first command encountered only
     // do nothing if no command is found
-    pub fn handle_command<T: api::GoBot>(&self, bot: &mut T, input: &[Ascii]) -> (bool, String) {
+    // a streamed response is returned whole, its lines being joined:
+    // as nothing can interrupt it, the analysis of `lz-analyze` is
+    // stopped after ANALYSIS_LINES lines
+    pub fn handle_command<T: api::GoBot>(&self, bot: &mut T, input: &str) -> (bool, String) {
+        let mut lines: Vec<String> = Vec::new();
+        let (continue_loop, result) = self.handle_command_streaming(bot, input, |line| {
+            lines.push(String::from_str(line));
+            // the header, then the analysis lines
+            lines.len() <= ANALYSIS_LINES
+        });
+        if lines.is_empty() {
+            (continue_loop, result)
+        } else {
+            (continue_loop, lines.connect("\n"))
+        }
+    }
+
+    // same as handle_command, but the response of `lz-analyze` is streamed:
+    // its first line, then the analysis lines reported by the bot, are
+    // passed to `report` as they come, until it returns false, ex: once
+    // the controller sent another command. The returned response is then
+    // empty, the caller only has to end it with an empty line.
+    pub fn handle_command_streaming<T: api::GoBot>(&self, bot: &mut T, input: &str,
+                                                   report: |&str| -> bool) -> (bool, String) {
+        let (continue_loop, result) = self.execute(bot, input, report);
+        if result.len() > 0 && diagnostics::enabled(diagnostics::Info) {
+            diagnostics::info(format!(">> {}", input.trim()).as_slice());
+            diagnostics::info(format!("<< {}", result).as_slice());
+        }
+        (continue_loop, result)
+    }
+
+    // replaces an alias by the command it stands for,
+    // its extra words being inserted before the arguments
+    fn expand_alias(&self, command: String, args: String) -> (String, String) {
+        match self.aliases.find(&command) {
+            Some(target) => {
+                let mut words = target.as_slice().splitn(' ', 1);
+                let new_command = String::from_str(words.next().unwrap());
+                let new_args = match words.next() {
+                    Some(extra) if args.len() > 0 => format!("{} {}", extra, args),
+                    Some(extra) => String::from_str(extra),
+                    None => args
+                };
+                (new_command, new_args)
+            },
+            None => (command, args)
+        }
+    }
+
+    fn execute<T: api::GoBot>(&self, bot: &mut T, input: &str,
+                              report: |&str| -> bool) -> (bool, String) {
         match parsing::parse_command(input) {
             Some(parsing::GTPCommand{id: id, command: command, args: args}) => {
-                if command.as_slice().as_str_ascii() == "quit" {
-                    (false, format!("={:s} bye",
-                        match id {Some(i) => format!("{:u}", i), _ => String::new()}))
+                self.ponder_colour.set(None);
+                let (command, args) = self.expand_alias(command, args);
+                let id = match id {Some(i) => format!("{:u}", i), _ => String::new()};
+                if !command.as_slice().is_ascii() {
+                    // command names are ascii only, but the response is still due
+                    (true, format!("?{:s} unknown command", id))
+                } else if command.as_slice() == "quit" {
+                    bot.on_quit();
+                    (false, format!("={:s} bye", id))
+                } else if command.as_slice() == "lz-analyze" && self.analyze {
+                    (true, self.cmd_lz_analyze(bot, id.as_slice(), args.as_slice(), report))
                 } else {
                     let (result, output) = self.dispatch_cmd(bot, command.as_slice(), args.as_slice());
                     (true, format!("{:c}{:s} {:s}",
                         match result {true => '=', false => '?'},
-                        match id {Some(i) => format!("{:u}", i), _ => String::new()},
+                        id,
                         output))
                     }
                 },
@@ -413,3 +597,84 @@ impl BotHandler {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use api;
+
+    // records the hooks called, analyses until it is interrupted
+    struct Recorder {
+        events: Vec<&'static str>
+    }
+
+    impl api::GoBot for Recorder {
+        fn gtp_name(&self) -> String { String::from_str("Recorder") }
+        fn gtp_version(&self) -> String { String::from_str("1.0") }
+        fn gtp_clear_board(&mut self) {}
+        fn gtp_komi(&mut self, _: f32) {}
+        fn gtp_boardsize(&mut self, _: uint) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_play(&mut self, _: api::ColouredMove) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_genmove(&mut self, _: api::Colour) -> api::Move { api::Pass }
+        fn gtp_final_score(&self) -> Result<(f32, api::Colour), api::GTPError> { Ok((0.5, api::White)) }
+        fn gtp_analyze(&mut self, _: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+            while report("info move D4 visits 1") {}
+            Ok(())
+        }
+        fn can_analyze(&self) -> bool { true }
+        fn on_start(&mut self) { self.events.push("start"); }
+        fn on_new_game(&mut self) { self.events.push("new game"); }
+        fn on_game_over(&mut self) { self.events.push("game over"); }
+        fn on_quit(&mut self) { self.events.push("quit"); }
+    }
+
+    fn run(bot: &mut Recorder, commands: &[&str]) -> Vec<&'static str> {
+        let handler = super::BotHandler::from_bot(bot);
+        for command in commands.iter() {
+            handler.handle_command(bot, *command);
+        }
+        bot.events.clone()
+    }
+
+    #[test]
+    fn hooks() {
+        let mut bot = Recorder{ events: Vec::new() };
+        assert_eq!(run(&mut bot, ["name"]), vec!("start"));
+        // scoring in the middle of a game does not end it
+        let mut bot = Recorder{ events: Vec::new() };
+        assert_eq!(run(&mut bot, ["clear_board", "play black D4", "final_score", "play white pass",
+                                  "genmove black", "final_score", "final_score"]),
+                   vec!("start", "new game", "game over"));
+        let mut bot = Recorder{ events: Vec::new() };
+        assert_eq!(run(&mut bot, ["clear_board", "play black resign", "clear_board",
+                                  "play black pass", "play white D4", "genmove black", "quit"]),
+                   vec!("start", "new game", "game over", "new game", "quit"));
+        // the game went on after two passes
+        let mut bot = Recorder{ events: Vec::new() };
+        assert_eq!(run(&mut bot, ["clear_board", "play black pass", "play white pass",
+                                  "play black D4", "play white pass", "genmove black"]),
+                   vec!("start", "new game", "game over", "game over"));
+    }
+    #[test]
+    fn bounded_analysis() {
+        let mut bot = Recorder{ events: Vec::new() };
+        let handler = super::BotHandler::from_bot(&mut bot);
+        let (_, response) = handler.handle_command(&mut bot, "1 lz-analyze black 10");
+        let lines: Vec<&str> = response.as_slice().lines().collect();
+        assert_eq!(lines.len(), super::ANALYSIS_LINES + 1);
+        assert_eq!(lines[0], "=1 ");
+        assert_eq!(lines[1], "info move D4 visits 1");
+    }
+    #[test]
+    fn aliases() {
+        let mut bot = Recorder{ events: Vec::new() };
+        let mut handler = super::BotHandler::from_bot(&mut bot);
+        for alias in ["gw", "gb", "zz", "aa"].iter() {
+            handler.add_alias(*alias, "genmove white");
+        }
+        let (_, list) = handler.handle_command(&mut bot, "list_commands");
+        let names: Vec<&str> = list.as_slice().lines().collect();
+        assert_eq!(names.slice_from(names.len() - 4), ["aa", "gb", "gw", "zz"].as_slice());
+        let (_, response) = handler.handle_command(&mut bot, "1 gb");
+        assert_eq!(response.as_slice(), "=1 pass");
+    }
+}
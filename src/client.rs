@@ -0,0 +1,457 @@
+//! The controller side of the protocol: drives an external engine
+//! (GNU Go, KataGo, or a bot built on this library) through GTP.
+//!
+//! Every command is sent with an id, and its response is read up to the
+//! empty line ending it. Standard commands are available as methods
+//! returning `api` types:
+//!
+//! ```ignore
+//! let mut engine = try!(gtprust::client::Client::spawn("gnugo", ["--mode".to_string(), "gtp".to_string()]));
+//! try!(engine.boardsize(9));
+//! try!(engine.clear_board());
+//! let move = try!(engine.genmove(gtprust::api::Black));
+//! ```
+
+use std::cell::Cell;
+use std::io::{IoError, IoResult, BufferedReader, Buffer, Writer};
+use std::io::pipe::PipeStream;
+use std::io::process::{Command, Process, InheritFd};
+use api;
+use parsing;
+use responses;
+
+/// Why a command did not give a result.
+#[deriving(Show)]
+pub enum ClientError {
+    /// The engine could not be reached, or closed its output.
+    IoFailure(IoError),
+    /// The engine answered with `?` and this message.
+    CommandFailed(String),
+    /// The engine answered something which is not a valid response
+    /// to the command.
+    MalformedResponse(String)
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// A response of the engine.
+#[deriving(PartialEq, Show)]
+pub struct Response {
+    pub id: Option<u32>,
+    /// True for `=`, false for `?`.
+    pub success: bool,
+    /// The text of the response, its lines separated by `\n`.
+    pub text: String
+}
+
+/// A connection to an engine, reading its responses from `R`
+/// and writing commands to `W`.
+pub struct Client<R, W> {
+    reader: R,
+    writer: W,
+    next_id: u32,
+    // waited for when the client is dropped, after its streams are closed
+    process: Option<Process>
+}
+
+/// A client of an engine running as a subprocess.
+pub type EngineClient = Client<BufferedReader<PipeStream>, PipeStream>;
+
+impl Client<BufferedReader<PipeStream>, PipeStream> {
+    /// Starts `program` with `args`. Its stderr is left to the
+    /// one of the current process.
+    pub fn spawn(program: &str, args: &[String]) -> IoResult<EngineClient> {
+        Client::spawn_command(Command::new(program).args(args))
+    }
+
+    /// Same as `spawn`, in the directory `cwd`.
+    pub fn spawn_in(program: &str, args: &[String], cwd: &Path) -> IoResult<EngineClient> {
+        Client::spawn_command(Command::new(program).args(args).cwd(cwd))
+    }
+
+    fn spawn_command(command: &mut Command) -> IoResult<EngineClient> {
+        let mut process = try!(command.stderr(InheritFd(2)).spawn());
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+        let mut client = Client::new(BufferedReader::new(stdout), stdin);
+        client.attach(process);
+        Ok(client)
+    }
+}
+
+impl<R: Buffer, W: Writer> Client<R, W> {
+    /// A client speaking over any pair of streams, ex: a socket.
+    pub fn new(reader: R, writer: W) -> Client<R, W> {
+        Client{ reader: reader, writer: writer, next_id: 1, process: None }
+    }
+
+    /// Makes the client responsible for `process`, the engine behind its
+    /// streams: `kill` stops it, and it is waited for when the client
+    /// is dropped.
+    pub fn attach(&mut self, process: Process) {
+        self.process = Some(process);
+    }
+
+    /// Kills the engine process, if any.
+    pub fn kill(&mut self) -> IoResult<()> {
+        match self.process {
+            Some(ref mut p) => p.signal_kill(),
+            None => Ok(())
+        }
+    }
+
+    /// The stream the responses are read from, ex: to change its timeout.
+    pub fn get_mut_reader<'a>(&'a mut self) -> &'a mut R {
+        &mut self.reader
+    }
+
+    /// Gives back the streams, ex: to inspect what was written.
+    pub fn unwrap(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+
+    /// Sends a single line command, with a new id, and reads its response.
+    pub fn send(&mut self, command: &str) -> ClientResult<Response> {
+        let id = self.next_id;
+        self.next_id += 1;
+        try!(self.writer.write_line(format!("{} {}", id, command).as_slice()).map_err(IoFailure));
+        try!(self.writer.flush().map_err(IoFailure));
+        let response = try!(self.read_response());
+        match response.id {
+            // engines omitting the id are tolerated
+            Some(i) if i != id => Err(MalformedResponse(format!("response to command {} instead of {}", i, id))),
+            _ => Ok(response)
+        }
+    }
+
+    /// Sends a command, and returns the text of its response
+    /// if it succeeded.
+    pub fn command(&mut self, command: &str) -> ClientResult<String> {
+        let response = try!(self.send(command));
+        if response.success {
+            Ok(response.text)
+        } else {
+            Err(CommandFailed(response.text))
+        }
+    }
+
+    /// Sends a command whose response goes on until the engine is
+    /// interrupted, as `lz-analyze`. The lines following the first one
+    /// are passed to `report` as they come; once it returns `false`, the
+    /// engine is interrupted by another command, whose response is
+    /// skipped. Returns the first line of the response.
+    pub fn send_streaming(&mut self, command: &str, report: |&str| -> bool) -> ClientResult<Response> {
+        let id = self.next_id;
+        self.next_id += 1;
+        try!(self.writer.write_line(format!("{} {}", id, command).as_slice()).map_err(IoFailure));
+        try!(self.writer.flush().map_err(IoFailure));
+        let mut first = try!(self.read_line());
+        while first.as_slice().trim().is_empty() {
+            first = try!(self.read_line());
+        }
+        let response = try!(parse_first_line(first.as_slice()));
+        match response.id {
+            Some(i) if i != id => return Err(MalformedResponse(format!("response to command {} instead of {}", i, id))),
+            _ => {}
+        }
+        loop {
+            let line = try!(self.read_line());
+            if line.as_slice().trim().is_empty() {
+                // the engine ended the response by itself
+                return Ok(response);
+            }
+            if !response.success || report(line.as_slice()) {
+                continue;
+            }
+            let interrupt = self.next_id;
+            self.next_id += 1;
+            try!(self.writer.write_line(format!("{} protocol_version", interrupt).as_slice()).map_err(IoFailure));
+            try!(self.writer.flush().map_err(IoFailure));
+            // lines sent before the interruption was seen
+            while !try!(self.read_line()).as_slice().trim().is_empty() {}
+            try!(self.read_response());
+            return Ok(response);
+        }
+    }
+
+    fn read_line(&mut self) -> ClientResult<String> {
+        let line = try!(parsing::read_line_lossy(&mut self.reader).map_err(IoFailure));
+        Ok(String::from_str(line.as_slice().trim_right_chars(['\n', '\r'].as_slice())))
+    }
+
+    // reads lines up to the empty one ending the response,
+    // ignoring empty lines before it
+    fn read_response(&mut self) -> ClientResult<Response> {
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            let line = try!(self.read_line());
+            if line.as_slice().trim().is_empty() {
+                if lines.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            lines.push(line);
+        }
+        let mut response = try!(parse_first_line(lines[0].as_slice()));
+        for line in lines.iter().skip(1) {
+            response.text.push_char('\n');
+            response.text.push_str(line.as_slice());
+        }
+        Ok(response)
+    }
+
+    // commands without result
+
+    fn expect_empty(&mut self, command: &str) -> ClientResult<()> {
+        self.command(command).map(|_| ())
+    }
+
+    pub fn boardsize(&mut self, size: uint) -> ClientResult<()> {
+        self.expect_empty(format!("boardsize {}", size).as_slice())
+    }
+
+    pub fn clear_board(&mut self) -> ClientResult<()> {
+        self.expect_empty("clear_board")
+    }
+
+    pub fn komi(&mut self, komi: f32) -> ClientResult<()> {
+        self.expect_empty(format!("komi {}", komi).as_slice())
+    }
+
+    pub fn play(&mut self, move: api::ColouredMove) -> ClientResult<()> {
+        self.expect_empty(format!("play {} {}", move.player.to_string(), move.move.to_string()).as_slice())
+    }
+
+    pub fn undo(&mut self) -> ClientResult<()> {
+        self.expect_empty("undo")
+    }
+
+    pub fn set_free_handicap(&mut self, stones: &[api::Vertex]) -> ClientResult<()> {
+        self.expect_empty(format!("set_free_handicap {}", responses::format_vertex_list(stones)).as_slice())
+    }
+
+    /// Times are given in seconds.
+    pub fn time_settings(&mut self, main_time: uint, byoyomi_time: uint, byoyomi_stones: uint) -> ClientResult<()> {
+        self.expect_empty(format!("time_settings {} {} {}", main_time, byoyomi_time, byoyomi_stones).as_slice())
+    }
+
+    pub fn time_left(&mut self, player: api::Colour, time: uint, stones: uint) -> ClientResult<()> {
+        self.expect_empty(format!("time_left {} {} {}", player.to_string(), time, stones).as_slice())
+    }
+
+    /// Loads a position from an SGF file, up to `move_number` if given.
+    /// The path is relative to the directory of the engine.
+    pub fn loadsgf(&mut self, path: &str, move_number: Option<uint>) -> ClientResult<()> {
+        match move_number {
+            Some(n) => self.expect_empty(format!("loadsgf {} {}", path, n).as_slice()),
+            None => self.expect_empty(format!("loadsgf {}", path).as_slice())
+        }
+    }
+
+    /// Asks the engine to stop, it should then exit.
+    pub fn quit(&mut self) -> ClientResult<()> {
+        self.expect_empty("quit")
+    }
+
+    // commands with a result
+
+    pub fn protocol_version(&mut self) -> ClientResult<uint> {
+        let text = try!(self.command("protocol_version"));
+        parse_response(text, |t| from_str::<uint>(t))
+    }
+
+    pub fn name(&mut self) -> ClientResult<String> {
+        self.command("name")
+    }
+
+    pub fn version(&mut self) -> ClientResult<String> {
+        self.command("version")
+    }
+
+    pub fn known_command(&mut self, command: &str) -> ClientResult<bool> {
+        let text = try!(self.command(format!("known_command {}", command).as_slice()));
+        parse_response(text, |t| responses::parse_bool(t))
+    }
+
+    pub fn list_commands(&mut self) -> ClientResult<Vec<String>> {
+        let text = try!(self.command("list_commands"));
+        Ok(text.as_slice().lines().map(|l| String::from_str(l.trim()))
+                                  .filter(|l| !l.is_empty()).collect())
+    }
+
+    pub fn genmove(&mut self, player: api::Colour) -> ClientResult<api::Move> {
+        let text = try!(self.command(format!("genmove {}", player.to_string()).as_slice()));
+        parse_response(text, |t| responses::parse_move(t))
+    }
+
+    pub fn reg_genmove(&mut self, player: api::Colour) -> ClientResult<api::Move> {
+        let text = try!(self.command(format!("reg_genmove {}", player.to_string()).as_slice()));
+        parse_response(text, |t| responses::parse_move(t))
+    }
+
+    pub fn fixed_handicap(&mut self, number: uint) -> ClientResult<Vec<api::Vertex>> {
+        let text = try!(self.command(format!("fixed_handicap {}", number).as_slice()));
+        parse_response(text, |t| responses::parse_vertex_list(t))
+    }
+
+    pub fn place_free_handicap(&mut self, number: uint) -> ClientResult<Vec<api::Vertex>> {
+        let text = try!(self.command(format!("place_free_handicap {}", number).as_slice()));
+        parse_response(text, |t| responses::parse_vertex_list(t))
+    }
+
+    pub fn final_status_list(&mut self, status: api::StoneStatus) -> ClientResult<Vec<api::Vertex>> {
+        let name = match status {
+            api::Alive => "alive",
+            api::Dead => "dead",
+            api::Seki => "seki"
+        };
+        let text = try!(self.command(format!("final_status_list {}", name).as_slice()));
+        parse_response(text, |t| responses::parse_vertex_list(t))
+    }
+
+    pub fn final_score(&mut self) -> ClientResult<(f32, api::Colour)> {
+        let text = try!(self.command("final_score"));
+        parse_response(text, |t| responses::parse_score(t))
+    }
+
+    pub fn showboard(&mut self) -> ClientResult<responses::Board> {
+        let text = try!(self.command("showboard"));
+        parse_response(text, |t| responses::parse_board(t))
+    }
+}
+
+// the status and id of a response, with the text of its first line
+fn parse_first_line(first: &str) -> ClientResult<Response> {
+    let success = match first.char_at(0) {
+        '=' => true,
+        '?' => false,
+        _ => return Err(MalformedResponse(format!("not a response: {}", first)))
+    };
+    let head = first.slice_from(1);
+    let digits = head.find(|c: char| !c.is_digit()).unwrap_or(head.len());
+    let id = if digits > 0 {
+        match from_str::<u32>(head.slice_to(digits)) {
+            Some(i) => Some(i),
+            None => return Err(MalformedResponse(format!("invalid id: {}", first)))
+        }
+    } else {
+        None
+    };
+    let rest = head.slice_from(digits);
+    if !rest.is_empty() && !rest.starts_with(" ") && !rest.starts_with("\t") {
+        return Err(MalformedResponse(format!("not a response: {}", first)));
+    }
+    Ok(Response{ id: id, success: success, text: String::from_str(rest.trim()) })
+}
+
+/// Turns the text of a response into a typed result, ex:
+/// `parse_response(text, |t| responses::parse_move(t))`.
+pub fn parse_response<T>(text: String, parser: |&str| -> Option<T>) -> ClientResult<T> {
+    match parser(text.as_slice()) {
+        Some(result) => Ok(result),
+        None => Err(MalformedResponse(text))
+    }
+}
+
+/// The result of a command which is not expected to fail. A `GoBot`
+/// wrapping an engine has no way to report most failures of the engine,
+/// and is left without a sensible answer: the task fails.
+pub fn unexpected<T>(engine: &str, command: &str, result: ClientResult<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => fail!("Failure of {} on {}: {}", engine, command, e)
+    }
+}
+
+/// Tells a `GoBot` wrapping engines whether it was started. The handler
+/// probes the optional commands before starting the bot, by calling
+/// them: until then, they must not reach the engines.
+pub struct Probing {
+    started: Cell<bool>
+}
+
+impl Probing {
+    pub fn new() -> Probing {
+        Probing{ started: Cell::new(false) }
+    }
+
+    /// To call from `GoBot::on_start`.
+    pub fn start(&self) {
+        self.started.set(true);
+    }
+
+    pub fn started(&self) -> bool {
+        self.started.get()
+    }
+
+    /// While probing, the result of an optional command, `dummy` if the
+    /// engines know it; `None` once started, the command must be sent.
+    pub fn probe<T>(&self, known: bool, dummy: T) -> Option<Result<T, api::GTPError>> {
+        if self.started.get() {
+            None
+        } else if known {
+            Some(Ok(dummy))
+        } else {
+            Some(Err(api::NotImplemented))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, MemWriter};
+    use api;
+
+    fn client<'a>(responses: &'a str) -> super::Client<BufReader<'a>, MemWriter> {
+        super::Client::new(BufReader::new(responses.as_bytes()), MemWriter::new())
+    }
+
+    #[test]
+    fn send() {
+        let mut c = client("=1 GNU Go\n\n\n?2 unknown command\n\n=3 first\nsecond\n\n");
+        assert_eq!(c.name().unwrap().as_slice(), "GNU Go");
+        match c.command("foo") {
+            Err(super::CommandFailed(msg)) => assert_eq!(msg.as_slice(), "unknown command"),
+            _ => fail!("failure expected")
+        }
+        assert_eq!(c.send("showboard").unwrap(), super::Response{
+            id: Some(3), success: true, text: String::from_str("first\nsecond") });
+        assert_eq!(c.writer.get_ref(), b"1 name\n2 foo\n3 showboard\n");
+    }
+
+    #[test]
+    fn typed_results() {
+        let mut c = client("=1 d4\n\n= PASS\n\n=3 C3 G7\nD5\n\n=4\n\n");
+        assert_eq!(c.genmove(api::Black).unwrap(), api::Stone(api::Vertex::from_str("D4").unwrap()));
+        assert_eq!(c.genmove(api::White).unwrap(), api::Pass);
+        assert_eq!(c.fixed_handicap(3).unwrap(), vec!(api::Vertex::from_str("C3").unwrap(),
+                                                      api::Vertex::from_str("G7").unwrap(),
+                                                      api::Vertex::from_str("D5").unwrap()));
+        assert!(c.play(api::ColouredMove{ player: api::White, move: api::Resign }).is_ok());
+        assert_eq!(c.writer.get_ref(), b"1 genmove black\n2 genmove white\n3 fixed_handicap 3\n4 play white resign\n");
+    }
+
+    #[test]
+    fn malformed() {
+        let mut c = client("=1 Z99\n\n=7 \n\nbye\n\n");
+        assert!(match c.genmove(api::Black) { Err(super::MalformedResponse(_)) => true, _ => false });
+        assert!(match c.clear_board() { Err(super::MalformedResponse(_)) => true, _ => false });
+        assert!(match c.clear_board() { Err(super::MalformedResponse(_)) => true, _ => false });
+        assert!(match c.clear_board() { Err(super::IoFailure(_)) => true, _ => false });
+    }
+
+    #[test]
+    fn send_streaming() {
+        let mut c = client("=1\ninfo move D4\ninfo move Q16\ninfo move C3\n\n=2 2\n\n=3\n\n");
+        let mut lines = Vec::new();
+        let response = c.send_streaming("lz-analyze black 10", |line| {
+            lines.push(String::from_str(line));
+            lines.len() < 2
+        }).unwrap();
+        assert!(response.success);
+        assert_eq!(lines, vec!(String::from_str("info move D4"), String::from_str("info move Q16")));
+        assert!(c.clear_board().is_ok());
+        assert_eq!(c.writer.get_ref(), b"1 lz-analyze black 10\n2 protocol_version\n3 clear_board\n");
+    }
+}
@@ -0,0 +1,481 @@
+//! A conformance test kit, checking that a bot honours the
+//! GTPv2 contract the library relies on.
+//!
+//! Each requirement drives a short command sequence through the same
+//! handler as `main_loop`, on a fresh bot, and is reported if violated.
+//! Requirements on optional commands are skipped when the bot does not
+//! implement them. The board size of the sequences is the first of 19,
+//! 13 and 9 that the bot accepts. In your tests:
+//!
+//! ```ignore
+//! fn new_bot() -> MyBot { MyBot::new() }
+//!
+//! #[test]
+//! fn conformance() {
+//!     gtprust::conformance::assert_conformance(new_bot);
+//! }
+//! ```
+
+use std::ascii::StrAsciiExt;
+use std::fmt;
+use std::task;
+use api;
+use regression::{Responder, BotResponder};
+
+/// A requirement of the specification that the bot did not meet.
+pub struct Violation {
+    pub requirement: &'static str,
+    pub description: &'static str,
+    /// What went wrong.
+    pub detail: String
+}
+
+impl fmt::Show for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.requirement, self.description, self.detail)
+    }
+}
+
+// board sizes tried for the sequences, in order
+static SIZES: &'static [uint] = &[19, 13, 9];
+
+// a command sequence, on the responses of a bot
+struct Session<'a> {
+    engine: &'a mut Responder + 'a,
+    // the board size of the sequence
+    size: uint
+}
+
+// the name of the vertex at these coordinates
+fn vertex(x: uint, y: uint) -> String {
+    api::Vertex::from_coords(x as u8, y as u8).unwrap().to_string()
+}
+
+impl<'a> Session<'a> {
+    // starts a session on the first board size the bot accepts
+    fn start(engine: &'a mut Responder + 'a) -> Result<Session<'a>, String> {
+        let mut session = Session{ engine: engine, size: 0 };
+        for size in SIZES.iter() {
+            match try!(session.send(format!("boardsize {}", size).as_slice())) {
+                (true, _) => {
+                    session.size = *size;
+                    return Ok(session);
+                },
+                (false, _) => {}
+            }
+        }
+        Err(String::from_str("none of the board sizes 19, 13 and 9 is accepted"))
+    }
+
+    // a new game on the board size of the session
+    fn new_game(&mut self) -> Result<(), String> {
+        try!(self.ok(format!("boardsize {}", self.size).as_slice()));
+        try!(self.ok("clear_board"));
+        Ok(())
+    }
+
+    // the point opposite to D4, ex: Q16 on 19x19
+    fn far_point(&self) -> String {
+        vertex(self.size - 3, self.size - 3)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(bool, String), String> {
+        match self.engine.respond(command) {
+            Ok(response) => {
+                let success = response.as_slice().starts_with("=");
+                let text = response.as_slice().slice_from(1).trim();
+                Ok((success, String::from_str(text)))
+            },
+            Err(e) => Err(format!("`{}` got no response: {}", command, e))
+        }
+    }
+
+    // the command must succeed, returns its response
+    fn ok(&mut self, command: &str) -> Result<String, String> {
+        match try!(self.send(command)) {
+            (true, text) => Ok(text),
+            (false, text) => Err(format!("`{}` failed with `{}`", command, text))
+        }
+    }
+
+    // the command must fail, returns the error message
+    fn fails(&mut self, command: &str) -> Result<String, String> {
+        match try!(self.send(command)) {
+            (false, text) => Ok(text),
+            (true, text) => Err(format!("`{}` succeeded with `{}`", command, text))
+        }
+    }
+
+    fn supports(&mut self, command: &str) -> Result<bool, String> {
+        Ok(try!(self.ok(format!("known_command {}", command).as_slice())).as_slice() == "true")
+    }
+}
+
+fn expect_equal(what: &str, expected: &str, actual: &str) -> Result<(), String> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!("{} should be `{}`, got `{}`", what, expected, actual))
+    }
+}
+
+struct Requirement {
+    name: &'static str,
+    description: &'static str,
+    // optional command needed for the requirement to apply
+    needs: Option<&'static str>,
+    check: fn(&mut Session) -> Result<(), String>
+}
+
+fn check_protocol_version(s: &mut Session) -> Result<(), String> {
+    let version = try!(s.ok("protocol_version"));
+    expect_equal("protocol_version", "2", version.as_slice())
+}
+
+fn check_name(s: &mut Session) -> Result<(), String> {
+    let name = try!(s.ok("name"));
+    if name.is_empty() {
+        return Err(String::from_str("the name is empty"));
+    }
+    if name.as_slice().contains_char('\n') {
+        return Err(String::from_str("the name spans several lines"));
+    }
+    Ok(())
+}
+
+fn check_boardsize(s: &mut Session) -> Result<(), String> {
+    try!(s.ok(format!("boardsize {}", s.size).as_slice()));
+    try!(s.fails("boardsize 26"));
+    try!(s.fails("boardsize 100"));
+    Ok(())
+}
+
+fn check_komi(s: &mut Session) -> Result<(), String> {
+    for komi in ["6.5", "0", "-1000.5", "1000000", "0.25"].iter() {
+        try!(s.ok(format!("komi {}", komi).as_slice()));
+    }
+    Ok(())
+}
+
+fn check_play_occupied(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black D4"));
+    let error = try!(s.fails("play white D4"));
+    expect_equal("the error", "invalid move", error.as_slice())
+}
+
+fn check_play_outside(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    let outside = s.size + 1;
+    try!(s.fails(format!("play black {}", vertex(outside, outside)).as_slice()));
+    try!(s.fails(format!("play black {}", vertex(outside, 1)).as_slice()));
+    try!(s.fails(format!("play black {}", vertex(1, outside)).as_slice()));
+    Ok(())
+}
+
+fn check_play_pass_and_same_colour(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black pass"));
+    try!(s.ok("play black pass"));
+    try!(s.ok("play white D4"));
+    try!(s.ok(format!("play white {}", s.far_point()).as_slice()));
+    Ok(())
+}
+
+fn check_clear_board(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black D4"));
+    try!(s.ok("clear_board"));
+    try!(s.ok("play white D4"));
+    Ok(())
+}
+
+fn check_boardsize_clears(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black D4"));
+    try!(s.new_game());
+    try!(s.ok("play white D4"));
+    Ok(())
+}
+
+fn check_genmove_plays(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    let mv = try!(s.ok("genmove black"));
+    match mv.as_slice().to_ascii_lower().as_slice() {
+        "pass" | "resign" => Ok(()),
+        vertex => {
+            // the generated move must be on the board of the bot
+            try!(s.fails(format!("play white {}", vertex).as_slice()));
+            Ok(())
+        }
+    }
+}
+
+fn check_reg_genmove(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black D4"));
+    let first = try!(s.ok("reg_genmove white"));
+    let second = try!(s.ok("reg_genmove white"));
+    try!(expect_equal("the second reg_genmove", first.as_slice(), second.as_slice()));
+    match first.as_slice().to_ascii_lower().as_slice() {
+        "pass" | "resign" => Ok(()),
+        vertex => {
+            // reg_genmove must not play its move
+            try!(s.ok(format!("play white {}", vertex).as_slice()));
+            Ok(())
+        }
+    }
+}
+
+fn check_undo(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black D4"));
+    try!(s.ok("undo"));
+    try!(s.ok("play white D4"));
+    try!(s.ok("clear_board"));
+    let error = try!(s.fails("undo"));
+    expect_equal("the error", "cannot undo", error.as_slice())
+}
+
+fn check_fixed_handicap(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    let stones = try!(s.ok("fixed_handicap 4"));
+    let count = stones.as_slice().words().count();
+    if count != 4 {
+        return Err(format!("fixed_handicap 4 placed {} stones: `{}`", count, stones));
+    }
+    let error = try!(s.fails("fixed_handicap 2"));
+    expect_equal("the error on a non-empty board", "board not empty", error.as_slice())
+}
+
+fn check_set_free_handicap(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.fails("set_free_handicap D4 D4"));
+    try!(s.ok("clear_board"));
+    try!(s.ok(format!("set_free_handicap D4 {}", s.far_point()).as_slice()));
+    try!(s.fails("play white D4"));
+    Ok(())
+}
+
+fn check_time_settings(s: &mut Session) -> Result<(), String> {
+    try!(s.ok("time_settings 0 0 0"));
+    try!(s.ok("time_settings 1800 30 5"));
+    Ok(())
+}
+
+fn check_showboard(s: &mut Session) -> Result<(), String> {
+    try!(s.new_game());
+    try!(s.ok("play black D4"));
+    try!(s.ok("showboard"));
+    Ok(())
+}
+
+static REQUIREMENTS: &'static [Requirement] = &[
+    Requirement{ name: "protocol_version", description: "the protocol version is 2",
+                 needs: None, check: check_protocol_version },
+    Requirement{ name: "name", description: "the name is a single non-empty line",
+                 needs: None, check: check_name },
+    Requirement{ name: "boardsize", description: "sizes above 25 are rejected",
+                 needs: None, check: check_boardsize },
+    Requirement{ name: "komi", description: "any komi is accepted, even absurd ones",
+                 needs: None, check: check_komi },
+    Requirement{ name: "play-occupied", description: "playing on an occupied point is an invalid move",
+                 needs: None, check: check_play_occupied },
+    Requirement{ name: "play-outside", description: "playing outside the board is an invalid move",
+                 needs: None, check: check_play_outside },
+    Requirement{ name: "play-sequence", description: "passes and several moves of the same colour are accepted",
+                 needs: None, check: check_play_pass_and_same_colour },
+    Requirement{ name: "clear_board", description: "clear_board empties the board",
+                 needs: None, check: check_clear_board },
+    Requirement{ name: "boardsize-clear", description: "the board is empty after boardsize and clear_board",
+                 needs: None, check: check_boardsize_clears },
+    Requirement{ name: "genmove", description: "the generated move is played on the board",
+                 needs: None, check: check_genmove_plays },
+    Requirement{ name: "reg_genmove", description: "reg_genmove is deterministic and does not play",
+                 needs: Some("reg_genmove"), check: check_reg_genmove },
+    Requirement{ name: "undo", description: "undo takes back the last move, and fails on an empty history",
+                 needs: Some("undo"), check: check_undo },
+    Requirement{ name: "fixed_handicap", description: "fixed handicap places the asked stones, on empty boards only",
+                 needs: Some("fixed_handicap"), check: check_fixed_handicap },
+    Requirement{ name: "set_free_handicap", description: "duplicate stones are rejected, the others placed",
+                 needs: Some("set_free_handicap"), check: check_set_free_handicap },
+    Requirement{ name: "time_settings", description: "time settings never fail",
+                 needs: Some("time_settings"), check: check_time_settings },
+    Requirement{ name: "showboard", description: "showboard never fails",
+                 needs: Some("showboard"), check: check_showboard }
+];
+
+fn run_requirement(engine: &mut Responder, requirement: &Requirement) -> Result<(), String> {
+    let mut session = try!(Session::start(engine));
+    match requirement.needs {
+        Some(cmd) => if !try!(session.supports(cmd)) {
+            return Ok(());
+        },
+        None => {}
+    }
+    (requirement.check)(&mut session)
+}
+
+/// Checks every requirement on a fresh bot built by `new_bot`,
+/// and returns those which are violated.
+/// A bot returning an error the library does not expect makes
+/// it fail, this is reported as a violation as well.
+pub fn check<T: api::GoBot>(new_bot: fn() -> T) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (i, requirement) in REQUIREMENTS.iter().enumerate() {
+        // each requirement runs in its own task, to survive failures
+        let result = task::try(proc() {
+            let mut bot = new_bot();
+            let mut responder = BotResponder::new(&mut bot);
+            run_requirement(&mut responder, &REQUIREMENTS[i])
+        });
+        let detail = match result {
+            Ok(Ok(())) => continue,
+            Ok(Err(detail)) => detail,
+            Err(_) => String::from_str("the library failed on an unexpected error of the bot")
+        };
+        violations.push(Violation{
+            requirement: requirement.name,
+            description: requirement.description,
+            detail: detail
+        });
+    }
+    violations
+}
+
+/// Fails with the list of violations, if any.
+pub fn assert_conformance<T: api::GoBot>(new_bot: fn() -> T) {
+    let violations = check(new_bot);
+    if !violations.is_empty() {
+        let lines: Vec<String> = violations.iter().map(|v| format!("{}", v)).collect();
+        fail!("{} violated requirement(s):\n{}", violations.len(), lines.as_slice().connect("\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api;
+
+    // accepts anything without a second thought
+    struct Careless;
+
+    #[allow(unused_variable)]
+    impl api::GoBot for Careless {
+        fn gtp_name(&self) -> String { String::from_str("Careless") }
+        fn gtp_version(&self) -> String { String::from_str("1") }
+        fn gtp_clear_board(&mut self) {}
+        fn gtp_komi(&mut self, komi: f32) {}
+        fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_genmove(&mut self, player: api::Colour) -> api::Move { api::Pass }
+    }
+
+    fn new_careless() -> Careless {
+        Careless
+    }
+
+    // keeps the stones, to reject what the specification asks to
+    struct Careful {
+        max_size: uint,
+        size: uint,
+        stones: Vec<api::Vertex>
+    }
+
+    #[allow(unused_variable)]
+    impl api::GoBot for Careful {
+        fn gtp_name(&self) -> String { String::from_str("Careful") }
+        fn gtp_version(&self) -> String { String::from_str("1") }
+        fn gtp_clear_board(&mut self) { self.stones.clear(); }
+        fn gtp_komi(&mut self, komi: f32) {}
+        fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+            if size > self.max_size {
+                return Err(api::InvalidBoardSize);
+            }
+            self.size = size;
+            Ok(())
+        }
+        fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+            match move.move {
+                api::Stone(v) => {
+                    let (x, y) = v.to_coords();
+                    if x as uint > self.size || y as uint > self.size || self.stones.contains(&v) {
+                        return Err(api::InvalidMove);
+                    }
+                    self.stones.push(v);
+                    Ok(())
+                },
+                _ => Ok(())
+            }
+        }
+        fn gtp_genmove(&mut self, player: api::Colour) -> api::Move { api::Pass }
+        fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+            if !self.stones.is_empty() {
+                return Err(api::BoardNotEmpty);
+            }
+            for (i, v) in stones.iter().enumerate() {
+                if stones.slice_from(i + 1).contains(v) {
+                    return Err(api::BadVertexList);
+                }
+            }
+            self.stones.push_all(stones);
+            Ok(())
+        }
+    }
+
+    fn new_careful() -> Careful {
+        Careful{ max_size: 25, size: 19, stones: Vec::new() }
+    }
+
+    fn new_small() -> Careful {
+        Careful{ max_size: 9, size: 9, stones: Vec::new() }
+    }
+
+    // takes duplicate handicap stones
+    struct Duplicating {
+        bot: Careful
+    }
+
+    #[allow(unused_variable)]
+    impl api::GoBot for Duplicating {
+        fn gtp_name(&self) -> String { self.bot.gtp_name() }
+        fn gtp_version(&self) -> String { self.bot.gtp_version() }
+        fn gtp_clear_board(&mut self) { self.bot.gtp_clear_board() }
+        fn gtp_komi(&mut self, komi: f32) {}
+        fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> { self.bot.gtp_boardsize(size) }
+        fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> { self.bot.gtp_play(move) }
+        fn gtp_genmove(&mut self, player: api::Colour) -> api::Move { api::Pass }
+        fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+            self.bot.stones.push_all(stones);
+            Ok(())
+        }
+    }
+
+    fn new_duplicating() -> Duplicating {
+        Duplicating{ bot: new_careful() }
+    }
+
+    #[test]
+    fn check() {
+        let violations = super::check(new_careless);
+        let names: Vec<&str> = violations.iter().map(|v| v.requirement).collect();
+        assert!(names.contains(&"boardsize"));
+        assert!(names.contains(&"play-occupied"));
+        assert!(!names.contains(&"komi"));
+        // optional commands are not implemented
+        assert!(!names.contains(&"undo"));
+    }
+
+    #[test]
+    fn board_size() {
+        // the sequences are played on the sizes the bot accepts
+        assert!(super::check(new_small).is_empty());
+    }
+
+    #[test]
+    fn set_free_handicap() {
+        assert!(super::check(new_careful).is_empty());
+        let violations = super::check(new_duplicating);
+        let names: Vec<&str> = violations.iter().map(|v| v.requirement).collect();
+        assert_eq!(names, vec!("set_free_handicap"));
+    }
+}
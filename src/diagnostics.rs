@@ -0,0 +1,235 @@
+//! Diagnostics for your bot, written on stderr.
+//!
+//! Stdout belongs to the protocol: writing anything else on it would
+//! corrupt the responses. This module provides a small logging facade
+//! and the live graphics understood by GoGui, which displays them on
+//! its board while the bot is thinking:
+//!
+//! ```ignore
+//! fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
+//!     diagnostics::info("starting search");
+//!     // ...
+//!     let mut gfx = diagnostics::Gfx::new();
+//!     gfx.label(best, "1").colour("green", candidates.as_slice())
+//!        .text(format!("{} playouts", playouts).as_slice());
+//!     gfx.emit();
+//!     // ...
+//! }
+//! ```
+
+use std::io::stdio;
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+use api;
+
+/// Importance of a message. Errors and warnings are always
+/// shown, info needs a verbosity of 1 and debug of 2.
+#[deriving(PartialEq, Show)]
+pub enum Level {
+    Error = 0,
+    Warning = 1,
+    Info = 2,
+    Debug = 3
+}
+
+static VERBOSITY: AtomicUint = INIT_ATOMIC_UINT;
+
+/// Sets the verbosity (0 by default), the launcher sets it
+/// from the number of `-v` flags.
+pub fn set_verbosity(verbosity: uint) {
+    VERBOSITY.store(verbosity, SeqCst);
+}
+
+/// Returns true if messages of this level are shown.
+pub fn enabled(level: Level) -> bool {
+    (level as uint) <= 1 + VERBOSITY.load(SeqCst)
+}
+
+/// Writes a message on stderr, if its level is enabled.
+pub fn log(level: Level, message: &str) {
+    if enabled(level) {
+        let prefix = match level {
+            Error => "error",
+            Warning => "warning",
+            Info => "info",
+            Debug => "debug"
+        };
+        let _ = stdio::stderr().write_str(format!("{}: {}\n", prefix, message).as_slice());
+    }
+}
+
+pub fn error(message: &str) {
+    log(Error, message);
+}
+
+pub fn warning(message: &str) {
+    log(Warning, message);
+}
+
+pub fn info(message: &str) {
+    log(Info, message);
+}
+
+pub fn debug(message: &str) {
+    log(Debug, message);
+}
+
+/// A set of live graphics for GoGui, to be shown over its board.
+/// Build it with the chainable methods, then `emit()` it.
+/// Each emission replaces the previous one.
+pub struct Gfx {
+    labels: Vec<(api::Vertex, String)>,
+    colours: Vec<(String, Vec<api::Vertex>)>,
+    influence: Vec<(api::Vertex, f32)>,
+    marked: Vec<api::Vertex>,
+    squares: Vec<api::Vertex>,
+    triangles: Vec<api::Vertex>,
+    circles: Vec<api::Vertex>,
+    variation: Vec<api::ColouredMove>,
+    text: Option<String>
+}
+
+fn vertex_list(vertices: &[api::Vertex]) -> String {
+    let names: Vec<String> = vertices.iter().map(|v| v.to_string()).collect();
+    names.as_slice().connect(" ")
+}
+
+impl Gfx {
+    pub fn new() -> Gfx {
+        Gfx {
+            labels: Vec::new(),
+            colours: Vec::new(),
+            influence: Vec::new(),
+            marked: Vec::new(),
+            squares: Vec::new(),
+            triangles: Vec::new(),
+            circles: Vec::new(),
+            variation: Vec::new(),
+            text: None
+        }
+    }
+
+    /// Writes a short label on a vertex.
+    pub fn label<'a>(&'a mut self, vertex: api::Vertex, label: &str) -> &'a mut Gfx {
+        self.labels.push((vertex, String::from_str(label)));
+        self
+    }
+
+    /// Paints vertices with a colour, either a name GoGui knows
+    /// (ex: "green") or an hexadecimal value (ex: "#ff0000").
+    pub fn colour<'a>(&'a mut self, colour: &str, vertices: &[api::Vertex]) -> &'a mut Gfx {
+        self.colours.push((String::from_str(colour), Vec::from_slice(vertices)));
+        self
+    }
+
+    /// Shows an influence on a vertex, from -1.0 (white) to 1.0 (black).
+    pub fn influence<'a>(&'a mut self, vertex: api::Vertex, value: f32) -> &'a mut Gfx {
+        let value = if value > 1.0 { 1.0 } else if value < -1.0 { -1.0 } else { value };
+        self.influence.push((vertex, value));
+        self
+    }
+
+    pub fn mark<'a>(&'a mut self, vertex: api::Vertex) -> &'a mut Gfx {
+        self.marked.push(vertex);
+        self
+    }
+
+    pub fn square<'a>(&'a mut self, vertex: api::Vertex) -> &'a mut Gfx {
+        self.squares.push(vertex);
+        self
+    }
+
+    pub fn triangle<'a>(&'a mut self, vertex: api::Vertex) -> &'a mut Gfx {
+        self.triangles.push(vertex);
+        self
+    }
+
+    pub fn circle<'a>(&'a mut self, vertex: api::Vertex) -> &'a mut Gfx {
+        self.circles.push(vertex);
+        self
+    }
+
+    /// Shows a sequence of moves, numbered on the board.
+    pub fn variation<'a>(&'a mut self, moves: &[api::ColouredMove]) -> &'a mut Gfx {
+        self.variation = Vec::from_slice(moves);
+        self
+    }
+
+    /// Shows a line of text in GoGui's status bar.
+    pub fn text<'a>(&'a mut self, text: &str) -> &'a mut Gfx {
+        self.text = Some(String::from_str(text));
+        self
+    }
+
+    /// The text block GoGui expects, ending with an empty line.
+    pub fn to_string(&self) -> String {
+        let mut output = String::from_str("gogui-gfx:\n");
+        if self.labels.len() > 0 {
+            output.push_str("LABEL");
+            for &(vertex, ref label) in self.labels.iter() {
+                output.push_str(format!(" {} {}", vertex.to_string(), label).as_slice());
+            }
+            output.push_str("\n");
+        }
+        for &(ref colour, ref vertices) in self.colours.iter() {
+            output.push_str(format!("COLOR {} {}\n", colour, vertex_list(vertices.as_slice())).as_slice());
+        }
+        if self.influence.len() > 0 {
+            output.push_str("INFLUENCE");
+            for &(vertex, value) in self.influence.iter() {
+                output.push_str(format!(" {} {:.2f}", vertex.to_string(), value).as_slice());
+            }
+            output.push_str("\n");
+        }
+        for &(keyword, ref vertices) in [("MARK", &self.marked), ("SQUARE", &self.squares),
+                                         ("TRIANGLE", &self.triangles), ("CIRCLE", &self.circles)].iter() {
+            if vertices.len() > 0 {
+                output.push_str(format!("{} {}\n", keyword, vertex_list(vertices.as_slice())).as_slice());
+            }
+        }
+        if self.variation.len() > 0 {
+            output.push_str("VAR");
+            for mv in self.variation.iter() {
+                output.push_str(format!(" {} {}", match mv.player {
+                    api::Black => "b",
+                    api::White => "w"
+                }, mv.move.to_string()).as_slice());
+            }
+            output.push_str("\n");
+        }
+        match self.text {
+            Some(ref t) => output.push_str(format!("TEXT {}\n", t).as_slice()),
+            None => {}
+        }
+        output.push_str("\n");
+        output
+    }
+
+    /// Writes the graphics on stderr, where GoGui reads them.
+    pub fn emit(&self) {
+        let _ = stdio::stderr().write_str(self.to_string().as_slice());
+    }
+
+    /// Removes any live graphics shown by GoGui.
+    pub fn clear() {
+        let _ = stdio::stderr().write_str("gogui-gfx:\nCLEAR\n\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api;
+
+    #[test]
+    fn gfx_to_string() {
+        let a1 = api::Vertex::from_str("A1").unwrap();
+        let c3 = api::Vertex::from_str("C3").unwrap();
+        let mut gfx = super::Gfx::new();
+        gfx.label(a1, "x").label(c3, "y").colour("green", [a1, c3])
+           .influence(c3, -0.5).square(a1)
+           .variation([api::ColouredMove{player: api::Black, move: api::Stone(c3)}])
+           .text("hello");
+        assert_eq!(gfx.to_string().as_slice(),
+                   "gogui-gfx:\nLABEL A1 x C3 y\nCOLOR green A1 C3\nINFLUENCE C3 -0.50\n\
+                    SQUARE A1\nVAR b C3\nTEXT hello\n\n");
+    }
+}
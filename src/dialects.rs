@@ -0,0 +1,226 @@
+// Translations between the dialects of GTP spoken by GUIs and engines,
+// used by the proxy when the engine does not know a command but knows
+// its equivalent:
+//
+// * kgs-time_settings <-> time_settings, KGS byo-yomi periods being
+//   given as a single stone per period
+// * lz-analyze <-> kata-analyze, with their output (win rates as
+//   integers in 1/10000 for Leela Zero, fractions for KataGo)
+// * GTP version 1 commands (black, white, genmove_black, genmove_white,
+//   help) <-> their version 2 equivalents
+
+use responses;
+
+// the commands having an equivalent
+pub static COMMANDS: &'static [&'static str] = &[
+    "kgs-time_settings", "time_settings", "lz-analyze", "kata-analyze", "play", "black", "white",
+    "genmove", "genmove_black", "genmove_white", "list_commands", "help", "clear_board"
+];
+
+// the command to send when the engine does not know `command`
+pub fn equivalent(command: &str) -> Option<&'static str> {
+    match command {
+        "kgs-time_settings" => Some("time_settings"),
+        "time_settings" => Some("kgs-time_settings"),
+        "lz-analyze" => Some("kata-analyze"),
+        "kata-analyze" => Some("lz-analyze"),
+        "play" => Some("black"),
+        "black" | "white" => Some("play"),
+        "genmove" => Some("genmove_black"),
+        "genmove_black" | "genmove_white" => Some("genmove"),
+        "list_commands" => Some("help"),
+        "help" => Some("list_commands"),
+        // boardsize clears the board in version 1
+        "clear_board" => Some("boardsize"),
+        _ => None
+    }
+}
+
+pub fn is_analysis(command: &str) -> bool {
+    command == "lz-analyze" || command == "kata-analyze"
+}
+
+fn kgs_to_time_settings(args: &str) -> Option<String> {
+    let words: Vec<&str> = args.words().collect();
+    let numbers: Vec<uint> = words.iter().skip(1).filter_map(|w| from_str::<uint>(*w)).collect();
+    if words.is_empty() || numbers.len() + 1 != words.len() {
+        return None;
+    }
+    let (main, period, stones) = match (words[0], numbers.as_slice()) {
+        ("none", []) => (0, 1, 0),
+        ("absolute", [main]) => (main, 0, 0),
+        ("byoyomi", [main, period, _]) => (main, period, 1),
+        ("canadian", [main, period, stones]) => (main, period, stones),
+        _ => return None
+    };
+    Some(format!("time_settings {} {} {}", main, period, stones))
+}
+
+fn time_settings_to_kgs(args: &str) -> Option<String> {
+    let numbers: Vec<Option<uint>> = args.words().map(|w| from_str::<uint>(w)).collect();
+    match numbers.as_slice() {
+        // no main time nor byo-yomi is no limit, not a lost game
+        [Some(0), Some(0), Some(_)] => Some(String::from_str("kgs-time_settings none")),
+        [Some(main), Some(0), Some(_)] => Some(format!("kgs-time_settings absolute {}", main)),
+        [Some(_), Some(_), Some(0)] => Some(String::from_str("kgs-time_settings none")),
+        [Some(main), Some(period), Some(stones)] =>
+            Some(format!("kgs-time_settings canadian {} {} {}", main, period, stones)),
+        _ => None
+    }
+}
+
+// KataGo options Leela Zero does not know, each with a single value
+static KATA_OPTIONS: &'static [&'static str] = &[
+    "ownership", "ownershipStdev", "pvVisits", "pvEdgeVisits", "rootInfo", "movesOwnership", "movesOwnershipStdev"
+];
+
+fn kata_args_to_lz(args: &str) -> String {
+    let words: Vec<&str> = args.words().collect();
+    let mut kept = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if KATA_OPTIONS.iter().any(|o| *o == words[i]) {
+            i += 2;
+        } else {
+            kept.push(words[i]);
+            i += 1;
+        }
+    }
+    kept.connect(" ")
+}
+
+fn with_args(command: &str, args: &str) -> String {
+    if args.is_empty() { String::from_str(command) } else { format!("{} {}", command, args) }
+}
+
+// the command line in the dialect of `equivalent(command)`,
+// `size` being the current board size
+pub fn translate(command: &str, args: &str, size: uint) -> String {
+    let args = args.trim();
+    let translated = match command {
+        "kgs-time_settings" => kgs_to_time_settings(args),
+        "time_settings" => time_settings_to_kgs(args),
+        "lz-analyze" => Some(with_args("kata-analyze", args)),
+        "kata-analyze" => Some(with_args("lz-analyze", kata_args_to_lz(args).as_slice())),
+        // play black D4 -> black D4
+        "play" => Some(String::from_str(args)),
+        "black" | "white" => Some(format!("play {} {}", command, args)),
+        "genmove" => Some(format!("genmove_{}", args)),
+        "genmove_black" => Some(String::from_str("genmove black")),
+        "genmove_white" => Some(String::from_str("genmove white")),
+        "list_commands" => Some(String::from_str("help")),
+        "help" => Some(String::from_str("list_commands")),
+        "clear_board" => Some(format!("boardsize {}", size)),
+        _ => None
+    };
+    translated.unwrap_or(with_args(command, args))
+}
+
+// win rates, priors and lower confidence bounds
+static RATES: &'static [&'static str] = &["winrate", "prior", "lcb"];
+
+// KataGo keys ending the move infos
+static KATA_END: &'static [&'static str] = &["ownership", "ownershipStdev", "rootInfo", "movesOwnership"];
+
+fn kata_to_lz(line: &str) -> String {
+    let words: Vec<&str> = line.words().collect();
+    let mut output: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        i += 1;
+        if KATA_END.iter().any(|k| *k == word) {
+            break;
+        }
+        match word {
+            "info" => output.push(String::from_str(word)),
+            "move" | "visits" | "order" if i < words.len() => {
+                output.push(String::from_str(word));
+                output.push(String::from_str(words[i]));
+                i += 1;
+            },
+            "pv" => {
+                output.push(String::from_str(word));
+                while i < words.len() && responses::parse_move(words[i]).is_some() {
+                    output.push(String::from_str(words[i]));
+                    i += 1;
+                }
+            },
+            _ if RATES.iter().any(|r| *r == word) && i < words.len() => {
+                match from_str::<f64>(words[i]) {
+                    Some(rate) => {
+                        output.push(String::from_str(word));
+                        output.push(format!("{}", (rate * 10000.0).round() as int));
+                    },
+                    None => {}
+                }
+                i += 1;
+            },
+            // other keys are left out, with their values
+            _ => while i < words.len() && from_str::<f64>(words[i]).is_some() {
+                i += 1;
+            }
+        }
+    }
+    output.connect(" ")
+}
+
+fn lz_to_kata(line: &str) -> String {
+    let words: Vec<&str> = line.words().collect();
+    let translated: Vec<String> = words.iter().enumerate().map(|(i, word)| {
+        let rate = i > 0 && RATES.iter().any(|r| *r == words[i - 1]);
+        match from_str::<int>(*word) {
+            Some(n) if rate => format!("{}", n as f64 / 10000.0),
+            _ => String::from_str(*word)
+        }
+    }).collect();
+    translated.connect(" ")
+}
+
+// an analysis line of the engine's `sent` command, in the dialect
+// of the GUI's `received` one
+pub fn translate_analysis(sent: &str, received: &str, line: &str) -> String {
+    match (sent, received) {
+        ("kata-analyze", "lz-analyze") => kata_to_lz(line),
+        ("lz-analyze", "kata-analyze") => lz_to_kata(line),
+        _ => String::from_str(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn time_settings() {
+        assert_eq!(super::translate("kgs-time_settings", "byoyomi 600 30 5", 19).as_slice(),
+                   "time_settings 600 30 1");
+        assert_eq!(super::translate("kgs-time_settings", "none", 19).as_slice(), "time_settings 0 1 0");
+        assert_eq!(super::translate("time_settings", "300 0 0", 19).as_slice(), "kgs-time_settings absolute 300");
+        assert_eq!(super::translate("time_settings", "0 0 0", 19).as_slice(), "kgs-time_settings none");
+        assert_eq!(super::translate("time_settings", "300 60 25", 19).as_slice(),
+                   "kgs-time_settings canadian 300 60 25");
+        assert_eq!(super::translate("kgs-time_settings", "fischer 10", 19).as_slice(),
+                   "kgs-time_settings fischer 10");
+    }
+
+    #[test]
+    fn version_1() {
+        assert_eq!(super::translate("play", "black D4", 19).as_slice(), "black D4");
+        assert_eq!(super::translate("white", "pass", 19).as_slice(), "play white pass");
+        assert_eq!(super::translate("genmove", "white", 19).as_slice(), "genmove_white");
+        assert_eq!(super::translate("clear_board", "", 9).as_slice(), "boardsize 9");
+    }
+
+    #[test]
+    fn analysis() {
+        assert_eq!(super::translate("kata-analyze", "B 50 ownership true", 19).as_slice(), "lz-analyze B 50");
+        let kata = "info move D4 visits 120 utility 0.1 winrate 0.5342 scoreMean 1.5 prior 0.25 lcb 0.51 order 0 \
+                    pv D4 Q16 pvVisits 120 80 info move C3 visits 2 winrate 0.4 prior 0.01 lcb 0.3 order 1 pv C3 \
+                    ownership 0.1 -0.2";
+        assert_eq!(super::translate_analysis("kata-analyze", "lz-analyze", kata).as_slice(),
+                   "info move D4 visits 120 winrate 5342 prior 2500 lcb 5100 order 0 pv D4 Q16 \
+                    info move C3 visits 2 winrate 4000 prior 100 lcb 3000 order 1 pv C3");
+        assert_eq!(super::translate_analysis("lz-analyze", "kata-analyze",
+                                             "info move D4 visits 120 winrate 5342 pv D4 Q16").as_slice(),
+                   "info move D4 visits 120 winrate 0.5342 pv D4 Q16");
+    }
+}
@@ -0,0 +1,464 @@
+//! A bot combining several engines, which vote for its moves.
+//!
+//! Every command changing the position is sent to all the engines,
+//! so that they stay synchronised. For `genmove`, each engine proposes
+//! a move, with `reg_genmove` if it knows it, otherwise with `genmove`
+//! followed by `undo`. The chosen move is then played into all of them.
+//!
+//! Votes are counted once per engine, or with the weights of the
+//! engines. Ties go to the move the arbiter prefers, if one is given:
+//! an engine of the ensemble knowing `kata-analyze` or `lz-analyze`,
+//! whose analysis ranks the tied moves by visits. Otherwise they go to
+//! the move of the first engine among the tied ones.
+//!
+//! ```ignore
+//! let engines = vec!(Member::new(try!(Client::spawn("gnugo", args)), 1.0), ...);
+//! let mut bot = try!(Ensemble::new(engines, Weighted, Some(1)));
+//! gtprust::launcher::launch(&mut bot);
+//! ```
+
+use std::cell::RefCell;
+use std::io::{Buffer, Writer};
+use time;
+use api;
+use client::{Client, ClientResult, CommandFailed, Probing, parse_response, unexpected};
+use responses;
+
+/// How votes are counted.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Voting {
+    /// One vote per engine.
+    Majority,
+    /// Each engine votes with its weight.
+    Weighted
+}
+
+/// An engine of the ensemble.
+pub struct Member<R, W> {
+    client: RefCell<Client<R, W>>,
+    pub weight: f64,
+    name: String,
+    commands: Vec<String>
+}
+
+impl<R: Buffer, W: Writer> Member<R, W> {
+    /// An engine voting with `weight`. It is asked its name and commands.
+    pub fn new(mut client: Client<R, W>, weight: f64) -> ClientResult<Member<R, W>> {
+        let name = try!(client.name());
+        let commands = try!(client.list_commands());
+        Ok(Member{ client: RefCell::new(client), weight: weight, name: name, commands: commands })
+    }
+
+    /// Gives back the client, to send `quit` to the engine.
+    pub fn unwrap(self) -> Client<R, W> {
+        self.client.unwrap()
+    }
+
+    fn knows(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c.as_slice() == command)
+    }
+}
+
+/// The ensemble, a `GoBot` driving its members.
+pub struct Ensemble<R, W> {
+    members: Vec<Member<R, W>>,
+    voting: Voting,
+    // index of the member settling ties
+    arbiter: Option<uint>,
+    // in milliseconds
+    analysis_time: u64,
+    probing: Probing
+}
+
+// interval of the analysis lines, in centiseconds
+static ANALYSIS_INTERVAL: uint = 10;
+
+/// The move with the most votes, `votes` giving the proposed move and
+/// the weight of each engine, in order. Returns the tied moves if
+/// several have the most votes.
+pub fn count_votes(votes: &[(api::Move, f64)]) -> Result<api::Move, Vec<api::Move>> {
+    let mut totals: Vec<(api::Move, f64)> = Vec::new();
+    for &(move, weight) in votes.iter() {
+        match totals.iter().position(|&(m, _)| m == move) {
+            Some(i) => {
+                let (m, total) = totals[i];
+                *totals.get_mut(i) = (m, total + weight);
+            },
+            None => totals.push((move, weight))
+        }
+    }
+    let best = totals.iter().fold(0.0f64, |b, &(_, w)| if w > b { w } else { b });
+    let tied: Vec<api::Move> = totals.iter().filter(|&&(_, w)| w == best).map(|&(m, _)| m).collect();
+    if tied.len() == 1 {
+        Ok(tied[0])
+    } else {
+        Err(tied)
+    }
+}
+
+impl<R: Buffer, W: Writer> Ensemble<R, W> {
+    /// An ensemble of at least one engine. The arbiter, if any, is the
+    /// index of an engine knowing `kata-analyze` or `lz-analyze`.
+    /// Fails if an engine knows neither `reg_genmove` nor `undo`.
+    pub fn new(members: Vec<Member<R, W>>, voting: Voting, arbiter: Option<uint>) -> ClientResult<Ensemble<R, W>> {
+        if members.is_empty() {
+            return Err(CommandFailed(String::from_str("an ensemble needs an engine")));
+        }
+        for m in members.iter() {
+            if !m.knows("reg_genmove") && !m.knows("undo") {
+                return Err(CommandFailed(format!("{} knows neither reg_genmove nor undo", m.name)));
+            }
+        }
+        match arbiter {
+            Some(i) if i >= members.len() || !(members[i].knows("kata-analyze") || members[i].knows("lz-analyze")) =>
+                return Err(CommandFailed(String::from_str("the arbiter must be an engine knowing an analyze command"))),
+            _ => {}
+        }
+        Ok(Ensemble{ members: members, voting: voting, arbiter: arbiter, analysis_time: 1000,
+                     probing: Probing::new() })
+    }
+
+    /// Sets how long the arbiter analyses ties, in milliseconds.
+    pub fn set_analysis_time(&mut self, milliseconds: u64) {
+        self.analysis_time = milliseconds;
+    }
+
+    /// Gives back the engines.
+    pub fn unwrap(self) -> Vec<Member<R, W>> {
+        self.members
+    }
+
+    // sends a command to every engine, returning the responses
+    fn broadcast(&self, command: &str) -> Vec<ClientResult<String>> {
+        self.members.iter().map(|m| m.client.borrow_mut().command(command)).collect()
+    }
+
+    // sends a command which cannot fail to every engine
+    fn broadcast_all(&self, command: &str) {
+        for m in self.members.iter() {
+            unexpected(m.name.as_slice(), command, m.client.borrow_mut().command(command));
+        }
+    }
+
+    // sends a command every engine must accept or refuse together,
+    // returns the response of the first engine, None if they refused it
+    fn broadcast_checked(&self, command: &str) -> Option<String> {
+        let mut results = self.broadcast(command);
+        let refused = results.iter().filter(|r| match **r { Err(CommandFailed(_)) => true, _ => false }).count();
+        if refused > 0 && refused < results.len() {
+            fail!("The engines disagree on {}", command);
+        }
+        for (m, result) in self.members.iter().zip(results.iter()) {
+            match *result {
+                Ok(_) | Err(CommandFailed(_)) => {},
+                Err(ref e) => fail!("Failure of {} on {}: {}", m.name, command, e)
+            }
+        }
+        if refused > 0 {
+            None
+        } else {
+            results.remove(0).unwrap().ok()
+        }
+    }
+
+    // the move proposed by an engine, leaving its position unchanged
+    fn propose(&self, member: &Member<R, W>, player: api::Colour) -> api::Move {
+        let mut client = member.client.borrow_mut();
+        let name = member.name.as_slice();
+        if member.knows("reg_genmove") {
+            return unexpected(name, "reg_genmove", client.reg_genmove(player));
+        }
+        let move = unexpected(name, "genmove", client.genmove(player));
+        if move != api::Resign {
+            unexpected(name, "undo", client.undo());
+        }
+        move
+    }
+
+    // the tied move with the most visits in the analysis of the arbiter
+    fn settle(&self, arbiter: &Member<R, W>, player: api::Colour, tied: &[api::Move]) -> api::Move {
+        let command = if arbiter.knows("kata-analyze") { "kata-analyze" } else { "lz-analyze" };
+        let line = format!("{} {} {}", command, player.to_string(), ANALYSIS_INTERVAL);
+        let deadline = time::precise_time_ns() + self.analysis_time * 1000000;
+        let mut latest = Vec::new();
+        let result = arbiter.client.borrow_mut().send_streaming(line.as_slice(), |info| {
+            match responses::parse_analysis(info) {
+                Some(infos) => latest = infos,
+                None => {}
+            }
+            time::precise_time_ns() < deadline
+        });
+        match result {
+            Ok(ref response) if !response.success => fail!("Failure of {} on {}: {}", arbiter.name, command, response.text),
+            Ok(_) => {},
+            Err(e) => fail!("Failure of {} on {}: {}", arbiter.name, command, e)
+        }
+        let mut best = tied[0];
+        let mut visits = 0u;
+        for info in latest.iter() {
+            if tied.iter().any(|m| *m == info.move) && info.visits > visits {
+                best = info.move;
+                visits = info.visits;
+            }
+        }
+        best
+    }
+
+    // true if all the engines know the command
+    fn all_know(&self, command: &str) -> bool {
+        self.members.iter().all(|m| m.knows(command))
+    }
+
+    // the first engine answers the commands which do not change the position
+    fn first(&self, command: &str) -> ClientResult<String> {
+        self.members[0].client.borrow_mut().command(command)
+    }
+}
+
+impl<R: Buffer, W: Writer> api::GoBot for Ensemble<R, W> {
+    fn gtp_name(&self) -> String {
+        String::from_str("Ensemble")
+    }
+
+    fn gtp_version(&self) -> String {
+        let names: Vec<&str> = self.members.iter().map(|m| m.name.as_slice()).collect();
+        names.connect(" + ")
+    }
+
+    fn gtp_clear_board(&mut self) -> () {
+        self.broadcast_all("clear_board");
+    }
+
+    fn gtp_komi(&mut self, komi: f32) -> () {
+        self.broadcast_all(format!("komi {}", komi).as_slice());
+    }
+
+    fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+        let command = format!("boardsize {}", size);
+        if self.broadcast_checked(command.as_slice()).is_some() { Ok(()) } else { Err(api::InvalidBoardSize) }
+    }
+
+    fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+        let command = format!("play {} {}", move.player.to_string(), move.move.to_string());
+        if self.broadcast_checked(command.as_slice()).is_some() { Ok(()) } else { Err(api::InvalidMove) }
+    }
+
+    fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
+        let votes: Vec<(api::Move, f64)> = self.members.iter().map(|m| {
+            let weight = match self.voting { Majority => 1.0, Weighted => m.weight };
+            (self.propose(m, player), weight)
+        }).collect();
+        let chosen = match count_votes(votes.as_slice()) {
+            Ok(move) => move,
+            Err(tied) => match self.arbiter {
+                Some(i) => self.settle(&self.members[i], player, tied.as_slice()),
+                // the first engine proposing one of the tied moves
+                None => votes.iter().map(|&(m, _)| m).find(|m| tied.iter().any(|t| *t == *m)).unwrap()
+            }
+        };
+        if chosen != api::Resign {
+            let command = format!("play {} {}", player.to_string(), chosen.to_string());
+            self.broadcast_all(command.as_slice());
+        }
+        chosen
+    }
+
+    fn gtp_undo(&mut self) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.all_know("undo"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        if self.broadcast_checked("undo").is_some() { Ok(()) } else { Err(api::CannotUndo) }
+    }
+
+    fn gtp_fixed_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.all_know("fixed_handicap"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let command = format!("fixed_handicap {}", number);
+        match self.broadcast_checked(command.as_slice()) {
+            // the engines place the same stones, the first one gives them
+            Some(text) => Ok(unexpected(self.members[0].name.as_slice(), "fixed_handicap",
+                                        parse_response(text, |s| responses::parse_vertex_list(s)))),
+            None => Err(api::BoardNotEmpty)
+        }
+    }
+
+    fn gtp_place_free_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.all_know("place_free_handicap"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        // the first engine chooses, the others are given its stones
+        let name = self.members[0].name.clone();
+        let stones = match self.first(format!("place_free_handicap {}", number).as_slice()) {
+            Err(CommandFailed(_)) => return Err(api::BoardNotEmpty),
+            other => unexpected(name.as_slice(), "place_free_handicap",
+                                other.and_then(|t| parse_response(t, |s| responses::parse_vertex_list(s))))
+        };
+        let command = format!("set_free_handicap {}", responses::format_vertex_list(stones.as_slice()));
+        for m in self.members.iter().skip(1) {
+            unexpected(m.name.as_slice(), "set_free_handicap", m.client.borrow_mut().command(command.as_slice()));
+        }
+        Ok(stones)
+    }
+
+    fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.all_know("set_free_handicap"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let command = format!("set_free_handicap {}", responses::format_vertex_list(stones));
+        if self.broadcast_checked(command.as_slice()).is_some() { Ok(()) } else { Err(api::BadVertexList) }
+    }
+
+    fn gtp_time_settings(&mut self, main_time: uint, byoyomi_time: uint, byoyomi_stones: uint)
+                         -> Result<(), api::GTPError> {
+        match self.probing.probe(self.all_know("time_settings"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        self.broadcast_all(format!("time_settings {} {} {}", main_time, byoyomi_time, byoyomi_stones).as_slice());
+        Ok(())
+    }
+
+    fn gtp_time_left(&mut self, player: api::Colour, time: uint, stones: uint) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.all_know("time_left"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        self.broadcast_all(format!("time_left {} {} {}", player.to_string(), time, stones).as_slice());
+        Ok(())
+    }
+
+    fn gtp_final_status_list(&self, status: api::StoneStatus) -> Result<Vec<api::Vertex>, api::GTPError> {
+        if !self.members[0].knows("final_status_list") {
+            return Err(api::NotImplemented);
+        }
+        if !self.probing.started() {
+            return Ok(Vec::new());
+        }
+        let name = match status {
+            api::Alive => "alive",
+            api::Dead => "dead",
+            api::Seki => "seki"
+        };
+        let result = self.first(format!("final_status_list {}", name).as_slice());
+        Ok(unexpected(self.members[0].name.as_slice(), "final_status_list",
+                      result.and_then(|t| parse_response(t, |s| responses::parse_vertex_list(s)))))
+    }
+
+    fn gtp_final_score(&self) -> Result<(f32, api::Colour), api::GTPError> {
+        if !self.members[0].knows("final_score") {
+            return Err(api::NotImplemented);
+        }
+        if !self.probing.started() {
+            return Ok((0.0, api::White));
+        }
+        match self.first("final_score") {
+            Err(CommandFailed(_)) => Err(api::CannotScore),
+            other => Ok(unexpected(self.members[0].name.as_slice(), "final_score",
+                                   other.and_then(|t| parse_response(t, |s| responses::parse_score(s)))))
+        }
+    }
+
+    fn gtp_showboard(&self) -> Result<(uint, Vec<api::Vertex>, Vec<api::Vertex>, uint, uint), api::GTPError> {
+        if !self.members[0].knows("showboard") {
+            return Err(api::NotImplemented);
+        }
+        if !self.probing.started() {
+            return Ok((0, Vec::new(), Vec::new(), 0, 0));
+        }
+        let board = unexpected(self.members[0].name.as_slice(), "showboard",
+                               self.first("showboard").and_then(|t| parse_response(t, |s| responses::parse_board(s))));
+        Ok((board.size, board.black, board.white,
+            board.black_captured.unwrap_or(0), board.white_captured.unwrap_or(0)))
+    }
+
+    fn on_start(&mut self) {
+        self.probing.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, MemWriter};
+    use api;
+    use api::GoBot;
+    use client::Client;
+
+    fn member<'a>(responses: &'a str, weight: f64) -> super::Member<BufReader<'a>, MemWriter> {
+        super::Member::new(Client::new(BufReader::new(responses.as_bytes()), MemWriter::new()), weight).unwrap()
+    }
+
+    fn stone(text: &str) -> api::Move {
+        api::Stone(api::Vertex::from_str(text).unwrap())
+    }
+
+    fn sent<'a>(member: super::Member<BufReader<'a>, MemWriter>) -> String {
+        let (_, writer) = member.unwrap().unwrap();
+        String::from_utf8(writer.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn count_votes() {
+        assert_eq!(super::count_votes([(stone("D4"), 1.0), (stone("Q16"), 1.0), (stone("D4"), 1.0)]),
+                   Ok(stone("D4")));
+        assert_eq!(super::count_votes([(stone("D4"), 1.0), (stone("Q16"), 2.5)]), Ok(stone("Q16")));
+        assert_eq!(super::count_votes([(stone("D4"), 1.0), (api::Pass, 1.0)]), Err(vec!(stone("D4"), api::Pass)));
+    }
+
+    #[test]
+    fn genmove() {
+        let members = vec!(member("=1 A\n\n=2 genmove\nundo\n\n=3 D4\n\n=4\n\n=5\n\n", 1.0),
+                           member("=1 B\n\n=2 reg_genmove\n\n=3 Q16\n\n=4\n\n", 1.0),
+                           member("=1 C\n\n=2 genmove\nundo\n\n=3 Q16\n\n=4\n\n=5\n\n", 1.0));
+        let mut bot = super::Ensemble::new(members, super::Majority, None).unwrap();
+        bot.on_start();
+        assert_eq!(bot.gtp_genmove(api::Black), stone("Q16"));
+        assert_eq!(bot.gtp_version().as_slice(), "A + B + C");
+        let mut members = bot.unwrap();
+        assert_eq!(sent(members.remove(0).unwrap()).as_slice(),
+                   "1 name\n2 list_commands\n3 genmove black\n4 undo\n5 play black Q16\n");
+        assert_eq!(sent(members.remove(0).unwrap()).as_slice(),
+                   "1 name\n2 list_commands\n3 reg_genmove black\n4 play black Q16\n");
+    }
+
+    #[test]
+    fn settle() {
+        // a tie between the moves of the engines, settled by the analysis of the second one
+        let members = vec!(member("=1 A\n\n=2 reg_genmove\n\n=3 D4\n\n=4\n\n", 1.0),
+                           member("=1 B\n\n=2 reg_genmove\nlz-analyze\n\n=3 Q16\n\n=4 \n\
+                                   info move D4 visits 10 winrate 0.4 order 1 pv D4 \
+                                   info move Q16 visits 50 winrate 0.6 order 0 pv Q16\n\n=5\n\n", 1.0));
+        let mut bot = super::Ensemble::new(members, super::Majority, Some(1)).unwrap();
+        bot.on_start();
+        assert_eq!(bot.gtp_genmove(api::Black), stone("Q16"));
+        let mut members = bot.unwrap();
+        assert_eq!(sent(members.remove(0).unwrap()).as_slice(),
+                   "1 name\n2 list_commands\n3 reg_genmove black\n4 play black Q16\n");
+        assert_eq!(sent(members.remove(0).unwrap()).as_slice(),
+                   "1 name\n2 list_commands\n3 reg_genmove black\n4 lz-analyze black 10\n5 play black Q16\n");
+    }
+
+    #[test]
+    fn fixed_handicap() {
+        let members = vec!(member("=1 A\n\n=2 undo\nfixed_handicap\n\n=3 D4 Q16\n\n", 1.0),
+                           member("=1 B\n\n=2 undo\nfixed_handicap\n\n=3 D4 Q16\n\n", 1.0));
+        let mut bot = super::Ensemble::new(members, super::Majority, None).unwrap();
+        bot.on_start();
+        assert_eq!(bot.gtp_fixed_handicap(2).unwrap(),
+                   vec!(api::Vertex::from_str("D4").unwrap(), api::Vertex::from_str("Q16").unwrap()));
+        let mut members = bot.unwrap();
+        assert_eq!(sent(members.remove(0).unwrap()).as_slice(), "1 name\n2 list_commands\n3 fixed_handicap 2\n");
+    }
+
+    #[test]
+    fn new() {
+        let members = vec!(member("=1 A\n\n=2 genmove\n\n", 1.0));
+        assert!(super::Ensemble::new(members, super::Majority, None).is_err());
+        let members = vec!(member("=1 A\n\n=2 undo\n\n", 1.0));
+        assert!(super::Ensemble::new(members, super::Weighted, Some(0)).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+// Randomized harness for the parser and the dispatcher.
+//
+// Inputs are generated from a seeded generator, biased towards what
+// GTP looks like, and fed to the parser and to the full handler with
+// a stub bot. Nothing may fail, and responses must be well framed.
+//
+// `GTPRUST_FUZZ_ITERATIONS` and `GTPRUST_FUZZ_SEED` allow longer or
+// different runs than the default ones, ex:
+//     GTPRUST_FUZZ_ITERATIONS=1000000 cargo test fuzz
+
+use std::os;
+use std::rand::{Rng, SeedableRng, XorShiftRng};
+use api;
+use bothandler;
+use parsing;
+
+static DEFAULT_ITERATIONS: uint = 5000;
+
+static COMMANDS: &'static [&'static str] = &[
+    "protocol_version", "name", "version", "known_command", "list_commands",
+    "boardsize", "clear_board", "komi", "play", "genmove", "loadsgf", "reg_genmove",
+    "fixed_handicap", "place_free_handicap", "set_free_handicap", "undo",
+    "time_settings", "time_left", "final_status_list", "final_score", "showboard",
+    "lz-analyze", "foo", "quit"
+];
+
+static TOKENS: &'static [&'static str] = &[
+    "b", "w", "black", "WHITE", "pass", "PASS", "resign", "alive", "dead", "seki",
+    "A1", "T19", "Z25", "Z26", "I5", "J10", "a1", "A0", "A", "A-1", "AA1",
+    "0", "2", "9", "10", "19", "25", "26", "-1", "4294967296", "6.5", "-0.5", "1e40", "NaN"
+];
+
+static ALPHABET: &'static [u8] = b"abcdefgHIJKLMNz0123456789 \t\n\r#+-.=?_\x00\x07\x1b\x7f";
+
+fn iterations() -> uint {
+    os::getenv("GTPRUST_FUZZ_ITERATIONS").and_then(|n| from_str::<uint>(n.as_slice()))
+                                          .unwrap_or(DEFAULT_ITERATIONS)
+}
+
+fn new_rng() -> XorShiftRng {
+    let seed = os::getenv("GTPRUST_FUZZ_SEED").and_then(|n| from_str::<u32>(n.as_slice()))
+                                              .unwrap_or(42);
+    SeedableRng::from_seed([seed, 0x193a6754, 0xa8a7d469, 0x97830e05])
+}
+
+fn random_input<R: Rng>(rng: &mut R) -> String {
+    let len = rng.gen_range(0u, 40);
+    range(0, len).map(|_| {
+        match rng.gen_range(0u, 20) {
+            // anything in the ascii range
+            0 | 1 => rng.gen_range(0u8, 128) as char,
+            // or beyond
+            2 => *rng.choose(['é', '囲', '\u00a0', '\u2028', '\ufffd']).unwrap(),
+            _ => ALPHABET[rng.gen_range(0, ALPHABET.len())] as char
+        }
+    }).collect()
+}
+
+// a line looking like a GTP command
+fn random_command<R: Rng>(rng: &mut R) -> String {
+    let mut line = String::new();
+    if rng.gen() {
+        line.push_str(format!("{} ", rng.gen_range(0u64, 5000000000)).as_slice());
+    }
+    line.push_str(*rng.choose(COMMANDS).unwrap());
+    for _ in range(0, rng.gen_range(0u, 5)) {
+        line.push_str(if rng.gen_weighted_bool(5) { " \t " } else { " " });
+        line.push_str(*rng.choose(TOKENS).unwrap());
+    }
+    if rng.gen_weighted_bool(5) {
+        line.push_str(" # comment");
+    }
+    line.push_char('\n');
+    line
+}
+
+// a valid argument of the given type
+fn random_arg<R: Rng>(rng: &mut R, arg_type: parsing::ArgType) -> String {
+    let vertex = api::Vertex::from_coords(rng.gen_range(1u8, 26), rng.gen_range(1u8, 26)).unwrap();
+    match arg_type {
+        parsing::ColourArg => String::from_str(*rng.choose(["b", "W", "black", "WHITE", "White"]).unwrap()),
+        parsing::VertexArg => vertex.to_string(),
+        parsing::MoveArg if rng.gen_weighted_bool(4) => String::from_str(*rng.choose(["pass", "PASS", "resign"]).unwrap()),
+        parsing::MoveArg => vertex.to_string(),
+        parsing::ColouredMoveArg => random_arg(rng, parsing::ColourArg).append(" ")
+                                        .append(random_arg(rng, parsing::MoveArg).as_slice()),
+        parsing::StoneStatusArg => String::from_str(*rng.choose(["alive", "DEAD", "seki"]).unwrap())
+    }
+}
+
+// a random string, with non-ascii characters
+fn random_unicode<R: Rng>(rng: &mut R) -> String {
+    let len = rng.gen_range(0u, 6);
+    range(0, len).map(|_| match rng.gen_range(0u, 4) {
+        0 => 'é',
+        1 => '囲',
+        2 => (rng.gen_range(b'A', b'Z' + 1)) as char,
+        _ => (rng.gen_range(b'0', b'9' + 1)) as char
+    }).collect()
+}
+
+// answers everything with valid values, and every error it may return
+struct StubBot {
+    size: uint,
+    moves: uint
+}
+
+#[allow(unused_variable)]
+impl api::GoBot for StubBot {
+    fn gtp_name(&self) -> String { String::from_str("Stub") }
+    fn gtp_version(&self) -> String { String::from_str("1.0") }
+    fn gtp_clear_board(&mut self) { self.moves = 0; }
+    fn gtp_komi(&mut self, komi: f32) {}
+    fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+        if size < 1 || size > 25 {
+            return Err(api::InvalidBoardSize);
+        }
+        self.size = size;
+        Ok(())
+    }
+    fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+        match move.move {
+            api::Stone(v) if v.to_coords().val0() == v.to_coords().val1() => Err(api::InvalidMove),
+            _ => { self.moves += 1; Ok(()) }
+        }
+    }
+    fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
+        self.moves += 1;
+        api::Stone(api::Vertex::from_coords(1, 2).unwrap())
+    }
+    fn gtp_genmove_regression(&self, player: api::Colour) -> Result<api::Move, api::GTPError> {
+        Ok(api::Pass)
+    }
+    fn gtp_undo(&mut self) -> Result<(), api::GTPError> {
+        if self.moves == 0 {
+            return Err(api::CannotUndo);
+        }
+        self.moves -= 1;
+        Ok(())
+    }
+    fn gtp_fixed_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        if self.moves > 0 {
+            return Err(api::BoardNotEmpty);
+        }
+        self.moves += number;
+        Ok(range(1, number + 1).map(|i| api::Vertex::from_coords(i as u8, 4).unwrap()).collect())
+    }
+    fn gtp_place_free_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        self.gtp_fixed_handicap(if number > 9 { 9 } else { number })
+    }
+    fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+        if self.moves > 0 {
+            return Err(api::BoardNotEmpty);
+        }
+        for (i, a) in stones.iter().enumerate() {
+            if stones.slice_from(i + 1).contains(a) {
+                return Err(api::BadVertexList);
+            }
+        }
+        Ok(())
+    }
+    fn gtp_time_settings(&mut self, main_time: uint, byoyomi_time: uint, byoyomi_stones: uint) -> Result<(), api::GTPError> {
+        Ok(())
+    }
+    fn gtp_final_status_list(&self, status: api::StoneStatus) -> Result<Vec<api::Vertex>, api::GTPError> {
+        Ok(vec!(api::Vertex::from_coords(3, 3).unwrap(), api::Vertex::from_coords(4, 4).unwrap()))
+    }
+    fn gtp_final_score(&self) -> Result<(f32, api::Colour), api::GTPError> {
+        if self.moves == 0 { Err(api::CannotScore) } else { Ok((3.5, api::White)) }
+    }
+    fn gtp_showboard(&self) -> Result<(uint, Vec<api::Vertex>, Vec<api::Vertex>, uint, uint), api::GTPError> {
+        Ok((self.size, vec!(api::Vertex::from_coords(1, 1).unwrap()), Vec::new(), 0, 2))
+    }
+    fn gtp_analyze(&mut self, player: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+        let mut visits = 0u;
+        loop {
+            visits += 1;
+            if !report(format!("info move A1 visits {}", visits).as_slice()) {
+                return Ok(());
+            }
+        }
+    }
+    fn can_analyze(&self) -> bool {
+        true
+    }
+}
+
+// a response must be a single `=` or `?` block, with the id of the command
+fn check_framing(input: &str, continue_loop: bool, response: &str) {
+    match parsing::parse_command(input) {
+        None => {
+            assert!(response.is_empty(), "response {} to no command {}", response, input);
+            assert!(continue_loop);
+        },
+        Some(cmd) => {
+            let id = match cmd.id { Some(i) => format!("{}", i), None => String::new() };
+            assert!(response.starts_with("=") || response.starts_with("?"), "bad response {}", response);
+            assert!(response.slice_from(1).starts_with(id.append(" ").as_slice()),
+                    "bad id in response {} to {}", response, input);
+            assert!(!response.contains("\n\n"), "empty line in response {}", response);
+            assert!(!response.ends_with("\n"), "trailing newline in response {}", response);
+            assert_eq!(continue_loop, cmd.command.as_slice() != "quit");
+        }
+    }
+}
+
+#[test]
+fn fuzz_parse_command() {
+    let mut rng = new_rng();
+    for _ in range(0, iterations()) {
+        let input = random_input(&mut rng);
+        match parsing::parse_command(input.as_slice()) {
+            Some(cmd) => {
+                assert!(cmd.command.len() > 0);
+                for c in cmd.command.as_slice().chars().chain(cmd.args.as_slice().chars()) {
+                    assert!(c != '\n' && c != '#' && !c.is_control(),
+                            "unexpected character in {}", cmd);
+                }
+                assert!(!cmd.command.as_slice().contains_char(' '));
+                assert!(!cmd.args.as_slice().contains("  "));
+            },
+            None => {}
+        }
+    }
+}
+
+#[test]
+fn fuzz_parse_args() {
+    let mut rng = new_rng();
+    let types = [parsing::ColourArg, parsing::VertexArg, parsing::MoveArg,
+                 parsing::ColouredMoveArg, parsing::StoneStatusArg];
+    for _ in range(0, iterations()) {
+        let arg_types: Vec<parsing::ArgType> = range(0, rng.gen_range(0u, 4))
+            .map(|_| *rng.choose(types).unwrap()).collect();
+        // valid arguments, some of them replaced by junk, and maybe extra ones
+        let mut valid = true;
+        let mut words: Vec<String> = Vec::new();
+        for arg_type in arg_types.iter() {
+            if rng.gen_weighted_bool(5) {
+                valid = false;
+                words.push(if rng.gen() { String::from_str(*rng.choose(TOKENS).unwrap()) }
+                           else { random_unicode(&mut rng) });
+            } else {
+                words.push(random_arg(&mut rng, *arg_type));
+            }
+        }
+        if rng.gen_weighted_bool(4) {
+            words.push(String::from_str(*rng.choose(TOKENS).unwrap()));
+        }
+        let input = words.connect(" ");
+        match parsing::parse_args(input.as_slice(), arg_types.as_slice()) {
+            Some(args) => {
+                assert_eq!(args.len(), arg_types.len());
+                for (arg, arg_type) in args.iter().zip(arg_types.iter()) {
+                    assert!(match (arg, *arg_type) {
+                        (&parsing::ArgColour(_), parsing::ColourArg) => true,
+                        (&parsing::ArgVertex(_), parsing::VertexArg) => true,
+                        (&parsing::ArgMove(_), parsing::MoveArg) => true,
+                        (&parsing::ArgColouredMove(_), parsing::ColouredMoveArg) => true,
+                        (&parsing::ArgStoneStatus(_), parsing::StoneStatusArg) => true,
+                        _ => false
+                    }, "{} parsed as {} from {}", arg_types, args, input);
+                }
+            },
+            None => assert!(!valid, "valid arguments {} rejected as {}", input, arg_types)
+        }
+    }
+}
+
+#[test]
+fn fuzz_vertex_from_str() {
+    let mut rng = new_rng();
+    for _ in range(0, iterations()) {
+        let text = random_unicode(&mut rng);
+        match api::Vertex::from_str(text.as_slice()) {
+            Some(v) => assert_eq!(api::Vertex::from_str(v.to_string().as_slice()), Some(v)),
+            None => {}
+        }
+    }
+}
+
+#[test]
+fn fuzz_handle_command() {
+    let mut rng = new_rng();
+    let mut bot = StubBot{ size: 19, moves: 0 };
+    let handler = bothandler::BotHandler::from_bot(&mut bot);
+    for _ in range(0, iterations()) {
+        let input = match rng.gen_range(0u, 8) {
+            0 | 1 => random_input(&mut rng),
+            // non-ascii arguments, or command names
+            2 => String::from_str(random_command(&mut rng).as_slice().trim_right())
+                     .append(" ").append(random_unicode(&mut rng).as_slice()),
+            3 => random_unicode(&mut rng).append(" ").append(random_command(&mut rng).as_slice()),
+            _ => random_command(&mut rng)
+        };
+        // the analysis of lz-analyze ends after a few lines
+        let (continue_loop, response) = handler.handle_command(&mut bot, input.as_slice());
+        check_framing(input.as_slice(), continue_loop, response.as_slice());
+    }
+}
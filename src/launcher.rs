@@ -0,0 +1,336 @@
+//! A standard command-line front end for bot binaries.
+//!
+//! `launch` parses the usual engine flags and starts the
+//! appropriate loop, so that a bot's `main` can be as short as:
+//!
+//! ```ignore
+//! fn main() {
+//!     let mut bot = MyBot::new();
+//!     gtprust::launcher::launch(&mut bot);
+//! }
+//! ```
+//!
+//! If the bot needs its options before being created (for pondering,
+//! or its own settings from the config file), use `parse_args` then `run`.
+//!
+//! Recognised flags:
+//!
+//! * `-t, --transport` : `stdio` (default) or `ws:ADDRESS:PORT`
+//! * `-l, --transcript FILE` : record a transcript of the session,
+//!   batch mode included
+//! * `-v, --verbose` : echo commands and responses on stderr, and show
+//!   messages from `diagnostics::info`; repeat it for `diagnostics::debug`
+//! * `-c, --config FILE` : read options from FILE
+//! * `-a, --alias NAME=COMMAND` : make NAME an alias of COMMAND, can be repeated
+//! * `-n, --name NAME` : override the name reported to the controller
+//! * `-p, --ponder` : allow the bot to think during the opponent's time:
+//!   after a `genmove`, `GoBot::gtp_analyze` runs for the opponent until
+//!   the next command, if the bot supports it
+//! * `-b, --batch FILE` : run the GTP commands of FILE instead of reading
+//!   the transport, the exit status is 1 if any of them failed
+//! * `-s, --stop-on-failure` : in batch mode, stop at the first failure
+//!
+//! The config file contains one `key = value` per line, with the long
+//! names of the flags as keys, and `#` starting comments. Command-line
+//! flags take precedence over it. Keys the launcher does not know are
+//! left to the bot in `Options::settings`.
+
+use std::collections::HashMap;
+use std::io::{BufferedReader, File};
+use std::io::stdio;
+use std::os;
+use getopts;
+use api;
+use batch;
+use bothandler;
+use diagnostics;
+use transcript;
+use websocket;
+
+/// How the bot talks to its controller.
+#[deriving(PartialEq, Show)]
+pub enum Transport {
+    /// GTP over stdin / stdout.
+    Stdio,
+    /// GTP over WebSocket, listening on address and port.
+    WebSocket(String, u16)
+}
+
+/// The standard engine options.
+pub struct Options {
+    pub transport: Transport,
+    pub transcript: Option<Path>,
+    pub verbosity: uint,
+    /// Pairs of (alias, command).
+    pub aliases: Vec<(String, String)>,
+    pub name: Option<String>,
+    pub ponder: bool,
+    /// Script to run in batch mode.
+    pub batch: Option<Path>,
+    pub stop_on_failure: bool,
+    /// Config file entries unknown to the launcher.
+    pub settings: HashMap<String, String>
+}
+
+impl Options {
+    /// Default options: stdio, no transcript, silent.
+    pub fn new() -> Options {
+        Options {
+            transport: Stdio,
+            transcript: None,
+            verbosity: 0,
+            aliases: Vec::new(),
+            name: None,
+            ponder: false,
+            batch: None,
+            stop_on_failure: false,
+            settings: HashMap::new()
+        }
+    }
+
+    // sets an option from its long name
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "transport" => self.transport = try!(parse_transport(value)),
+            "transcript" => self.transcript = Some(Path::new(value)),
+            "verbose" => self.verbosity = match from_str::<uint>(value) {
+                Some(v) => v,
+                None => return Err(format!("invalid verbosity: {}", value))
+            },
+            "alias" => self.aliases.push(try!(parse_alias(value))),
+            "name" => self.name = Some(String::from_str(value)),
+            "ponder" => self.ponder = match value {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                _ => return Err(format!("invalid value for ponder: {}", value))
+            },
+            "batch" => self.batch = Some(Path::new(value)),
+            "stop-on-failure" => self.stop_on_failure = match value {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                _ => return Err(format!("invalid value for stop-on-failure: {}", value))
+            },
+            _ => { self.settings.insert(String::from_str(key), String::from_str(value)); }
+        }
+        Ok(())
+    }
+
+    fn load_config(&mut self, path: &Path) -> Result<(), String> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("cannot open {}: {}", path.display(), e))
+        };
+        let mut reader = BufferedReader::new(file);
+        for (n, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+            };
+            let content = line.as_slice().splitn('#', 1).next().unwrap().trim();
+            if content.is_empty() {
+                continue;
+            }
+            let mut parts = content.splitn('=', 1);
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => try!(self.set(key.trim(), value.trim())),
+                _ => return Err(format!("{}:{}: expected `key = value`", path.display(), n + 1))
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_transport(text: &str) -> Result<Transport, String> {
+    if text == "stdio" {
+        return Ok(Stdio);
+    }
+    if text.starts_with("ws:") {
+        let address = text.slice_from(3);
+        match address.rfind(':') {
+            Some(i) => match from_str::<u16>(address.slice_from(i + 1)) {
+                Some(port) => return Ok(WebSocket(String::from_str(address.slice_to(i)), port)),
+                None => {}
+            },
+            None => {}
+        }
+    }
+    Err(format!("invalid transport: {}, expected stdio or ws:ADDRESS:PORT", text))
+}
+
+fn parse_alias(text: &str) -> Result<(String, String), String> {
+    let mut parts = text.splitn('=', 1);
+    match (parts.next(), parts.next()) {
+        (Some(alias), Some(cmd)) if text.is_ascii() && alias.trim().len() > 0 && cmd.trim().len() > 0 =>
+            Ok((String::from_str(alias.trim()), String::from_str(cmd.trim()))),
+        _ => Err(format!("invalid alias: {}, expected NAME=COMMAND", text))
+    }
+}
+
+fn option_groups() -> Vec<getopts::OptGroup> {
+    vec!(
+        getopts::optopt("t", "transport", "stdio (default) or ws:ADDRESS:PORT", "TRANSPORT"),
+        getopts::optopt("l", "transcript", "record a transcript of the session in FILE", "FILE"),
+        getopts::optflagmulti("v", "verbose", "echo commands and responses on stderr"),
+        getopts::optopt("c", "config", "read options from FILE", "FILE"),
+        getopts::optmulti("a", "alias", "make NAME an alias of COMMAND", "NAME=COMMAND"),
+        getopts::optopt("n", "name", "override the name reported to the controller", "NAME"),
+        getopts::optflag("p", "ponder", "think during the opponent's time"),
+        getopts::optopt("b", "batch", "run the GTP commands of FILE and exit", "FILE"),
+        getopts::optflag("s", "stop-on-failure", "in batch mode, stop at the first failure"),
+        getopts::optflag("h", "help", "print this help")
+    )
+}
+
+/// Parses options from a list of arguments, without the program name.
+/// Returns `Ok(None)` if help was requested.
+pub fn parse_options(args: &[String]) -> Result<Option<Options>, String> {
+    let groups = option_groups();
+    let matches = match getopts::getopts(args, groups.as_slice()) {
+        Ok(m) => m,
+        Err(f) => return Err(format!("{}", f))
+    };
+    if matches.opt_present("h") {
+        return Ok(None);
+    }
+    let mut options = Options::new();
+    match matches.opt_str("c") {
+        Some(path) => try!(options.load_config(&Path::new(path.as_slice()))),
+        None => {}
+    }
+    match matches.opt_str("t") {
+        Some(t) => try!(options.set("transport", t.as_slice())),
+        None => {}
+    }
+    match matches.opt_str("l") {
+        Some(l) => try!(options.set("transcript", l.as_slice())),
+        None => {}
+    }
+    match matches.opt_str("n") {
+        Some(n) => try!(options.set("name", n.as_slice())),
+        None => {}
+    }
+    for alias in matches.opt_strs("a").iter() {
+        try!(options.set("alias", alias.as_slice()));
+    }
+    if matches.opt_count("v") > 0 {
+        options.verbosity = matches.opt_count("v");
+    }
+    if matches.opt_present("p") {
+        options.ponder = true;
+    }
+    match matches.opt_str("b") {
+        Some(b) => try!(options.set("batch", b.as_slice())),
+        None => {}
+    }
+    if matches.opt_present("s") {
+        options.stop_on_failure = true;
+    }
+    Ok(Some(options))
+}
+
+/// Parses the options of the process.
+/// Prints the usage and exits the process on `--help` or invalid options.
+pub fn parse_args() -> Options {
+    let args = os::args();
+    let brief = format!("Usage: {} [options]", args[0]);
+    match parse_options(args.tail()) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", getopts::usage(brief.as_slice(), option_groups().as_slice()));
+            unsafe { ::libc::exit(0) }
+        },
+        Err(msg) => {
+            let mut err = ::std::io::stdio::stderr();
+            let _ = err.write_line(msg.as_slice());
+            let _ = err.write_line(getopts::usage(brief.as_slice(), option_groups().as_slice()).as_slice());
+            unsafe { ::libc::exit(2) }
+        }
+    }
+}
+
+// runs a script, the exit status of the process reflects its success
+fn run_batch<T: api::GoBot>(bot: &mut T, handler: &bothandler::BotHandler, path: &Path,
+                            transcript: &mut Option<transcript::Transcript>, stop_on_failure: bool) {
+    let script = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            diagnostics::error(format!("cannot open {}: {}", path.display(), e).as_slice());
+            os::set_exit_status(2);
+            return;
+        }
+    };
+    let mut output = stdio::stdout();
+    match batch::run_with_handler(handler, bot, &mut BufferedReader::new(script), &mut output,
+                                  transcript, stop_on_failure) {
+        Ok(result) => os::set_exit_status(result.exit_status()),
+        Err(e) => {
+            diagnostics::error(format!("cannot run {}: {}", path.display(), e).as_slice());
+            os::set_exit_status(2);
+        }
+    }
+}
+
+/// Runs the bot with the given options, until it receives `quit`,
+/// or until the end of the script in batch mode.
+pub fn run<T: api::GoBot>(bot: &mut T, options: &Options) {
+    let mut handler = bothandler::BotHandler::from_bot(bot);
+    for &(ref alias, ref cmd) in options.aliases.iter() {
+        handler.add_alias(alias.as_slice(), cmd.as_slice());
+    }
+    match options.name {
+        Some(ref n) => handler.set_name(n.as_slice()),
+        None => {}
+    }
+    handler.set_ponder(options.ponder);
+    diagnostics::set_verbosity(options.verbosity);
+    let mut transcript = match options.transcript {
+        Some(ref path) => match transcript::Transcript::create(path) {
+            Ok(t) => Some(t),
+            Err(e) => fail!("Cannot open transcript {}: {}", path.display(), e)
+        },
+        None => transcript::Transcript::from_env()
+    };
+    match options.batch {
+        Some(ref path) => {
+            run_batch(bot, &handler, path, &mut transcript, options.stop_on_failure);
+            return;
+        },
+        None => {}
+    }
+    match options.transport {
+        Stdio => ::stdio_loop(bot, &handler, transcript),
+        WebSocket(ref address, port) => websocket::serve(bot, &handler, transcript, address.as_slice(), port)
+    }
+}
+
+/// Parses the options of the process and runs the bot.
+pub fn launch<T: api::GoBot>(bot: &mut T) {
+    let options = parse_args();
+    run(bot, &options);
+}
+
+#[cfg(test)]
+mod tests {
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| String::from_str(*s)).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = super::parse_options(args(["-t", "ws:127.0.0.1:8080", "-vv",
+                                                 "--alias", "gb=genmove black",
+                                                 "--name", "Foo"]).as_slice()).unwrap().unwrap();
+        assert_eq!(options.transport, super::WebSocket(String::from_str("127.0.0.1"), 8080));
+        assert_eq!(options.verbosity, 2);
+        assert_eq!(options.aliases, vec!((String::from_str("gb"), String::from_str("genmove black"))));
+        assert_eq!(options.name, Some(String::from_str("Foo")));
+        assert!(!options.ponder);
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert!(super::parse_options(args(["-t", "ws:localhost"]).as_slice()).is_err());
+        assert!(super::parse_options(args(["--alias", "foo"]).as_slice()).is_err());
+        assert!(super::parse_options(args(["--help"]).as_slice()).unwrap().is_none());
+    }
+}
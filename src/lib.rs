@@ -1,38 +1,237 @@
 #![crate_type = "lib"]
 
+extern crate getopts;
+extern crate libc;
+extern crate regex;
+extern crate serialize;
+extern crate time;
+
 use std::io;
 
+pub use websocket::websocket_loop;
+pub use batch::{run_script, BatchResult};
+
 pub mod api;
+pub mod book;
+pub mod client;
+pub mod conformance;
+pub mod diagnostics;
+pub mod ensemble;
+pub mod launcher;
+pub mod matchrunner;
+pub mod mock;
+pub mod proxy;
+pub mod regression;
+pub mod replay;
+pub mod responses;
+pub mod selfplay;
+pub mod sgf;
+pub mod sprt;
+pub mod supervisor;
+pub mod tournament;
 mod bothandler;
 mod parsing;
+mod dialects;
 mod boarddrawer;
+mod batch;
+mod websocket;
+mod transcript;
+mod signals;
+#[cfg(test)]
+mod fuzz;
+
+// reads lines in their own task, so that the main loop can also watch
+// for signals, and stop an analysis once the next command comes;
+// end of input is turned into `quit`
+// a new line is only read once the previous one has been acknowledged,
+// the reader stops when the acknowledgement channel is closed
+fn spawn_line_reader<R: Buffer + Send>(input: R) -> (Receiver<String>, Sender<()>) {
+    let (line_tx, line_rx) = channel();
+    let (ack_tx, ack_rx) = channel();
+    spawn(proc() {
+        let mut input = input;
+        loop {
+            let line: String = match parsing::read_line_lossy(&mut input) {
+                Ok(txt) => txt,
+                Err(io::IoError{kind: io::EndOfFile, desc: _, detail: _}) => String::from_str("quit"),
+                Err(_) => fail!("IO error.")
+            };
+            if line_tx.send_opt(line).is_err() || ack_rx.recv_opt().is_err() {
+                break;
+            }
+        }
+    });
+    (line_rx, ack_tx)
+}
 
 /// This function is the mail loop of your bot.
 /// You must provide it a struct implementing the
 /// trait `api::GoBot`, thus providing all the required callbacks.
+///
+/// If the environment variable `GTPRUST_TRANSCRIPT` is set, every
+/// exchange is recorded in the file it names, with timestamps and latencies.
+///
+/// SIGINT and SIGTERM are handled as a `quit` command, so that
+/// `GoBot::on_quit` is always called before the process exits.
 #[allow(dead_code)]
 pub fn main_loop<T: api::GoBot>(bot: &mut T) {
     let handler = bothandler::BotHandler::from_bot(bot);
-    let mut input = io::stdio::stdin();
-    let mut output = io::stdio::stdout();
+    stdio_loop(bot, &handler, transcript::Transcript::from_env());
+}
+
+fn stdio_loop<T: api::GoBot>(bot: &mut T, handler: &bothandler::BotHandler,
+                             transcript: Option<transcript::Transcript>) {
+    signals::install_handlers();
+    let (lines, ack) = spawn_line_reader(io::stdio::stdin());
+    serve_lines(bot, handler, lines, ack, &mut io::stdio::stdout(), transcript);
+    // the reader task may be blocked on stdin, and would keep
+    // the process alive: exit as a process killed by this signal would
+    match signals::termination_requested() {
+        Some(signum) => unsafe { libc::exit(128 + signum as libc::c_int) },
+        None => {}
+    }
+}
+
+// runs the commands coming from a line reader until `quit`,
+// a termination signal being handled as `quit`
+fn serve_lines<T: api::GoBot, W: Writer>(bot: &mut T, handler: &bothandler::BotHandler,
+                                         lines: Receiver<String>, ack: Sender<()>, output: &mut W,
+                                         mut transcript: Option<transcript::Transcript>) {
+    let mut timer = io::Timer::new().unwrap();
+    let ticks = timer.periodic(100);
+    // a line read while an analysis was running
+    let mut pending: Option<String> = None;
     loop {
-        let line: String = match input.read_line() {
-            Ok(txt) => txt,
-            Err(io::IoError{kind: io::EndOfFile, desc: _, detail: _}) => String::from_str("quit"),
-            Err(_) => fail!("IO error.")
+        let line: String = match pending.take() {
+            Some(txt) => txt,
+            None => select! {
+                txt = lines.recv() => txt,
+                () = ticks.recv() => match signals::termination_requested() {
+                    Some(_) => String::from_str("quit"),
+                    None => continue
+                }
+            }
         };
-        // convert line to ascii slice
-        let ascii_input: Vec<Ascii> = match line.as_slice().to_ascii_opt() {
-            Some(txt) => Vec::from_slice(txt),
-            None => vec!('#'.to_ascii())
-        };
-        let (continue_loop, result) = handler.handle_command(bot, ascii_input.as_slice());
-        match output.write(result.append("\n\n").as_bytes()) {
+        let start = time::precise_time_ns();
+        // a streamed response is written as it comes: the next line is
+        // read meanwhile, and stops the analysis
+        let mut header: Option<String> = None;
+        let (continue_loop, result) = handler.handle_command_streaming(bot, line.as_slice(), |info| {
+            if header.is_none() {
+                header = Some(String::from_str(info));
+                ack.send(());
+            }
+            if pending.is_none() {
+                pending = lines.try_recv().ok();
+            }
+            output.write_line(info).is_ok() && pending.is_none() && signals::termination_requested().is_none()
+        });
+        let streamed = header.is_some();
+        match transcript {
+            Some(ref mut t) => {
+                let response = header.unwrap_or(result.clone());
+                let _ = t.record(line.as_slice(), response.as_slice(), time::precise_time_ns() - start);
+            },
+            None => {}
+        }
+        let end = if streamed { String::from_str("\n") } else { result.append("\n\n") };
+        match output.write(end.as_bytes()) {
             Err(_) => fail!("IO error."),
             _ => {}
         }
         if !continue_loop {
             break;
         }
+        if !streamed {
+            ack.send(());
+        }
+        // ponders until the next line
+        if pending.is_none() {
+            handler.ponder(bot, |_| {
+                if pending.is_none() {
+                    pending = lines.try_recv().ok();
+                }
+                pending.is_none() && signals::termination_requested().is_none()
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufferedReader, ChanReader, ChanWriter};
+    use std::io::timer;
+    use api;
+    use bothandler;
+    use client;
+
+    // analyses until it is interrupted, telling for which colour
+    struct Analyst {
+        analyses: Sender<api::Colour>
+    }
+
+    impl api::GoBot for Analyst {
+        fn gtp_name(&self) -> String { String::from_str("Analyst") }
+        fn gtp_version(&self) -> String { String::from_str("1.0") }
+        fn gtp_clear_board(&mut self) {}
+        fn gtp_komi(&mut self, _: f32) {}
+        fn gtp_boardsize(&mut self, _: uint) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_play(&mut self, _: api::ColouredMove) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_genmove(&mut self, _: api::Colour) -> api::Move { api::Pass }
+        fn gtp_analyze(&mut self, player: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+            let _ = self.analyses.send_opt(player);
+            let mut visits = 0u;
+            loop {
+                visits += 1;
+                if !report(format!("info move D4 visits {}", visits).as_slice()) {
+                    return Ok(());
+                }
+                timer::sleep(1);
+            }
+        }
+        fn can_analyze(&self) -> bool { true }
+    }
+
+    // serves an Analyst in its own task
+    fn start(ponder: bool) -> (client::Client<BufferedReader<ChanReader>, ChanWriter>, Receiver<api::Colour>) {
+        let (command_tx, command_rx) = channel();
+        let (response_tx, response_rx) = channel();
+        let (analyses_tx, analyses_rx) = channel();
+        spawn(proc() {
+            let mut bot = Analyst{ analyses: analyses_tx };
+            let mut handler = bothandler::BotHandler::from_bot(&mut bot);
+            handler.set_ponder(ponder);
+            let (lines, ack) = super::spawn_line_reader(BufferedReader::new(ChanReader::new(command_rx)));
+            super::serve_lines(&mut bot, &handler, lines, ack, &mut ChanWriter::new(response_tx), None);
+        });
+        let c = client::Client::new(BufferedReader::new(ChanReader::new(response_rx)),
+                                    ChanWriter::new(command_tx));
+        (c, analyses_rx)
+    }
+
+    #[test]
+    fn streamed_analysis() {
+        let (mut c, _analyses) = start(false);
+        let mut lines = Vec::new();
+        let response = c.send_streaming("lz-analyze black 10", |line| {
+            lines.push(String::from_str(line));
+            lines.len() < 3
+        }).unwrap();
+        assert_eq!(response, client::Response{ id: Some(1), success: true, text: String::new() });
+        assert_eq!(lines, vec!(String::from_str("info move D4 visits 1"),
+                               String::from_str("info move D4 visits 2"),
+                               String::from_str("info move D4 visits 3")));
+        // the analysis stopped on the interrupting command
+        assert_eq!(c.name().unwrap().as_slice(), "Analyst");
+        assert!(c.quit().is_ok());
+    }
+    #[test]
+    fn ponder() {
+        let (mut c, analyses) = start(true);
+        assert_eq!(c.genmove(api::Black).unwrap(), api::Pass);
+        // the bot ponders for white, until the next command
+        assert_eq!(analyses.recv(), api::White);
+        assert_eq!(c.name().unwrap().as_slice(), "Analyst");
+        assert!(c.quit().is_ok());
     }
 }
@@ -0,0 +1,800 @@
+//! Plays games between two GTP engines, as GoGui's twogtp does.
+//!
+//! Engines are driven through `client::Client`: each game is set up
+//! with `boardsize`, `clear_board`, `komi`, the handicap and the time
+//! settings, then `genmove` answers of one engine are sent to the other
+//! with `play`. A game ends on two consecutive passes (it is then scored
+//! with `final_score`), a resignation, a time loss, a move refused by
+//! the opponent, or the move limit. Each game is kept as SGF.
+//!
+//! An optional referee engine receives every move before the opponent.
+//! A move it refuses loses the game, and at the end of the game its
+//! `final_score` decides the result, instead of the agreement of both
+//! players.
+//!
+//! Games can be adjudicated early: every few moves, the lead is
+//! estimated by the referee, or by both players who then have to agree.
+//! A game ends when the same player leads by more than a threshold for
+//! a number of consecutive checks, or as a draw when the lead stays
+//! small late in the game. The reason is given in the SGF comments.
+
+use std::io;
+use std::io::{File, BufferedReader, Buffer, Writer};
+use std::io::pipe::PipeStream;
+use getopts;
+use time;
+use api;
+use client::{Client, ClientResult, IoFailure, CommandFailed, MalformedResponse};
+use responses;
+use sgf::SgfGame;
+
+/// The settings of a match.
+#[deriving(Clone)]
+pub struct Settings {
+    pub boardsize: uint,
+    pub komi: f32,
+    /// Number of handicap stones, on the standard points.
+    pub handicap: uint,
+    /// Main time, byo-yomi time and stones, in seconds.
+    pub time_settings: Option<(uint, uint, uint)>,
+    /// Games reaching this number of moves are void.
+    pub max_moves: uint,
+    pub games: uint,
+    /// Engines swap colours after each game.
+    pub alternate: bool,
+    /// Directory where the games and the results table are written.
+    pub output: Option<Path>,
+    /// Early adjudication of the games, none by default.
+    pub adjudication: Option<Adjudication>
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings{ boardsize: 19, komi: 7.5, handicap: 0, time_settings: None, max_moves: 1000,
+                  games: 1, alternate: true, output: None, adjudication: None }
+    }
+}
+
+/// How the lead is estimated for adjudication.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Estimate {
+    /// `final_score` in the current position.
+    FinalScore,
+    /// A command answering a score as `final_score` does
+    /// (ex: `estimate_score` for GNU Go).
+    ScoreCommand(String),
+    /// The `scoreLead` of the best move of `kata-analyze`, after
+    /// analysing for this number of milliseconds.
+    Analysis(u64)
+}
+
+/// Settings of the early adjudication of games.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Adjudication {
+    pub estimate: Estimate,
+    /// Number of moves between two checks.
+    pub interval: uint,
+    /// Lead in points deciding the game.
+    pub threshold: f32,
+    /// Number of consecutive checks needed to end a game.
+    pub checks: uint,
+    /// Games where the lead stays within this margin are draws...
+    pub draw_margin: Option<f32>,
+    /// ...once this number of moves is reached.
+    pub draw_after: uint
+}
+
+impl Adjudication {
+    /// Checks every 10 moves with `final_score`, ending games at a lead
+    /// of 20 points for 3 checks, without draws.
+    pub fn new() -> Adjudication {
+        Adjudication{ estimate: FinalScore, interval: 10, threshold: 20.0, checks: 3, draw_margin: None,
+                      draw_after: 150 }
+    }
+
+    /// The outcome suggested by the estimated leads of black after
+    /// `moves` moves: `Some(winner)`, `winner` being `None` for a draw,
+    /// or `None` if the game is not decided. All the estimates must
+    /// agree.
+    pub fn outcome(&self, leads: &[Option<f32>], moves: uint) -> Option<Option<api::Colour>> {
+        let mut known = Vec::new();
+        for lead in leads.iter() {
+            match *lead {
+                Some(x) => known.push(x),
+                None => return None
+            }
+        }
+        if known.is_empty() {
+            None
+        } else if known.iter().all(|x| *x > self.threshold) {
+            Some(Some(api::Black))
+        } else if known.iter().all(|x| *x < -self.threshold) {
+            Some(Some(api::White))
+        } else {
+            match self.draw_margin {
+                Some(margin) if moves >= self.draw_after && known.iter().all(|x| x.abs() <= margin) => Some(None),
+                _ => None
+            }
+        }
+    }
+}
+
+/// Options setting up the games, shared by the binaries playing
+/// matches: size, komi, handicap, time, max-moves and output.
+pub fn option_groups() -> Vec<getopts::OptGroup> {
+    vec!(
+        getopts::optopt("s", "size", "board size (default 19)", "SIZE"),
+        getopts::optopt("k", "komi", "komi (default 7.5)", "KOMI"),
+        getopts::optopt("H", "handicap", "number of fixed handicap stones", "N"),
+        getopts::optopt("t", "time", "time settings in seconds, main time with optional byo-yomi",
+                        "MAIN[:BYOYOMI:STONES]"),
+        getopts::optopt("m", "max-moves", "games reaching this number of moves are void (default 1000)", "N"),
+        getopts::optopt("o", "output", "directory for the SGF files and the results", "DIR"),
+        getopts::optopt("", "adjudicate", "every N moves, check the lead, ending games when it stays over POINTS \
+                                          for CHECKS checks", "N:POINTS:CHECKS"),
+        getopts::optopt("", "adjudicate-draw", "adjudicate draws when the lead stays within POINTS after MOVES moves",
+                        "POINTS:MOVES"),
+        getopts::optopt("", "estimate", "how the lead is estimated: final_score (default), analysis:MS for \
+                                        kata-analyze, or a command answering a score", "METHOD")
+    )
+}
+
+/// The value of a numerical option, `default` if it is absent.
+pub fn number_opt<T: FromStr>(matches: &getopts::Matches, name: &str, default: T) -> Result<T, String> {
+    match matches.opt_str(name) {
+        Some(text) => match from_str::<T>(text.as_slice()) {
+            Some(value) => Ok(value),
+            None => Err(format!("invalid value for --{}: {}", name, text))
+        },
+        None => Ok(default)
+    }
+}
+
+// parses MAIN[:BYOYOMI:STONES]
+fn parse_time(text: &str) -> Option<(uint, uint, uint)> {
+    let parts: Vec<Option<uint>> = text.split(':').map(|p| from_str::<uint>(p)).collect();
+    match parts.as_slice() {
+        [Some(main), Some(byoyomi), Some(stones)] => Some((main, byoyomi, stones)),
+        [Some(main)] => Some((main, 0, 0)),
+        _ => None
+    }
+}
+
+fn parse_estimate(text: &str) -> Option<Estimate> {
+    match text.splitn(':', 1).collect::<Vec<&str>>().as_slice() {
+        ["final_score"] => Some(FinalScore),
+        ["analysis", ms] => from_str::<u64>(ms).map(|ms| Analysis(ms)),
+        [command] if !command.is_empty() => Some(ScoreCommand(String::from_str(command))),
+        _ => None
+    }
+}
+
+fn parse_adjudication(matches: &getopts::Matches) -> Result<Option<Adjudication>, String> {
+    let mut adjudication = Adjudication::new();
+    match matches.opt_str("adjudicate") {
+        Some(text) => {
+            let parts: Vec<&str> = text.as_slice().split(':').collect();
+            match parts.as_slice() {
+                [n, points, checks] => match (from_str::<uint>(n), from_str::<f32>(points), from_str::<uint>(checks)) {
+                    (Some(n), Some(points), Some(checks)) if n > 0 && checks > 0 => {
+                        adjudication.interval = n;
+                        adjudication.threshold = points;
+                        adjudication.checks = checks;
+                    },
+                    _ => return Err(format!("invalid value for --adjudicate: {}", text))
+                },
+                _ => return Err(format!("invalid value for --adjudicate: {}", text))
+            }
+        },
+        None if matches.opt_present("adjudicate-draw") || matches.opt_present("estimate") =>
+            return Err(String::from_str("--adjudicate-draw and --estimate need --adjudicate")),
+        None => return Ok(None)
+    }
+    match matches.opt_str("adjudicate-draw") {
+        Some(text) => {
+            let parts: Vec<&str> = text.as_slice().split(':').collect();
+            match parts.as_slice() {
+                [points, moves] => match (from_str::<f32>(points), from_str::<uint>(moves)) {
+                    (Some(points), Some(moves)) => {
+                        adjudication.draw_margin = Some(points);
+                        adjudication.draw_after = moves;
+                    },
+                    _ => return Err(format!("invalid value for --adjudicate-draw: {}", text))
+                },
+                _ => return Err(format!("invalid value for --adjudicate-draw: {}", text))
+            }
+        },
+        None => {}
+    }
+    match matches.opt_str("estimate") {
+        Some(text) => match parse_estimate(text.as_slice()) {
+            Some(e) => adjudication.estimate = e,
+            None => return Err(format!("invalid value for --estimate: {}", text))
+        },
+        None => {}
+    }
+    Ok(Some(adjudication))
+}
+
+/// Reads the options of `option_groups` into settings.
+pub fn parse_settings(matches: &getopts::Matches) -> Result<Settings, String> {
+    let mut settings = Settings::new();
+    settings.boardsize = try!(number_opt(matches, "size", settings.boardsize));
+    settings.komi = try!(number_opt(matches, "komi", settings.komi));
+    settings.handicap = try!(number_opt(matches, "handicap", settings.handicap));
+    settings.max_moves = try!(number_opt(matches, "max-moves", settings.max_moves));
+    match matches.opt_str("time") {
+        Some(text) => match parse_time(text.as_slice()) {
+            Some(t) => settings.time_settings = Some(t),
+            None => return Err(format!("invalid value for --time: {}", text))
+        },
+        None => {}
+    }
+    settings.output = matches.opt_str("output").map(|d| Path::new(d));
+    settings.adjudication = try!(parse_adjudication(matches));
+    Ok(settings)
+}
+
+/// How a game ended.
+#[deriving(PartialEq, Show)]
+pub enum GameResult {
+    /// The winner and its margin, a margin of 0 being a draw.
+    Score(api::Colour, f32),
+    /// The winner, its opponent resigned.
+    Resignation(api::Colour),
+    /// The winner, its opponent ran out of time.
+    Time(api::Colour),
+    /// The winner, its opponent played a move which was refused.
+    Forfeit(api::Colour),
+    /// The winner decided by the estimated lead, `None` for a draw.
+    Adjudicated(Option<api::Colour>),
+    /// No result: move limit, or scores which do not agree.
+    Unknown
+}
+
+fn colour_letter(colour: api::Colour) -> &'static str {
+    match colour {
+        api::Black => "B",
+        api::White => "W"
+    }
+}
+
+impl GameResult {
+    /// The result given by `final_score`.
+    pub fn from_score(score: (f32, api::Colour)) -> GameResult {
+        let (x, c) = score;
+        Score(c, x)
+    }
+
+    pub fn winner(&self) -> Option<api::Colour> {
+        match *self {
+            Score(_, x) if x == 0.0 => None,
+            Score(c, _) | Resignation(c) | Time(c) | Forfeit(c) => Some(c),
+            Adjudicated(c) => c,
+            Unknown => None
+        }
+    }
+
+    /// The result in the SGF notation (ex: `B+3.5`, `W+R`, `Void`),
+    /// adjudicated games being won without margin (ex: `B+`).
+    pub fn to_string(&self) -> String {
+        match *self {
+            Score(_, x) if x == 0.0 => String::from_str("0"),
+            Score(c, x) => format!("{}+{}", colour_letter(c), x),
+            Resignation(c) => format!("{}+R", colour_letter(c)),
+            Time(c) => format!("{}+T", colour_letter(c)),
+            Forfeit(c) => format!("{}+F", colour_letter(c)),
+            Adjudicated(Some(c)) => format!("{}+", colour_letter(c)),
+            Adjudicated(None) => String::from_str("0"),
+            Unknown => String::from_str("Void")
+        }
+    }
+}
+
+/// An engine taking part in a match.
+pub struct Player<R, W> {
+    /// Identifies the engine in the results.
+    pub id: uint,
+    pub name: String,
+    pub client: Client<R, W>
+}
+
+impl<R: Buffer, W: Writer> Player<R, W> {
+    /// Names the engine after its answers to `name` and `version`.
+    pub fn new(id: uint, mut client: Client<R, W>) -> ClientResult<Player<R, W>> {
+        let name = try!(client.name());
+        let version = try!(optional(client.version())).unwrap_or(String::new());
+        Ok(Player{ id: id, name: String::from_str(format!("{} {}", name, version).as_slice().trim()),
+                   client: client })
+    }
+}
+
+/// An engine running as a subprocess.
+pub type EnginePlayer = Player<BufferedReader<PipeStream>, PipeStream>;
+
+impl Player<BufferedReader<PipeStream>, PipeStream> {
+    /// Starts an engine from a command line, split on spaces
+    /// (ex: "gnugo --mode gtp --level 3").
+    pub fn spawn(id: uint, command: &str) -> ClientResult<EnginePlayer> {
+        let words: Vec<String> = command.words().map(|w| String::from_str(w)).collect();
+        if words.is_empty() {
+            return Err(IoFailure(io::standard_error(io::InvalidInput)));
+        }
+        let client = try!(Client::spawn(words[0].as_slice(), words.tail()).map_err(IoFailure));
+        Player::new(id, client)
+    }
+}
+
+/// A finished game.
+pub struct Game {
+    /// Id of the black player.
+    pub black: uint,
+    /// Id of the white player.
+    pub white: uint,
+    pub result: GameResult,
+    /// Details on the result, ex: the refused move.
+    pub reason: Option<String>,
+    pub record: SgfGame,
+    pub time_ns: u64
+}
+
+// failed commands and malformed answers are treated as missing answers,
+// only the loss of the engine is an error
+fn optional<T>(result: ClientResult<T>) -> ClientResult<Option<T>> {
+    match result {
+        Ok(x) => Ok(Some(x)),
+        Err(CommandFailed(_)) | Err(MalformedResponse(_)) => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+// the time left to a player, in seconds
+struct Clock {
+    byoyomi: f64,
+    stones: uint,
+    remaining: f64,
+    in_byoyomi: bool,
+    stones_left: uint
+}
+
+impl Clock {
+    // no clock if there are no time limits: as in GTP, no byo-yomi
+    // stones means no limit, unless there is only main time
+    fn new(settings: Option<(uint, uint, uint)>) -> Option<Clock> {
+        match settings {
+            Some((main, byoyomi, stones)) if stones > 0 || (main > 0 && byoyomi == 0) => Some(Clock{
+                byoyomi: byoyomi as f64,
+                stones: stones,
+                remaining: main as f64,
+                in_byoyomi: false,
+                stones_left: 0
+            }),
+            _ => None
+        }
+    }
+
+    // the arguments of `time_left`
+    fn time_left(&self) -> (uint, uint) {
+        (self.remaining as uint, if self.in_byoyomi { self.stones_left } else { 0 })
+    }
+
+    // returns false if the player ran out of time
+    fn spend(&mut self, seconds: f64) -> bool {
+        let mut seconds = seconds;
+        if !self.in_byoyomi {
+            if seconds <= self.remaining {
+                self.remaining -= seconds;
+                return true;
+            }
+            if self.byoyomi == 0.0 {
+                return false;
+            }
+            seconds -= self.remaining;
+            self.in_byoyomi = true;
+            self.remaining = self.byoyomi;
+            self.stones_left = self.stones;
+        }
+        if seconds > self.remaining {
+            return false;
+        }
+        self.remaining -= seconds;
+        self.stones_left -= 1;
+        if self.stones_left == 0 {
+            self.remaining = self.byoyomi;
+            self.stones_left = self.stones;
+        }
+        true
+    }
+}
+
+fn same_score(a: (f32, api::Colour), b: (f32, api::Colour)) -> bool {
+    a.val0() == b.val0() && (a.val0() == 0.0 || a.val1() == b.val1())
+}
+
+// the referee decides, the dead stones it sees are given as reason
+fn referee_score<R: Buffer, W: Writer>(referee: &mut Player<R, W>)
+                                      -> ClientResult<(GameResult, Option<String>)> {
+    let dead = match try!(optional(referee.client.final_status_list(api::Dead))) {
+        Some(ref stones) if stones.len() > 0 => Some(format!("dead stones: {}",
+                                                             responses::format_vertex_list(stones.as_slice()))),
+        _ => None
+    };
+    Ok(match try!(optional(referee.client.final_score())) {
+        Some(s) => (GameResult::from_score(s), dead),
+        None => (Unknown, Some(String::from_str("the referee could not score the game")))
+    })
+}
+
+// asks both engines for the score, they have to agree
+fn score<R: Buffer, W: Writer>(black: &mut Player<R, W>, white: &mut Player<R, W>)
+                              -> ClientResult<(GameResult, Option<String>)> {
+    let black_score = try!(optional(black.client.final_score()));
+    let white_score = try!(optional(white.client.final_score()));
+    Ok(match (black_score, white_score) {
+        (Some(a), Some(b)) if !same_score(a, b) => (Unknown, Some(format!("scores differ: {} by {}, {} by {}",
+            GameResult::from_score(a).to_string(), black.name,
+            GameResult::from_score(b).to_string(), white.name))),
+        (Some(a), _) | (None, Some(a)) => (GameResult::from_score(a), None),
+        (None, None) => (Unknown, Some(String::from_str("no engine could score the game")))
+    })
+}
+
+// the lead of black, negative if white leads
+fn signed_lead(score: (f32, api::Colour)) -> f32 {
+    match score {
+        (x, api::Black) => x,
+        (x, api::White) => -x
+    }
+}
+
+fn format_lead(lead: f32) -> String {
+    if lead < 0.0 { format!("W+{}", -lead) } else { format!("B+{}", lead) }
+}
+
+// the lead of black estimated by an engine, `to_play` being the
+// player to move
+fn estimate_lead<R: Buffer, W: Writer>(engine: &mut Player<R, W>, estimate: &Estimate, to_play: api::Colour)
+                                      -> ClientResult<Option<f32>> {
+    match *estimate {
+        FinalScore => Ok(try!(optional(engine.client.final_score())).map(|s| signed_lead(s))),
+        ScoreCommand(ref command) => Ok(try!(optional(engine.client.command(command.as_slice()))).and_then(|text| {
+            responses::parse_score(text.as_slice().words().next().unwrap_or("")).map(|s| signed_lead(s))
+        })),
+        Analysis(ms) => {
+            let line = format!("kata-analyze {} 50", to_play.to_string());
+            let deadline = time::precise_time_ns() + ms * 1000000;
+            let mut latest = Vec::new();
+            let response = try!(engine.client.send_streaming(line.as_slice(), |info| {
+                match responses::parse_analysis(info) {
+                    Some(infos) => latest = infos,
+                    None => {}
+                }
+                time::precise_time_ns() < deadline
+            }));
+            if !response.success {
+                return Ok(None);
+            }
+            // the lead is given for the player to move
+            let best = latest.iter().find(|i| i.order == Some(0)).or(latest.as_slice().get(0));
+            Ok(best.and_then(|i| i.score_lead).map(|lead| signed_lead((lead, to_play))))
+        }
+    }
+}
+
+// the leads estimated by the referee, or by both players
+fn estimate_leads<R: Buffer, W: Writer>(black: &mut Player<R, W>, white: &mut Player<R, W>,
+                                        referee: &mut Option<&mut Player<R, W>>, estimate: &Estimate,
+                                        to_play: api::Colour) -> ClientResult<Vec<Option<f32>>> {
+    match *referee {
+        Some(ref mut r) => Ok(vec!(try!(estimate_lead(&mut **r, estimate, to_play)))),
+        None => {
+            let black_lead = try!(estimate_lead(black, estimate, to_play));
+            let white_lead = try!(estimate_lead(white, estimate, to_play));
+            Ok(vec!(black_lead, white_lead))
+        }
+    }
+}
+
+fn setup<R: Buffer, W: Writer>(player: &mut Player<R, W>, settings: &Settings) -> ClientResult<()> {
+    try!(player.client.boardsize(settings.boardsize));
+    try!(player.client.clear_board());
+    try!(player.client.komi(settings.komi));
+    match settings.time_settings {
+        Some((main, byoyomi, stones)) => player.client.time_settings(main, byoyomi, stones),
+        None => Ok(())
+    }
+}
+
+// alternates genmove and play until the end of the game
+fn play_moves<R: Buffer, W: Writer>(black: &mut Player<R, W>, white: &mut Player<R, W>,
+                                    referee: &mut Option<&mut Player<R, W>>,
+                                    settings: &Settings, record: &mut SgfGame)
+                                    -> ClientResult<(GameResult, Option<String>)> {
+    let mut clocks = [Clock::new(settings.time_settings), Clock::new(settings.time_settings)];
+    let mut to_play = if settings.handicap > 0 { api::White } else { api::Black };
+    let mut last_was_pass = false;
+    // the outcome of the last adjudication checks, and their number
+    let mut adjudged = None;
+    let mut streak = 0u;
+    loop {
+        if record.moves.len() >= settings.max_moves {
+            return Ok((Unknown, Some(String::from_str("move limit reached"))));
+        }
+        let opponent = match to_play { api::Black => api::White, api::White => api::Black };
+        let move = {
+            let (mover, other, clock) = match to_play {
+                api::Black => (&mut *black, &mut *white, &mut clocks[0]),
+                api::White => (&mut *white, &mut *black, &mut clocks[1])
+            };
+            match *clock {
+                Some(ref c) => {
+                    let (seconds, stones) = c.time_left();
+                    try!(optional(mover.client.time_left(to_play, seconds, stones)));
+                },
+                None => {}
+            }
+            let move_start = time::precise_time_ns();
+            let move = match mover.client.genmove(to_play) {
+                Ok(m) => m,
+                Err(MalformedResponse(text)) => {
+                    return Ok((Forfeit(opponent), Some(format!("invalid move {}", text))));
+                },
+                Err(e) => return Err(e)
+            };
+            let elapsed = (time::precise_time_ns() - move_start) as f64 / 1e9;
+            let in_time = match *clock {
+                Some(ref mut c) => c.spend(elapsed),
+                None => true
+            };
+            if !in_time {
+                return Ok((Time(opponent), None));
+            }
+            let coloured = api::ColouredMove{ player: to_play, move: move };
+            record.push(coloured, None);
+            if move == api::Resign {
+                return Ok((Resignation(opponent), None));
+            }
+            match *referee {
+                Some(ref mut r) => match r.client.play(coloured) {
+                    Ok(()) => {},
+                    Err(CommandFailed(text)) => {
+                        return Ok((Forfeit(opponent), Some(format!("illegal move {}: {}", move.to_string(), text))));
+                    },
+                    Err(e) => return Err(e)
+                },
+                None => {}
+            }
+            match other.client.play(coloured) {
+                Ok(()) => {},
+                Err(CommandFailed(text)) => {
+                    // if the referee accepted the move, the opponent is wrong
+                    let winner = if referee.is_some() { to_play } else { opponent };
+                    return Ok((Forfeit(winner), Some(format!("{} refused {}: {}",
+                                                             other.name, move.to_string(), text))));
+                },
+                Err(e) => return Err(e)
+            }
+            move
+        };
+        if move == api::Pass && last_was_pass {
+            return match *referee {
+                Some(ref mut r) => referee_score(&mut **r),
+                None => score(black, white)
+            };
+        }
+        last_was_pass = move == api::Pass;
+        to_play = opponent;
+        match settings.adjudication {
+            Some(ref a) if a.interval > 0 && record.moves.len() % a.interval == 0 => {
+                let leads = try!(estimate_leads(&mut *black, &mut *white, referee, &a.estimate, to_play));
+                let outcome = a.outcome(leads.as_slice(), record.moves.len());
+                streak = if outcome.is_some() && outcome == adjudged { streak + 1 } else { 1 };
+                adjudged = outcome;
+                match adjudged {
+                    Some(winner) if streak >= a.checks => {
+                        let estimates: Vec<String> = leads.iter().map(|l| format_lead(l.unwrap())).collect();
+                        let reason = match winner {
+                            Some(c) => format!("adjudicated after {} moves: {} leads by more than {} points in {} \
+                                                consecutive checks ({})", record.moves.len(), c.to_string(),
+                                               a.threshold, streak, estimates.connect(", ")),
+                            None => format!("adjudicated as a draw after {} moves: the lead stays within {} points \
+                                             in {} consecutive checks ({})", record.moves.len(),
+                                            a.draw_margin.unwrap(), streak, estimates.connect(", "))
+                        };
+                        let last = record.moves.len() - 1;
+                        record.moves.get_mut(last).comment = Some(reason.clone());
+                        return Ok((Adjudicated(winner), Some(reason)));
+                    },
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Plays a game, `black` taking black, under the control of `referee` if any.
+pub fn play_game<R: Buffer, W: Writer>(black: &mut Player<R, W>, white: &mut Player<R, W>,
+                                       mut referee: Option<&mut Player<R, W>>,
+                                       settings: &Settings) -> ClientResult<Game> {
+    let start = time::precise_time_ns();
+    let mut record = SgfGame::new(settings.boardsize, settings.komi);
+    record.black_name = Some(black.name.clone());
+    record.white_name = Some(white.name.clone());
+    try!(setup(black, settings));
+    try!(setup(white, settings));
+    match referee {
+        Some(ref mut r) => try!(setup(&mut **r, settings)),
+        None => {}
+    }
+    if settings.handicap > 0 {
+        record.handicap = try!(black.client.fixed_handicap(settings.handicap));
+        try!(white.client.set_free_handicap(record.handicap.as_slice()));
+        match referee {
+            Some(ref mut r) => try!(r.client.set_free_handicap(record.handicap.as_slice())),
+            None => {}
+        }
+    }
+    let (result, reason) = try!(play_moves(black, white, &mut referee, settings, &mut record));
+    record.result = Some(result.to_string());
+    record.comment = reason.clone();
+    Ok(Game{ black: black.id, white: white.id, result: result, reason: reason, record: record,
+             time_ns: time::precise_time_ns() - start })
+}
+
+/// Number of games won by the player `id`.
+pub fn wins(games: &[Game], id: uint) -> uint {
+    games.iter().filter(|g| match g.result.winner() {
+        Some(api::Black) => g.black == id,
+        Some(api::White) => g.white == id,
+        None => false
+    }).count()
+}
+
+/// A row of the results table.
+pub fn format_row(number: uint, game: &Game) -> String {
+    format!("{:4u} {:20s} {:20s} {:8s} {:5u} {:8.1f} {}", number,
+            game.record.black_name.clone().unwrap_or(String::new()),
+            game.record.white_name.clone().unwrap_or(String::new()),
+            game.result.to_string(), game.record.moves.len(), game.time_ns as f64 / 1e9,
+            game.reason.clone().unwrap_or(String::new()))
+}
+
+/// The header of the results table.
+pub fn table_header() -> String {
+    format!("{:4s} {:20s} {:20s} {:8s} {:5s} {:8s} {}", "GAME", "BLACK", "WHITE", "RESULT", "MOVES", "TIME", "REASON")
+}
+
+fn write_file(path: &Path, content: &str) -> ClientResult<()> {
+    File::create(path).write_str(content).map_err(IoFailure)
+}
+
+/// Plays the games of a match, `first` taking black in the first game,
+/// under the control of `referee` if any.
+/// `report` is called after each game, with its number.
+/// Games are written to `game-NNN.sgf` and the table to `results.txt`
+/// in the output directory, if any.
+pub fn run_match<R: Buffer, W: Writer>(first: &mut Player<R, W>, second: &mut Player<R, W>,
+                                       mut referee: Option<&mut Player<R, W>>,
+                                       settings: &Settings, report: |uint, &Game|) -> ClientResult<Vec<Game>> {
+    let mut games = Vec::new();
+    let mut table = table_header().append("\n");
+    for i in range(0, settings.games) {
+        let referee = match referee {
+            Some(ref mut r) => Some(&mut **r),
+            None => None
+        };
+        let mut game = if settings.alternate && i % 2 == 1 {
+            try!(play_game(second, first, referee, settings))
+        } else {
+            try!(play_game(first, second, referee, settings))
+        };
+        game.record.date = Some(time::strftime("%Y-%m-%d", &time::now()));
+        table.push_str(format_row(i + 1, &game).append("\n").as_slice());
+        match settings.output {
+            Some(ref dir) => {
+                let name = format!("game-{:03u}.sgf", i + 1);
+                try!(write_file(&dir.join(name.as_slice()), game.record.to_sgf().as_slice()));
+                try!(write_file(&dir.join("results.txt"), table.as_slice()));
+            },
+            None => {}
+        }
+        report(i + 1, &game);
+        games.push(game);
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, MemWriter};
+    use api;
+    use client::Client;
+
+    fn player<'a>(id: uint, responses: &'a str) -> super::Player<BufReader<'a>, MemWriter> {
+        super::Player{ id: id, name: format!("engine{}", id),
+                       client: Client::new(BufReader::new(responses.as_bytes()), MemWriter::new()) }
+    }
+
+    #[test]
+    fn play_game() {
+        let mut black = player(0, "=1\n\n=2\n\n=3\n\n=4 D4\n\n=5\n\n=6 pass\n\n=7\n\n=8 W+0.5\n\n");
+        let mut white = player(1, "=1\n\n=2\n\n=3\n\n=4\n\n=5 E5\n\n=6\n\n=7 PASS\n\n=8 W+0.5\n\n");
+        let mut settings = super::Settings::new();
+        settings.boardsize = 9;
+        let game = super::play_game(&mut black, &mut white, None, &settings).unwrap();
+        assert_eq!(game.result, super::Score(api::White, 0.5));
+        assert_eq!(game.record.moves.len(), 4);
+        assert_eq!(super::wins([game].as_slice(), 1), 1);
+    }
+
+    #[test]
+    fn refused_move() {
+        let mut black = player(0, "=1\n\n=2\n\n=3\n\n=4 D4\n\n");
+        let mut white = player(1, "=1\n\n=2\n\n=3\n\n?4 illegal move\n\n");
+        let game = super::play_game(&mut black, &mut white, None, &super::Settings::new()).unwrap();
+        assert_eq!(game.result, super::Forfeit(api::White));
+        assert_eq!(game.record.result, Some(String::from_str("W+F")));
+    }
+
+    #[test]
+    fn referee() {
+        // the referee refuses the second move
+        let mut black = player(0, "=1\n\n=2\n\n=3\n\n=4 D4\n\n");
+        let mut white = player(1, "=1\n\n=2\n\n=3\n\n=4\n\n=5 D4\n\n");
+        let mut referee = player(2, "=1\n\n=2\n\n=3\n\n=4\n\n?5 illegal move\n\n");
+        let game = super::play_game(&mut black, &mut white, Some(&mut referee), &super::Settings::new()).unwrap();
+        assert_eq!(game.result, super::Forfeit(api::Black));
+        // the referee scores the game, whatever the players think
+        let mut black = player(0, "=1\n\n=2\n\n=3\n\n=4 pass\n\n=5\n\n=6 B+10\n\n");
+        let mut white = player(1, "=1\n\n=2\n\n=3\n\n=4\n\n=5 pass\n\n=6 B+10\n\n");
+        let mut referee = player(2, "=1\n\n=2\n\n=3\n\n=4\n\n=5\n\n=6 C3 D4\n\n=7 W+2.5\n\n");
+        let game = super::play_game(&mut black, &mut white, Some(&mut referee), &super::Settings::new()).unwrap();
+        assert_eq!(game.result, super::Score(api::White, 2.5));
+        assert_eq!(game.reason, Some(String::from_str("dead stones: C3 D4")));
+    }
+
+    #[test]
+    fn adjudication() {
+        let mut adjudication = super::Adjudication::new();
+        adjudication.draw_margin = Some(2.0);
+        assert_eq!(adjudication.outcome([Some(25.0), Some(21.5)], 10), Some(Some(api::Black)));
+        assert_eq!(adjudication.outcome([Some(-30.0)], 10), Some(Some(api::White)));
+        assert_eq!(adjudication.outcome([Some(25.0), Some(-21.5)], 10), None);
+        assert_eq!(adjudication.outcome([Some(25.0), None], 10), None);
+        assert_eq!(adjudication.outcome([Some(1.5), Some(-0.5)], 10), None);
+        assert_eq!(adjudication.outcome([Some(1.5), Some(-0.5)], 200), Some(None));
+        // two checks every two moves, both players agreeing
+        let mut black = player(0, "=1\n\n=2\n\n=3\n\n=4 D4\n\n=5\n\n=6 B+15\n\n=7 C3\n\n=8\n\n=9 B+20\n\n");
+        let mut white = player(1, "=1\n\n=2\n\n=3\n\n=4\n\n=5 E5\n\n=6 B+12\n\n=7\n\n=8 G7\n\n=9 B+11\n\n");
+        let mut settings = super::Settings::new();
+        adjudication.interval = 2;
+        adjudication.threshold = 10.0;
+        adjudication.checks = 2;
+        settings.adjudication = Some(adjudication);
+        let game = super::play_game(&mut black, &mut white, None, &settings).unwrap();
+        assert_eq!(game.result, super::Adjudicated(Some(api::Black)));
+        assert_eq!(game.record.result, Some(String::from_str("B+")));
+        assert_eq!(game.record.moves.len(), 4);
+        assert_eq!(game.record.moves[3].comment, game.reason);
+    }
+
+    #[test]
+    fn clock() {
+        let mut clock = super::Clock::new(Some((10, 5, 2))).unwrap();
+        assert!(clock.spend(8.0));
+        assert_eq!(clock.time_left(), (2, 0));
+        // 3 seconds of byo-yomi for the first stone
+        assert!(clock.spend(5.0));
+        assert_eq!(clock.time_left(), (2, 1));
+        assert!(clock.spend(1.0));
+        assert_eq!(clock.time_left(), (5, 2));
+        assert!(!clock.spend(6.0));
+        assert!(super::Clock::new(Some((0, 10, 0))).is_none());
+        assert!(super::Clock::new(Some((0, 0, 0))).is_none());
+        // absolute time
+        let mut clock = super::Clock::new(Some((10, 0, 0))).unwrap();
+        assert!(clock.spend(8.0));
+        assert!(!clock.spend(3.0));
+    }
+}
@@ -0,0 +1,531 @@
+//! A mock engine answering from a script, to test controllers and
+//! proxies without real engines.
+//!
+//! It can run over a GTP stream (see the binary `gtp-mock`), or be used
+//! in process as a `GoBot`. A script has one rule per line, lines
+//! starting with `#` being comments:
+//!
+//! ```text
+//! # a response
+//! name = Mock
+//! # successive responses, the last one being repeated
+//! genmove = D4
+//! genmove = Q16
+//! # a failure
+//! play ? illegal move
+//! # a line which is not a GTP response
+//! showboard ! garbage
+//! # `\n` is a line break
+//! fixed_handicap = D4 Q16\nQ4
+//! # waits 500 ms before answering genmove, 10 ms before any command
+//! delay genmove 500
+//! delay * 10
+//! # stops after answering 20 commands
+//! crash after 20
+//! ```
+//!
+//! Commands without rules get default answers: `name` is `mock`,
+//! `genmove` passes, `list_commands` and `known_command` cover the
+//! required commands and the scripted ones, the other required commands
+//! succeed silently and unknown commands fail.
+//!
+//! In process, failures are turned into the errors of the `GoBot`
+//! methods, while crashes and malformed responses make the bot `fail!`.
+
+use std::cell::Cell;
+use std::cmp;
+use std::io;
+use std::io::{File, IoError, IoResult};
+use std::io::timer;
+use api;
+use client::{ClientResult, CommandFailed, IoFailure, MalformedResponse, Probing, parse_response, unexpected};
+use parsing;
+use responses;
+
+/// A scripted answer.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Answer {
+    /// A successful response with this text.
+    Success(String),
+    /// A failure with this message.
+    Failure(String),
+    /// A line written instead of a response.
+    Malformed(String)
+}
+
+/// The behaviour of a mock engine, see the module documentation.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Script {
+    answers: Vec<(String, Vec<Answer>)>,
+    delays: Vec<(String, u64)>,
+    crash_after: Option<uint>
+}
+
+// commands always known, answered by default
+static REQUIRED_COMMANDS: &'static [&'static str] = &[
+    "protocol_version", "name", "version", "known_command", "list_commands", "quit",
+    "boardsize", "clear_board", "komi", "play", "genmove"
+];
+
+// commands going to the methods of `GoBot` rather than to
+// `gtp_custom_command`
+static BOT_COMMANDS: &'static [&'static str] = &[
+    "protocol_version", "name", "version", "known_command", "list_commands", "quit",
+    "boardsize", "clear_board", "komi", "play", "genmove", "reg_genmove", "undo",
+    "fixed_handicap", "place_free_handicap", "set_free_handicap", "time_settings",
+    "time_left", "final_status_list", "final_score", "showboard", "lz-analyze"
+];
+
+impl Script {
+    /// A script without rules, every command getting its default answer.
+    pub fn new() -> Script {
+        Script{ answers: Vec::new(), delays: Vec::new(), crash_after: None }
+    }
+
+    /// Parses a script, see the module documentation.
+    pub fn parse(text: &str) -> Result<Script, String> {
+        let mut script = Script::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") {
+                continue;
+            }
+            let words: Vec<&str> = line.words().collect();
+            let parsed = match words.as_slice() {
+                [command, marker, ..] if marker == "=" || marker == "?" || marker == "!" => {
+                    let text = line.slice_from(command.len()).trim_left().slice_from(1).trim()
+                                   .replace("\\n", "\n");
+                    script.answer(command, match marker {
+                        "=" => Success(text),
+                        "?" => Failure(text),
+                        _ => Malformed(text)
+                    });
+                    true
+                },
+                ["delay", command, ms] => match from_str::<u64>(ms) {
+                    Some(ms) => {
+                        script.delay(command, ms);
+                        true
+                    },
+                    None => false
+                },
+                ["crash", "after", n] => match from_str::<uint>(n) {
+                    Some(n) => {
+                        script.crash_after(n);
+                        true
+                    },
+                    None => false
+                },
+                _ => false
+            };
+            if !parsed {
+                return Err(format!("line {}: invalid rule: {}", number + 1, line));
+            }
+        }
+        Ok(script)
+    }
+
+    /// Reads a script from a file.
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let text = try!(File::open(path).read_to_string()
+                        .map_err(|e| format!("cannot read {}: {}", path.display(), e)));
+        Script::parse(text.as_slice()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Adds an answer to `command`, given after the previous ones.
+    pub fn answer(&mut self, command: &str, answer: Answer) {
+        match self.answers.iter().position(|&(ref c, _)| c.as_slice() == command) {
+            Some(i) => match *self.answers.get_mut(i) {
+                (_, ref mut answers) => answers.push(answer)
+            },
+            None => self.answers.push((String::from_str(command), vec!(answer)))
+        }
+    }
+
+    /// Waits `ms` milliseconds before answering `command`,
+    /// `*` standing for all commands.
+    pub fn delay(&mut self, command: &str, ms: u64) {
+        self.delays.push((String::from_str(command), ms));
+    }
+
+    /// Stops after answering `count` commands.
+    pub fn crash_after(&mut self, count: uint) {
+        self.crash_after = Some(count);
+    }
+
+    fn scripted(&self, command: &str) -> bool {
+        self.answers.iter().any(|&(ref c, _)| c.as_slice() == command)
+    }
+
+    fn knows(&self, command: &str) -> bool {
+        REQUIRED_COMMANDS.iter().any(|c| *c == command) || self.scripted(command)
+    }
+}
+
+/// The mock engine.
+pub struct MockEngine {
+    script: Script,
+    // the number of answers given to each scripted command
+    given: Vec<Cell<uint>>,
+    commands: Cell<uint>,
+    probing: Probing
+}
+
+impl MockEngine {
+    pub fn new(script: Script) -> MockEngine {
+        let given = script.answers.iter().map(|_| Cell::new(0)).collect();
+        MockEngine{ script: script, given: given, commands: Cell::new(0), probing: Probing::new() }
+    }
+
+    /// The number of commands answered so far.
+    pub fn commands(&self) -> uint {
+        self.commands.get()
+    }
+
+    /// The answer to a command, after its delay.
+    /// `None` once the engine crashed.
+    pub fn answer(&self, command: &str, args: &str) -> Option<Answer> {
+        match self.script.crash_after {
+            Some(count) if self.commands.get() >= count => return None,
+            _ => {}
+        }
+        self.commands.set(self.commands.get() + 1);
+        for &(ref c, ms) in self.script.delays.iter() {
+            if c.as_slice() == command || c.as_slice() == "*" {
+                timer::sleep(ms);
+            }
+        }
+        match self.script.answers.iter().position(|&(ref c, _)| c.as_slice() == command) {
+            Some(i) => {
+                let (_, ref answers) = self.script.answers[i];
+                let given = &self.given[i];
+                let answer = answers[cmp::min(given.get(), answers.len() - 1)].clone();
+                given.set(given.get() + 1);
+                Some(answer)
+            },
+            None => Some(self.default_answer(command, args))
+        }
+    }
+
+    fn default_answer(&self, command: &str, args: &str) -> Answer {
+        match command {
+            "protocol_version" => Success(String::from_str("2")),
+            "name" => Success(String::from_str("mock")),
+            "version" => Success(String::from_str("1.0")),
+            "known_command" => Success(format!("{}", self.script.knows(args.trim()))),
+            "list_commands" => {
+                let mut list: Vec<String> = REQUIRED_COMMANDS.iter().map(|c| String::from_str(*c)).collect();
+                for &(ref c, _) in self.script.answers.iter() {
+                    if !REQUIRED_COMMANDS.iter().any(|r| *r == c.as_slice()) {
+                        list.push(c.clone());
+                    }
+                }
+                Success(list.connect("\n"))
+            },
+            "genmove" => Success(String::from_str("pass")),
+            _ if self.script.knows(command) => Success(String::new()),
+            _ => Failure(String::from_str("unknown command"))
+        }
+    }
+
+    /// Answers the commands read from `input` on `output`, until `quit`,
+    /// the end of the input, or a crash. Returns `true` on a crash.
+    pub fn run<R: Buffer, W: Writer>(&self, input: &mut R, output: &mut W) -> IoResult<bool> {
+        loop {
+            let line = match parsing::read_line_lossy(input) {
+                Ok(l) => l,
+                Err(ref e) if e.kind == io::EndOfFile => return Ok(false),
+                Err(e) => return Err(e)
+            };
+            let command = match parsing::parse_command(line.as_slice()) {
+                Some(c) => c,
+                None => continue
+            };
+            let id = command.id.map(|id| format!("{}", id)).unwrap_or(String::new());
+            let response = match self.answer(command.command.as_slice(), command.args.as_slice()) {
+                Some(Success(ref text)) if text.is_empty() => format!("={}", id),
+                Some(Success(text)) => format!("={} {}", id, text),
+                Some(Failure(text)) => format!("?{} {}", id, text),
+                Some(Malformed(text)) => text,
+                None => {
+                    try!(output.flush());
+                    return Ok(true);
+                }
+            };
+            try!(output.write_str(response.append("\n\n").as_slice()));
+            try!(output.flush());
+            if command.command.as_slice() == "quit" {
+                return Ok(false);
+            }
+        }
+    }
+
+    fn command(&self, command: &str, args: &str) -> ClientResult<String> {
+        match self.answer(command, args) {
+            Some(Success(text)) => Ok(text),
+            Some(Failure(message)) => Err(CommandFailed(message)),
+            Some(Malformed(text)) => Err(MalformedResponse(text)),
+            None => Err(IoFailure(IoError{ kind: io::EndOfFile, desc: "mock engine crashed", detail: None }))
+        }
+    }
+}
+
+// crashes and malformed responses are failures of the bot, named so
+static ENGINE: &'static str = "the mock engine";
+
+fn vertices_result(text: String) -> ClientResult<Vec<api::Vertex>> {
+    parse_response(text, |t| responses::parse_vertex_list(t))
+}
+
+impl api::GoBot for MockEngine {
+    fn gtp_name(&self) -> String {
+        unexpected(ENGINE, "name", self.command("name", ""))
+    }
+
+    fn gtp_version(&self) -> String {
+        unexpected(ENGINE, "version", self.command("version", ""))
+    }
+
+    fn gtp_clear_board(&mut self) {
+        unexpected(ENGINE, "clear_board", self.command("clear_board", ""));
+    }
+
+    fn gtp_komi(&mut self, komi: f32) {
+        unexpected(ENGINE, "komi", self.command("komi", format!("{}", komi).as_slice()));
+    }
+
+    fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+        match self.command("boardsize", format!("{}", size).as_slice()) {
+            Err(CommandFailed(_)) => Err(api::InvalidBoardSize),
+            other => Ok(unexpected(ENGINE, "boardsize", other.map(|_| ())))
+        }
+    }
+
+    fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+        let args = format!("{} {}", move.player.to_string(), move.move.to_string());
+        match self.command("play", args.as_slice()) {
+            Err(CommandFailed(_)) => Err(api::InvalidMove),
+            other => Ok(unexpected(ENGINE, "play", other.map(|_| ())))
+        }
+    }
+
+    fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
+        let result = self.command("genmove", player.to_string().as_slice());
+        unexpected(ENGINE, "genmove", result.and_then(|t| parse_response(t, |s| responses::parse_move(s))))
+    }
+
+    fn gtp_genmove_regression(&self, player: api::Colour) -> Result<api::Move, api::GTPError> {
+        match self.probing.probe(self.script.knows("reg_genmove"), api::Pass) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let result = self.command("reg_genmove", player.to_string().as_slice());
+        Ok(unexpected(ENGINE, "reg_genmove", result.and_then(|t| parse_response(t, |s| responses::parse_move(s)))))
+    }
+
+    fn gtp_undo(&mut self) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.script.knows("undo"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.command("undo", "") {
+            Err(CommandFailed(_)) => Err(api::CannotUndo),
+            other => Ok(unexpected(ENGINE, "undo", other.map(|_| ())))
+        }
+    }
+
+    fn gtp_fixed_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.script.knows("fixed_handicap"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.command("fixed_handicap", format!("{}", number).as_slice()) {
+            Err(CommandFailed(_)) => Err(api::BoardNotEmpty),
+            other => Ok(unexpected(ENGINE, "fixed_handicap", other.and_then(|t| vertices_result(t))))
+        }
+    }
+
+    fn gtp_place_free_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.script.knows("place_free_handicap"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.command("place_free_handicap", format!("{}", number).as_slice()) {
+            Err(CommandFailed(_)) => Err(api::BoardNotEmpty),
+            other => Ok(unexpected(ENGINE, "place_free_handicap", other.and_then(|t| vertices_result(t))))
+        }
+    }
+
+    fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.script.knows("set_free_handicap"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.command("set_free_handicap", responses::format_vertex_list(stones).as_slice()) {
+            Err(CommandFailed(_)) => Err(api::BadVertexList),
+            other => Ok(unexpected(ENGINE, "set_free_handicap", other.map(|_| ())))
+        }
+    }
+
+    fn gtp_time_settings(&mut self, main_time: uint, byoyomi_time: uint, byoyomi_stones: uint)
+                         -> Result<(), api::GTPError> {
+        match self.probing.probe(self.script.knows("time_settings"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let args = format!("{} {} {}", main_time, byoyomi_time, byoyomi_stones);
+        unexpected(ENGINE, "time_settings", self.command("time_settings", args.as_slice()));
+        Ok(())
+    }
+
+    fn gtp_time_left(&mut self, player: api::Colour, time: uint, stones: uint) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.script.knows("time_left"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let args = format!("{} {} {}", player.to_string(), time, stones);
+        unexpected(ENGINE, "time_left", self.command("time_left", args.as_slice()));
+        Ok(())
+    }
+
+    fn gtp_final_status_list(&self, status: api::StoneStatus) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.script.knows("final_status_list"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let name = match status {
+            api::Alive => "alive",
+            api::Dead => "dead",
+            api::Seki => "seki"
+        };
+        let result = self.command("final_status_list", name);
+        Ok(unexpected(ENGINE, "final_status_list", result.and_then(|t| vertices_result(t))))
+    }
+
+    fn gtp_final_score(&self) -> Result<(f32, api::Colour), api::GTPError> {
+        match self.probing.probe(self.script.knows("final_score"), (0.0, api::White)) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.command("final_score", "") {
+            Err(CommandFailed(_)) => Err(api::CannotScore),
+            other => Ok(unexpected(ENGINE, "final_score",
+                                   other.and_then(|t| parse_response(t, |s| responses::parse_score(s)))))
+        }
+    }
+
+    fn gtp_showboard(&self) -> Result<(uint, Vec<api::Vertex>, Vec<api::Vertex>, uint, uint), api::GTPError> {
+        match self.probing.probe(self.script.knows("showboard"), (0, Vec::new(), Vec::new(), 0, 0)) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let result = self.command("showboard", "").and_then(|t| parse_response(t, |s| responses::parse_board(s)));
+        let board = unexpected(ENGINE, "showboard", result);
+        Ok((board.size, board.black, board.white,
+            board.black_captured.unwrap_or(0), board.white_captured.unwrap_or(0)))
+    }
+
+    // the lines of the scripted response are reported one by one
+    fn gtp_analyze(&mut self, player: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+        let text = match self.command("lz-analyze", player.to_string().as_slice()) {
+            Err(CommandFailed(_)) => return Ok(()),
+            other => unexpected(ENGINE, "lz-analyze", other)
+        };
+        for line in text.as_slice().lines() {
+            if !report(line) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn can_analyze(&self) -> bool {
+        self.script.knows("lz-analyze")
+    }
+
+    fn gtp_custom_command(&mut self, command: &str, args: &str) -> (bool, String) {
+        match self.command(command, args) {
+            Ok(text) => (true, text),
+            Err(CommandFailed(message)) => (false, message),
+            other => unexpected(ENGINE, command, other.map(|_| (true, String::new())))
+        }
+    }
+
+    fn gtp_known_custom_command(&self, command: &str) -> bool {
+        !BOT_COMMANDS.iter().any(|c| *c == command) && self.script.scripted(command)
+    }
+
+    fn gtp_list_custom_commands(&self) -> Vec<String> {
+        self.script.answers.iter().map(|&(ref c, _)| c.clone())
+            .filter(|c| !BOT_COMMANDS.iter().any(|b| *b == c.as_slice())).collect()
+    }
+
+    fn on_start(&mut self) {
+        self.probing.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, MemWriter};
+    use std::task;
+    use api;
+    use api::GoBot;
+
+    static SCRIPT: &'static str = "# a test script
+genmove = D4
+genmove = Q16
+play ? illegal move
+fixed_handicap = D4 Q16\\nQ4
+showboard ! garbage
+kgs-chat = hello
+crash after 6
+";
+
+    #[test]
+    fn parse() {
+        let script = super::Script::parse(SCRIPT).unwrap();
+        assert_eq!(script.answers[0], (String::from_str("genmove"),
+                                       vec!(super::Success(String::from_str("D4")),
+                                            super::Success(String::from_str("Q16")))));
+        let (_, ref handicap) = script.answers[2];
+        assert_eq!(handicap[0], super::Success(String::from_str("D4 Q16\nQ4")));
+        assert_eq!(script.crash_after, Some(6));
+        assert!(super::Script::parse("delay genmove soon").is_err());
+        assert!(super::Script::parse("genmove D4").is_err());
+    }
+
+    #[test]
+    fn run() {
+        let engine = super::MockEngine::new(super::Script::parse(SCRIPT).unwrap());
+        let mut input = BufReader::new(b"1 name\ngenmove black\n# comment\n\n3 genmove white\ngenmove black\n\
+                                         play white A1\nshowboard\nquit\n");
+        let mut output = MemWriter::new();
+        assert!(engine.run(&mut input, &mut output).unwrap());
+        assert_eq!(output.get_ref(), b"=1 mock\n\n= D4\n\n=3 Q16\n\n= Q16\n\n? illegal move\n\ngarbage\n\n");
+    }
+
+    #[test]
+    fn bot() {
+        let mut engine = super::MockEngine::new(super::Script::parse(SCRIPT).unwrap());
+        assert!(engine.gtp_undo().is_err());
+        assert!(engine.gtp_fixed_handicap(3).is_ok());
+        assert!(engine.gtp_known_custom_command("kgs-chat"));
+        assert_eq!(engine.gtp_list_custom_commands(), vec!(String::from_str("kgs-chat")));
+        engine.on_start();
+        assert_eq!(engine.commands(), 0);
+        assert_eq!(engine.gtp_genmove(api::Black), api::Stone(api::Vertex::from_str("D4").unwrap()));
+        assert!(match engine.gtp_play(api::ColouredMove{ player: api::White, move: api::Pass }) {
+            Err(api::InvalidMove) => true,
+            _ => false
+        });
+        assert_eq!(engine.gtp_fixed_handicap(3).unwrap().len(), 3);
+        assert_eq!(engine.gtp_custom_command("kgs-chat", ""), (true, String::from_str("hello")));
+        let result = task::try(proc() {
+            let mut engine = super::MockEngine::new(super::Script::parse("showboard ! garbage").unwrap());
+            engine.on_start();
+            engine.gtp_showboard()
+        });
+        assert!(result.is_err());
+    }
+}
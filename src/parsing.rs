@@ -1,25 +1,33 @@
 use std::vec::Vec;
-use std::ascii::Ascii;
+use std::ascii::StrAsciiExt;
+use std::io::{IoResult, Buffer};
 use api;
 
+// reads a line as utf-8, invalid sequences are replaced rather than
+// making the read fail, so that every command still gets an answer
+pub fn read_line_lossy<B: Buffer>(input: &mut B) -> IoResult<String> {
+    let bytes = try!(input.read_until(b'\n'));
+    Ok(String::from_utf8_lossy(bytes.as_slice()).into_string())
+}
+
 // Strips all ignored content from input string
 // according to specifications of GTPv2
-fn strip_input(input: &[Ascii]) -> Vec<Ascii> {
-    let mut output: Vec<Ascii> = Vec::new();
-    let mut last_char: Ascii = '\n'.to_ascii(); // set initial lastchar as LF
+fn strip_input(input: &str) -> String {
+    let mut output = String::new();
+    let mut last_char = '\n'; // set initial lastchar as LF
     let mut in_comment = false;
-    for &c in input.iter() {
-        if c == '\n'.to_ascii() { // newline
+    for c in input.chars() {
+        if c == '\n' { // newline
             in_comment = false;
-            if last_char == '\n'.to_ascii() {
+            if last_char == '\n' {
                 // ignore multiple newlines
                 continue;
             }
-            last_char = '\n'.to_ascii();
-            output.push('\n'.to_ascii());
+            last_char = '\n';
+            output.push_char('\n');
             continue;
         }
-        if c == '#'.to_ascii() {
+        if c == '#' {
             in_comment = true;
             continue;
         }
@@ -27,23 +35,23 @@ fn strip_input(input: &[Ascii]) -> Vec<Ascii> {
             // we are in a comment, and the caracter did not end it,
             continue;
         }
-        if c.is_blank() { // space or \t
-            if last_char.to_char().is_whitespace() {
+        if c == ' ' || c == '\t' {
+            if last_char.is_whitespace() {
                 // multiple spaces are discarded
                 // as well as spaces in the begining of a line
                 continue;
             }
-            last_char = ' '.to_ascii();
-            output.push(' '.to_ascii());
+            last_char = ' ';
+            output.push_char(' ');
             continue;
         }
         if c.is_control() {
             // non printable, drop it
             continue;
         }
-        // any other character is kept as it
+        // any other character is kept as it, including non-ascii ones
         last_char = c;
-        output.push(c);
+        output.push_char(c);
     }
     output
 }
@@ -51,38 +59,38 @@ fn strip_input(input: &[Ascii]) -> Vec<Ascii> {
 #[deriving(PartialEq, Show)]
 pub struct GTPCommand {
     pub id: Option<u32>,
-    pub command: Vec<Ascii>,
-    pub args: Vec<Ascii>
+    pub command: String,
+    pub args: String
 }
 
 // parses a command from a line
 // input is suposed to be a single stripped line
 // without trailing \n
-fn parse_command_from_stripped(line: &[Ascii]) -> Option<GTPCommand> {
-    if line.as_str_ascii().is_whitespace() {
+fn parse_command_from_stripped(line: &str) -> Option<GTPCommand> {
+    if line.is_whitespace() {
         // empty line no command to parse
         None
     } else {
-        let mut first_split = line.splitn(1, |&c| { c == ' '.to_ascii()});
+        let mut first_split = line.splitn(' ', 1);
         // there is always a first value
         let first_part = first_split.next().unwrap();
         let mut to_split = match first_split.next() {
             Some(text) => text,
-            _ => "".to_ascii()
+            _ => ""
         };
-        let id = from_str::<u32>(first_part.as_str_ascii());
+        let id = from_str::<u32>(first_part);
         match id {
             None => { to_split = line; },
             _ => { }
         };
-        let mut second_split = to_split.splitn(1, |&c| { c == ' '.to_ascii()});
+        let mut second_split = to_split.splitn(' ', 1);
         match second_split.next() {
             Some(text) if text.len() > 0 => Some(GTPCommand{
                 id: id,
-                command: Vec::from_slice(text),
+                command: String::from_str(text),
                 args: match second_split.next() {
-                    Some(arguments) => Vec::from_slice(arguments),
-                    _ => Vec::new()
+                    Some(arguments) => String::from_str(arguments),
+                    _ => String::new()
                 }
             }),
             _ => None
@@ -93,8 +101,8 @@ fn parse_command_from_stripped(line: &[Ascii]) -> Option<GTPCommand> {
 // parses a command from a un-stripped line
 // if inputed several lines, only the first non empty
 // and non comment is parsed
-pub fn parse_command(input: &[Ascii]) -> Option<GTPCommand> {
-    match strip_input(input).as_slice().splitn(1, |&c| {c == '\n'.to_ascii()}).next() {
+pub fn parse_command(input: &str) -> Option<GTPCommand> {
+    match strip_input(input).as_slice().splitn('\n', 1).next() {
         Some(line) => parse_command_from_stripped(line),
         _ => None
     }
@@ -118,20 +126,20 @@ pub enum Argument {
     ArgStoneStatus(api::StoneStatus)
 }
 
-pub fn arg_parse_colour (input: &[Ascii]) -> Option<api::Colour> {
-    match input.to_lower().as_slice().as_str_ascii() {
+pub fn arg_parse_colour (input: &str) -> Option<api::Colour> {
+    match input.to_ascii_lower().as_slice() {
         "w" | "white" => Some(api::White),
         "b" | "black" => Some(api::Black),
         _ => None
     }
 }
 
-pub fn arg_parse_vertex (input: &[Ascii]) -> Option<api::Vertex> {
-    api::Vertex::from_str(input.as_str_ascii())
+pub fn arg_parse_vertex (input: &str) -> Option<api::Vertex> {
+    api::Vertex::from_str(input)
 }
 
-pub fn arg_parse_move (input: &[Ascii]) -> Option<api::Move> {
-    match input.to_lower().as_slice().as_str_ascii() {
+pub fn arg_parse_move (input: &str) -> Option<api::Move> {
+    match input.to_ascii_lower().as_slice() {
         "pass" => Some(api::Pass),
         "resign" => Some(api::Resign),
         _ => match arg_parse_vertex(input) {
@@ -141,8 +149,8 @@ pub fn arg_parse_move (input: &[Ascii]) -> Option<api::Move> {
     }
 }
 
-pub fn arg_parse_stone_status (input: &[Ascii]) -> Option<api::StoneStatus> {
-    match input.to_lower().as_slice().as_str_ascii() {
+pub fn arg_parse_stone_status (input: &str) -> Option<api::StoneStatus> {
+    match input.to_ascii_lower().as_slice() {
         "alive" => Some(api::Alive),
         "dead" => Some(api::Dead),
         "seki" => Some(api::Seki),
@@ -150,8 +158,8 @@ pub fn arg_parse_stone_status (input: &[Ascii]) -> Option<api::StoneStatus> {
     }
 }
 
-pub fn parse_args (input: &[Ascii], types: &[ArgType]) -> Option<Vec<Argument>> {
-    let mut args_iter = input.split(|&c| {c == ' '.to_ascii() });
+pub fn parse_args (input: &str, types: &[ArgType]) -> Option<Vec<Argument>> {
+    let mut args_iter = input.split(' ');
     let mut vect: Vec<Argument> = Vec::new();
     for &arg_type in types.iter() {
         let itered = args_iter.next();
@@ -200,83 +208,83 @@ mod tests {
     use api;
     #[test]
     fn strip_input() {
-        let input = "command1 \t and \x15 argu\x07ments\n# this is a comment\n  command2    !op # comment\n\n\nfoo bar\n\n".to_ascii();
-        let expected_output = "command1 and arguments\ncommand2 !op \nfoo bar\n".to_ascii();
+        let input = "command1 \t and \x15 argu\x07ments\n# this is a comment\n  command2    !op # comment\n\n\nfoo bar\n\n";
+        let expected_output = "command1 and arguments\ncommand2 !op \nfoo bar\n";
         let output = super::strip_input(input);
         assert_eq!(output.as_slice(), expected_output);
     }
 
     #[test]
     fn parse_command_from_stripped() {
-        assert_eq!(super::parse_command_from_stripped("".to_ascii()), None);
-        assert_eq!(super::parse_command_from_stripped("56".to_ascii()), None);
-        assert_eq!(super::parse_command_from_stripped("foo".to_ascii()), Some(
+        assert_eq!(super::parse_command_from_stripped(""), None);
+        assert_eq!(super::parse_command_from_stripped("56"), None);
+        assert_eq!(super::parse_command_from_stripped("foo"), Some(
             super::GTPCommand{
                 id: None,
-                command: Vec::from_slice("foo".to_ascii()),
-                args: Vec::new()
+                command: String::from_str("foo"),
+                args: String::new()
             }));
-        assert_eq!(super::parse_command_from_stripped("foo bar baz".to_ascii()), Some(
+        assert_eq!(super::parse_command_from_stripped("foo bar baz"), Some(
             super::GTPCommand{
                 id: None,
-                command: Vec::from_slice("foo".to_ascii()),
-                args: Vec::from_slice("bar baz".to_ascii())
+                command: String::from_str("foo"),
+                args: String::from_str("bar baz")
             }));
-        assert_eq!(super::parse_command_from_stripped("42 foo".to_ascii()), Some(
+        assert_eq!(super::parse_command_from_stripped("42 foo"), Some(
             super::GTPCommand{
                 id: Some(42u32),
-                command: Vec::from_slice("foo".to_ascii()),
-                args: Vec::from_slice("".to_ascii())
+                command: String::from_str("foo"),
+                args: String::from_str("")
             }));
-        assert_eq!(super::parse_command_from_stripped("42 foo bar baz".to_ascii()), Some(
+        assert_eq!(super::parse_command_from_stripped("42 foo bar baz"), Some(
             super::GTPCommand{
                 id: Some(42u32),
-                command: Vec::from_slice("foo".to_ascii()),
-                args: Vec::from_slice("bar baz".to_ascii())
+                command: String::from_str("foo"),
+                args: String::from_str("bar baz")
             }));
     }
 
     #[test]
     fn parse_command() {
         assert_eq!(
-            super::parse_command("  #  this is a comment\n\t  \n  # this as well".to_ascii()),
+            super::parse_command("  #  this is a comment\n\t  \n  # this as well"),
             None
         );
         assert_eq!(
-            super::parse_command("#this command is really cool\n42 foo Cake is a lie  # cool isn't it ?".to_ascii()),
+            super::parse_command("#this command is really cool\n42 foo Cake is a lie  # cool isn't it ?"),
             Some(super::GTPCommand{
                 id: Some(42u32),
-                command: Vec::from_slice("foo".to_ascii()),
-                args: Vec::from_slice("Cake is a lie ".to_ascii())
+                command: String::from_str("foo"),
+                args: String::from_str("Cake is a lie ")
             })
         );
     }
 
     #[test]
     fn arg_parse_colour() {
-        assert_eq!(super::arg_parse_colour("BlAcK".to_ascii()), Some(api::Black));
-        assert_eq!(super::arg_parse_colour("b".to_ascii()), Some(api::Black));
-        assert_eq!(super::arg_parse_colour("WHIte".to_ascii()), Some(api::White));
-        assert_eq!(super::arg_parse_colour("W".to_ascii()), Some(api::White));
-        assert_eq!(super::arg_parse_colour("FOO".to_ascii()), None);
-        assert_eq!(super::arg_parse_colour("bar".to_ascii()), None);
+        assert_eq!(super::arg_parse_colour("BlAcK"), Some(api::Black));
+        assert_eq!(super::arg_parse_colour("b"), Some(api::Black));
+        assert_eq!(super::arg_parse_colour("WHIte"), Some(api::White));
+        assert_eq!(super::arg_parse_colour("W"), Some(api::White));
+        assert_eq!(super::arg_parse_colour("FOO"), None);
+        assert_eq!(super::arg_parse_colour("bar"), None);
     }
 
     #[test]
     fn arg_parse_move() {
-        assert_eq!(super::arg_parse_move("ReSiGn".to_ascii()), Some(api::Resign));
-        assert_eq!(super::arg_parse_move("PasS".to_ascii()), Some(api::Pass));
-        assert_eq!(super::arg_parse_move("A12".to_ascii()), Some(api::Stone(api::Vertex::from_coords(1,12).unwrap())));
-        assert_eq!(super::arg_parse_move("T7".to_ascii()), Some(api::Stone(api::Vertex::from_coords(19,7).unwrap())));
-        assert_eq!(super::arg_parse_move("F26".to_ascii()), None);
-        assert_eq!(super::arg_parse_move("I13".to_ascii()), None);
-        assert_eq!(super::arg_parse_move("foo".to_ascii()), None);
+        assert_eq!(super::arg_parse_move("ReSiGn"), Some(api::Resign));
+        assert_eq!(super::arg_parse_move("PasS"), Some(api::Pass));
+        assert_eq!(super::arg_parse_move("A12"), Some(api::Stone(api::Vertex::from_coords(1,12).unwrap())));
+        assert_eq!(super::arg_parse_move("T7"), Some(api::Stone(api::Vertex::from_coords(19,7).unwrap())));
+        assert_eq!(super::arg_parse_move("F26"), None);
+        assert_eq!(super::arg_parse_move("I13"), None);
+        assert_eq!(super::arg_parse_move("foo"), None);
 
     }
 
     #[test]
     fn parse_args() {
-        let arg_string = "W G7 alive black pass E5".to_ascii();
+        let arg_string = "W G7 alive black pass E5";
         let arg_types = vec!(
             super::ColouredMoveArg,
             super::StoneStatusArg,
@@ -294,4 +302,18 @@ mod tests {
         let parsed_args = super::parse_args(arg_string, arg_types.as_slice());
         assert_eq!(parsed_args.unwrap(), expected_args);
     }
+
+    #[test]
+    fn non_ascii_input() {
+        assert_eq!(
+            super::parse_command("3 loadsgf parties/Ünïcödé 囲碁.sgf # comment"),
+            Some(super::GTPCommand{
+                id: Some(3u32),
+                command: String::from_str("loadsgf"),
+                args: String::from_str("parties/Ünïcödé 囲碁.sgf ")
+            })
+        );
+        assert_eq!(super::arg_parse_colour("blàck"), None);
+        assert_eq!(super::arg_parse_move("Ä1"), None);
+    }
 }
@@ -0,0 +1,563 @@
+//! A bot forwarding every command to an external engine, to sit
+//! between a GUI and the engine.
+//!
+//! The proxy answers the GUI through the usual handler, and sends the
+//! commands to the engine with `client::Client`. Every exchange with
+//! the engine can be logged with timestamps, and rules rewrite the
+//! traffic on its way:
+//!
+//! * `rename OLD NEW` : sends `OLD` commands to the engine as `NEW`
+//! * `drop NAME` : answers `NAME` commands with an empty success,
+//!   without sending them
+//! * `before NAME COMMAND...` : sends `COMMAND...` before each `NAME`
+//! * `after NAME COMMAND...` : sends `COMMAND...` after each `NAME`
+//!
+//! Rules are matched against the command names received from the GUI.
+//! The responses to injected commands are logged, but not given to
+//! the GUI.
+//!
+//! When the engine does not know a command but knows its equivalent in
+//! another dialect, the command is translated:
+//!
+//! * `kgs-time_settings` and `time_settings`, KGS byo-yomi being given
+//!   to the engine as one stone per period
+//! * `lz-analyze` and `kata-analyze`, with their output
+//! * the GTP version 1 commands `black`, `white`, `genmove_black`,
+//!   `genmove_white` and `help`, and their version 2 equivalents;
+//!   `clear_board` is sent as `boardsize` to version 1 engines
+//!
+//! Analysis commands run for the analysis time, 2 seconds by default,
+//! then the engine is interrupted.
+//!
+//! Commands answered by the handler itself (`protocol_version`,
+//! `known_command`, `list_commands`, `quit`) are not forwarded; the
+//! optional commands, `time_left` included, are available when the
+//! engine lists them, or their equivalents, or when a rule takes care
+//! of them.
+
+use std::cell::{Cell, RefCell};
+use std::io::{IoResult, Buffer, Writer};
+use time;
+use api;
+use client::{Client, ClientResult, CommandFailed, Probing, parse_response, unexpected};
+use dialects;
+use responses;
+
+/// A rewriting rule, see the module documentation.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Rule {
+    Rename(String, String),
+    Discard(String),
+    InjectBefore(String, String),
+    InjectAfter(String, String)
+}
+
+impl Rule {
+    /// Parses a rule written as in the module documentation.
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let words: Vec<&str> = text.words().collect();
+        let rest = |n: uint| -> String { words.slice_from(n).connect(" ") };
+        match words.as_slice() {
+            ["rename", old, new] => Ok(Rename(String::from_str(old), String::from_str(new))),
+            ["drop", name] => Ok(Discard(String::from_str(name))),
+            ["before", name, _, ..] => Ok(InjectBefore(String::from_str(name), rest(2))),
+            ["after", name, _, ..] => Ok(InjectAfter(String::from_str(name), rest(2))),
+            _ => Err(format!("invalid rule: {}, expected rename OLD NEW, drop NAME, \
+                              before NAME COMMAND or after NAME COMMAND", text))
+        }
+    }
+}
+
+// the commands the handler answers, or whose availability it decides;
+// the other commands of the engine are forwarded as custom commands
+static HANDLED_COMMANDS: &'static [&'static str] = &[
+    "protocol_version", "name", "version", "known_command", "list_commands", "quit",
+    "boardsize", "clear_board", "komi", "play", "genmove", "reg_genmove", "undo",
+    "fixed_handicap", "place_free_handicap", "set_free_handicap", "time_settings",
+    "time_left", "final_status_list", "final_score", "showboard", "lz-analyze"
+];
+
+/// The proxy, a `GoBot` driving the engine behind `client`.
+pub struct Proxy<R, W> {
+    client: RefCell<Client<R, W>>,
+    rules: Vec<Rule>,
+    log: RefCell<Option<Box<Writer + 'static>>>,
+    name: String,
+    version: String,
+    // commands listed by the engine
+    commands: Vec<String>,
+    // for version 1 engines, which clear the board with boardsize
+    size: Cell<uint>,
+    // in milliseconds
+    analysis_time: u64,
+    probing: Probing
+}
+
+// interval of the analysis lines, in centiseconds
+static ANALYSIS_INTERVAL: uint = 10;
+
+// ex: 2014-10-18 21:05:12.042
+fn timestamp() -> String {
+    let now = time::now();
+    format!("{}.{:03d}", time::strftime("%Y-%m-%d %H:%M:%S", &now), now.tm_nsec / 1000000)
+}
+
+impl<R: Buffer, W: Writer> Proxy<R, W> {
+    /// A proxy for the engine behind `client`, applying `rules`,
+    /// logging the exchanges to `log` if any.
+    /// The engine is asked its name, version and commands.
+    pub fn new(client: Client<R, W>, rules: Vec<Rule>, log: Option<Box<Writer + 'static>>)
+               -> ClientResult<Proxy<R, W>> {
+        let mut proxy = Proxy{ client: RefCell::new(client), rules: rules, log: RefCell::new(log),
+                               name: String::new(), version: String::new(), commands: Vec::new(),
+                               size: Cell::new(19), analysis_time: 2000, probing: Probing::new() };
+        proxy.name = try!(proxy.exchange("name"));
+        proxy.version = match proxy.exchange("version") {
+            Err(CommandFailed(_)) => String::new(),
+            other => try!(other)
+        };
+        let list = match proxy.exchange("list_commands") {
+            // version 1
+            Err(CommandFailed(_)) => try!(proxy.exchange("help")),
+            other => try!(other)
+        };
+        proxy.commands = list.as_slice().lines().map(|l| String::from_str(l.trim()))
+                                                .filter(|l| !l.is_empty()).collect();
+        Ok(proxy)
+    }
+
+    /// Sets how long analysis commands run, in milliseconds.
+    pub fn set_analysis_time(&mut self, milliseconds: u64) {
+        self.analysis_time = milliseconds;
+    }
+
+    /// Gives back the client, to send `quit` to the engine.
+    pub fn unwrap(self) -> Client<R, W> {
+        self.client.unwrap()
+    }
+
+    fn log_line(&self, direction: &str, text: &str) {
+        let mut log = self.log.borrow_mut();
+        let failed = match *log {
+            Some(ref mut writer) => {
+                let stamp = timestamp();
+                let mut result: IoResult<()> = Ok(());
+                for line in text.lines() {
+                    result = result.and(writer.write_line(format!("{} {} {}", stamp, direction, line).as_slice()));
+                }
+                result.and(writer.flush()).is_err()
+            },
+            None => false
+        };
+        // a broken log must not stop the session
+        if failed {
+            *log = None;
+        }
+    }
+
+    // sends a command line as is
+    fn exchange(&self, line: &str) -> ClientResult<String> {
+        self.log_line(">", line);
+        let result = self.client.borrow_mut().send(line);
+        match result {
+            Ok(response) => {
+                let mark = if response.success { "=" } else { "?" };
+                self.log_line("<", format!("{} {}", mark, response.text).as_slice());
+                if response.success { Ok(response.text) } else { Err(CommandFailed(response.text)) }
+            },
+            Err(e) => {
+                self.log_line("!", format!("{}", e).as_slice());
+                Err(e)
+            }
+        }
+    }
+
+    /// Sends a command line to the engine, applying the rules.
+    pub fn forward(&self, line: &str) -> ClientResult<String> {
+        let name = line.words().next().unwrap_or("");
+        let rest = line.trim_left().slice_from(name.len());
+        for rule in self.rules.iter() {
+            match *rule {
+                InjectBefore(ref n, ref command) if n.as_slice() == name => {
+                    let _ = self.exchange(command.as_slice());
+                },
+                _ => {}
+            }
+        }
+        let dropped = self.rules.iter().any(|r| match *r {
+            Discard(ref n) => n.as_slice() == name,
+            _ => false
+        });
+        let result = if dropped {
+            self.log_line("-", line);
+            Ok(String::new())
+        } else {
+            let renamed = self.rules.iter().filter_map(|r| match *r {
+                Rename(ref old, ref new) if old.as_slice() == name => Some(new.clone()),
+                _ => None
+            }).next();
+            let line = match renamed {
+                Some(new) => new.append(rest),
+                None => String::from_str(line)
+            };
+            self.exchange(self.to_engine_dialect(line.as_slice()).as_slice())
+        };
+        for rule in self.rules.iter() {
+            match *rule {
+                InjectAfter(ref n, ref command) if n.as_slice() == name => {
+                    let _ = self.exchange(command.as_slice());
+                },
+                _ => {}
+            }
+        }
+        result
+    }
+
+    // translates the command line if the engine only knows its equivalent
+    fn to_engine_dialect(&self, line: &str) -> String {
+        let name = line.words().next().unwrap_or("");
+        match dialects::equivalent(name) {
+            Some(other) if !self.engine_knows(name) && self.engine_knows(other) => {
+                dialects::translate(name, line.trim_left().slice_from(name.len()), self.size.get())
+            },
+            _ => String::from_str(line)
+        }
+    }
+
+    // runs an analysis command for the analysis time, its lines being
+    // given to `report` in the dialect of the command
+    fn analyze(&self, line: &str, mut report: |&str| -> bool) -> ClientResult<()> {
+        let received = line.words().next().unwrap_or("");
+        let line = self.to_engine_dialect(line);
+        let sent = String::from_str(line.as_slice().words().next().unwrap_or(""));
+        let deadline = time::precise_time_ns() + self.analysis_time * 1000000;
+        self.log_line(">", line.as_slice());
+        let result = self.client.borrow_mut().send_streaming(line.as_slice(), |info| {
+            self.log_line("<", info);
+            let translated = dialects::translate_analysis(sent.as_slice(), received, info);
+            report(translated.as_slice()) && time::precise_time_ns() < deadline
+        });
+        match result {
+            Ok(response) => {
+                let mark = if response.success { "=" } else { "?" };
+                self.log_line("<", format!("{} {}", mark, response.text).as_slice());
+                if response.success { Ok(()) } else { Err(CommandFailed(response.text)) }
+            },
+            Err(e) => {
+                self.log_line("!", format!("{}", e).as_slice());
+                Err(e)
+            }
+        }
+    }
+
+    fn engine_knows(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c.as_slice() == command)
+    }
+
+    // true if the engine knows the command or its equivalent,
+    // or if a rule takes care of it
+    fn knows(&self, command: &str) -> bool {
+        self.engine_knows(command) || dialects::equivalent(command).map_or(false, |c| self.engine_knows(c))
+            || self.rules.iter().any(|r| match *r {
+                Rename(ref old, _) | Discard(ref old) => old.as_slice() == command,
+                _ => false
+            })
+    }
+}
+
+fn move_result(text: String) -> ClientResult<api::Move> {
+    parse_response(text, |t| responses::parse_move(t))
+}
+
+fn vertices_result(text: String) -> ClientResult<Vec<api::Vertex>> {
+    parse_response(text, |t| responses::parse_vertex_list(t))
+}
+
+impl<R: Buffer, W: Writer> api::GoBot for Proxy<R, W> {
+    fn gtp_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn gtp_version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn gtp_clear_board(&mut self) -> () {
+        unexpected(self.name.as_slice(), "clear_board", self.forward("clear_board"));
+    }
+
+    fn gtp_komi(&mut self, komi: f32) -> () {
+        unexpected(self.name.as_slice(), "komi", self.forward(format!("komi {}", komi).as_slice()));
+    }
+
+    fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
+        match self.forward(format!("boardsize {}", size).as_slice()) {
+            Ok(_) => {
+                self.size.set(size);
+                Ok(())
+            },
+            Err(CommandFailed(_)) => Err(api::InvalidBoardSize),
+            other => unexpected(self.name.as_slice(), "boardsize", other.map(|_| Ok(())))
+        }
+    }
+
+    fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
+        match self.forward(format!("play {} {}", move.player.to_string(), move.move.to_string()).as_slice()) {
+            Ok(_) => Ok(()),
+            Err(CommandFailed(_)) => Err(api::InvalidMove),
+            other => unexpected(self.name.as_slice(), "play", other.map(|_| Ok(())))
+        }
+    }
+
+    fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
+        let result = self.forward(format!("genmove {}", player.to_string()).as_slice());
+        unexpected(self.name.as_slice(), "genmove", result.and_then(|t| move_result(t)))
+    }
+
+    fn gtp_genmove_regression(&self, player: api::Colour) -> Result<api::Move, api::GTPError> {
+        match self.probing.probe(self.knows("reg_genmove"), api::Pass) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let result = self.forward(format!("reg_genmove {}", player.to_string()).as_slice());
+        Ok(unexpected(self.name.as_slice(), "reg_genmove", result.and_then(|t| move_result(t))))
+    }
+
+    fn gtp_undo(&mut self) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.knows("undo"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.forward("undo") {
+            Ok(_) => Ok(()),
+            Err(CommandFailed(_)) => Err(api::CannotUndo),
+            other => unexpected(self.name.as_slice(), "undo", other.map(|_| Ok(())))
+        }
+    }
+
+    fn gtp_fixed_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.knows("fixed_handicap"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.forward(format!("fixed_handicap {}", number).as_slice()) {
+            Err(CommandFailed(_)) => Err(api::BoardNotEmpty),
+            other => Ok(unexpected(self.name.as_slice(), "fixed_handicap", other.and_then(|t| vertices_result(t))))
+        }
+    }
+
+    fn gtp_place_free_handicap(&mut self, number: uint) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.knows("place_free_handicap"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.forward(format!("place_free_handicap {}", number).as_slice()) {
+            Err(CommandFailed(_)) => Err(api::BoardNotEmpty),
+            other => Ok(unexpected(self.name.as_slice(), "place_free_handicap", other.and_then(|t| vertices_result(t))))
+        }
+    }
+
+    fn gtp_set_free_handicap(&mut self, stones: &[api::Vertex]) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.knows("set_free_handicap"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let line = format!("set_free_handicap {}", responses::format_vertex_list(stones));
+        match self.forward(line.as_slice()) {
+            Ok(_) => Ok(()),
+            Err(CommandFailed(ref text)) if text.as_slice().contains("not empty") => Err(api::BoardNotEmpty),
+            Err(CommandFailed(_)) => Err(api::BadVertexList),
+            other => unexpected(self.name.as_slice(), "set_free_handicap", other.map(|_| Ok(())))
+        }
+    }
+
+    fn gtp_time_settings(&mut self, main_time: uint, byoyomi_time: uint, byoyomi_stones: uint)
+                         -> Result<(), api::GTPError> {
+        match self.probing.probe(self.knows("time_settings"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let line = format!("time_settings {} {} {}", main_time, byoyomi_time, byoyomi_stones);
+        unexpected(self.name.as_slice(), "time_settings", self.forward(line.as_slice()));
+        Ok(())
+    }
+
+    fn gtp_time_left(&mut self, player: api::Colour, time: uint, stones: uint) -> Result<(), api::GTPError> {
+        match self.probing.probe(self.knows("time_left"), ()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let line = format!("time_left {} {} {}", player.to_string(), time, stones);
+        unexpected(self.name.as_slice(), "time_left", self.forward(line.as_slice()));
+        Ok(())
+    }
+
+    fn gtp_final_status_list(&self, status: api::StoneStatus) -> Result<Vec<api::Vertex>, api::GTPError> {
+        match self.probing.probe(self.knows("final_status_list"), Vec::new()) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let name = match status {
+            api::Alive => "alive",
+            api::Dead => "dead",
+            api::Seki => "seki"
+        };
+        let result = self.forward(format!("final_status_list {}", name).as_slice());
+        Ok(unexpected(self.name.as_slice(), "final_status_list", result.and_then(|t| vertices_result(t))))
+    }
+
+    fn gtp_final_score(&self) -> Result<(f32, api::Colour), api::GTPError> {
+        match self.probing.probe(self.knows("final_score"), (0.0, api::White)) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        match self.forward("final_score") {
+            Err(CommandFailed(_)) => Err(api::CannotScore),
+            other => Ok(unexpected(self.name.as_slice(), "final_score",
+                                   other.and_then(|t| parse_response(t, |s| responses::parse_score(s)))))
+        }
+    }
+
+    fn gtp_showboard(&self) -> Result<(uint, Vec<api::Vertex>, Vec<api::Vertex>, uint, uint), api::GTPError> {
+        match self.probing.probe(self.knows("showboard"), (0, Vec::new(), Vec::new(), 0, 0)) {
+            Some(answer) => return answer,
+            None => {}
+        }
+        let result = self.forward("showboard").and_then(|t| parse_response(t, |s| responses::parse_board(s)));
+        let board = unexpected(self.name.as_slice(), "showboard", result);
+        Ok((board.size, board.black, board.white,
+            board.black_captured.unwrap_or(0), board.white_captured.unwrap_or(0)))
+    }
+
+    fn gtp_analyze(&mut self, player: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+        let line = format!("lz-analyze {} {}", player.to_string(), ANALYSIS_INTERVAL);
+        match self.analyze(line.as_slice(), report) {
+            Err(CommandFailed(_)) => Ok(()),
+            other => Ok(unexpected(self.name.as_slice(), "lz-analyze", other))
+        }
+    }
+
+    fn can_analyze(&self) -> bool {
+        self.knows("lz-analyze")
+    }
+
+    fn gtp_custom_command(&mut self, command: &str, args: &str) -> (bool, String) {
+        let line = if args.is_empty() { String::from_str(command) } else { format!("{} {}", command, args) };
+        if dialects::is_analysis(command) {
+            // the lines come in the response, once the analysis is over
+            let mut text = String::new();
+            let result = self.analyze(line.as_slice(), |info| { text.push_str(format!("\n{}", info).as_slice()); true });
+            return match result {
+                Err(CommandFailed(message)) => (false, message),
+                other => {
+                    unexpected(self.name.as_slice(), command, other);
+                    (true, text)
+                }
+            };
+        }
+        match self.forward(line.as_slice()) {
+            Err(CommandFailed(text)) => (false, text),
+            other => (true, unexpected(self.name.as_slice(), command, other))
+        }
+    }
+
+    fn gtp_known_custom_command(&self, command: &str) -> bool {
+        !HANDLED_COMMANDS.iter().any(|c| *c == command) && self.knows(command)
+    }
+
+    fn gtp_list_custom_commands(&self) -> Vec<String> {
+        let mut list: Vec<String> = self.commands.iter().filter(|c| !HANDLED_COMMANDS.iter().any(|h| *h == c.as_slice()))
+                                                        .map(|c| c.clone()).collect();
+        for c in dialects::COMMANDS.iter() {
+            if !HANDLED_COMMANDS.iter().any(|h| h == c) && !self.engine_knows(*c) && self.knows(*c) {
+                list.push(String::from_str(*c));
+            }
+        }
+        list
+    }
+
+    fn on_start(&mut self) {
+        self.probing.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, MemWriter};
+    use api;
+    use api::GoBot;
+    use client::Client;
+
+    fn proxy<'a>(responses: &'a str, rules: Vec<super::Rule>) -> super::Proxy<BufReader<'a>, MemWriter> {
+        let client = Client::new(BufReader::new(responses.as_bytes()), MemWriter::new());
+        super::Proxy::new(client, rules, None).unwrap()
+    }
+
+    fn sent<'a>(proxy: super::Proxy<BufReader<'a>, MemWriter>) -> String {
+        let (_, writer) = proxy.unwrap().unwrap();
+        String::from_utf8(writer.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_rule() {
+        assert_eq!(super::Rule::parse("rename kgs-genmove_cleanup genmove"),
+                   Ok(super::Rename(String::from_str("kgs-genmove_cleanup"), String::from_str("genmove"))));
+        assert_eq!(super::Rule::parse("after clear_board komi 6.5"),
+                   Ok(super::InjectAfter(String::from_str("clear_board"), String::from_str("komi 6.5"))));
+        assert!(super::Rule::parse("drop").is_err());
+    }
+
+    #[test]
+    fn forward() {
+        let rules = vec!(super::Rule::parse("rename genmove_cleanup genmove").unwrap(),
+                         super::Rule::parse("drop komi").unwrap(),
+                         super::Rule::parse("before clear_board boardsize 9").unwrap());
+        let mut p = proxy("=1 Engine\n\n=2 1.0\n\n=3 genmove\nplay\n\n=4\n\n=5\n\n=6 D4\n\n", rules);
+        p.on_start();
+        p.gtp_komi(6.5);
+        p.gtp_clear_board();
+        assert_eq!(p.gtp_custom_command("genmove_cleanup", "black"), (true, String::from_str("D4")));
+        assert!(p.gtp_known_custom_command("genmove_cleanup"));
+        assert!(!p.gtp_known_custom_command("genmove"));
+        assert_eq!(p.gtp_name(), String::from_str("Engine"));
+        assert_eq!(sent(p).as_slice(),
+                   "1 name\n2 version\n3 list_commands\n4 boardsize 9\n5 clear_board\n6 genmove black\n");
+    }
+
+    #[test]
+    fn time_left() {
+        let mut p = proxy("=1 Engine\n\n=2 1.0\n\n=3 time_left\n\n=4\n\n", Vec::new());
+        assert!(p.gtp_time_left(api::Black, 0, 0).is_ok());
+        p.on_start();
+        assert!(p.gtp_time_left(api::Black, 300, 0).is_ok());
+        assert_eq!(sent(p).as_slice(), "1 name\n2 version\n3 list_commands\n4 time_left black 300 0\n");
+        // available through the rule, but never sent
+        let mut p = proxy("=1 Engine\n\n=2 1.0\n\n=3 genmove\n\n", vec!(super::Rule::parse("drop time_left").unwrap()));
+        assert!(p.gtp_time_left(api::Black, 0, 0).is_ok());
+        p.on_start();
+        assert!(p.gtp_time_left(api::White, 25, 3).is_ok());
+        assert_eq!(sent(p).as_slice(), "1 name\n2 version\n3 list_commands\n");
+    }
+
+    #[test]
+    fn version_1() {
+        let mut p = proxy("=1 Engine\n\n?2 unknown command\n\n?3 unknown command\n\n\
+                           =4 black\nwhite\ngenmove_black\ngenmove_white\nboardsize\n\n\
+                           =5\n\n=6\n\n=7\n\n=8 Q16\n\n", Vec::new());
+        p.on_start();
+        assert!(p.gtp_boardsize(9).is_ok());
+        p.gtp_clear_board();
+        assert!(p.gtp_play(api::ColouredMove{ player: api::Black, move: api::Pass }).is_ok());
+        assert_eq!(p.gtp_genmove(api::White), api::Stone(api::Vertex::from_str("Q16").unwrap()));
+        assert_eq!(sent(p).as_slice(), "1 name\n2 version\n3 list_commands\n4 help\n\
+                                        5 boardsize 9\n6 boardsize 9\n7 black pass\n8 genmove_white\n");
+    }
+
+    #[test]
+    fn probing() {
+        let mut p = proxy("=1 Engine\n\n=2\n\n=3 undo\n\n", Vec::new());
+        assert!(p.gtp_undo().is_ok());
+        assert!(p.gtp_final_score().is_err());
+        assert_eq!(sent(p).as_slice(), "1 name\n2 version\n3 list_commands\n");
+    }
+}
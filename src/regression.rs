@@ -0,0 +1,310 @@
+//! Runs GNU Go style regression suites (`.tst` files).
+//!
+//! A suite is a file of GTP commands, where numbered commands can be
+//! followed by the expected result:
+//!
+//! ```text
+//! loadsgf games/strategy1.sgf 12
+//! 1 reg_genmove white
+//! #? [C3|D4]
+//! 2 reg_genmove black
+//! #? [!pass]*
+//! ```
+//!
+//! The pattern between brackets is a regular expression which must match
+//! the whole response, `!` negates it, and a trailing `*` marks a test
+//! known to fail. Each test ends up as a pass, a failure, an expected
+//! failure or an unexpected pass.
+
+use std::fmt;
+use std::io;
+use std::io::{IoError, IoResult, File};
+use std::os;
+use regex::Regex;
+use time;
+use api;
+use bothandler;
+use client::{Client, EngineClient, IoFailure, CommandFailed, MalformedResponse};
+use parsing;
+
+/// Something answering GTP commands.
+pub trait Responder {
+    /// Sends a command line and returns the full response,
+    /// without the final empty line (ex: "=1 C3").
+    fn respond(&mut self, command: &str) -> IoResult<String>;
+}
+
+/// A bot answering through the same handler as `main_loop`.
+pub struct BotResponder<'a, T: 'a> {
+    bot: &'a mut T,
+    handler: bothandler::BotHandler
+}
+
+impl<'a, T: api::GoBot> BotResponder<'a, T> {
+    pub fn new(bot: &'a mut T) -> BotResponder<'a, T> {
+        let handler = bothandler::BotHandler::from_bot(bot);
+        BotResponder{ bot: bot, handler: handler }
+    }
+}
+
+impl<'a, T: api::GoBot> Responder for BotResponder<'a, T> {
+    fn respond(&mut self, command: &str) -> IoResult<String> {
+        let (_, result) = self.handler.handle_command(&mut *self.bot, command);
+        Ok(result)
+    }
+}
+
+/// An external engine, speaking GTP on its standard streams.
+pub struct ProcessEngine {
+    client: EngineClient
+}
+
+impl ProcessEngine {
+    /// Starts `program` with `args`, in the directory `cwd`.
+    /// A relative path to the program is taken from the current
+    /// directory, not from `cwd`.
+    pub fn spawn(program: &str, args: &[String], cwd: &Path) -> IoResult<ProcessEngine> {
+        let program = if program.contains_char('/') {
+            String::from_str(os::make_absolute(&Path::new(program)).as_str().unwrap_or(program))
+        } else {
+            // looked up in the PATH
+            String::from_str(program)
+        };
+        let client = try!(Client::spawn_in(program.as_slice(), args, cwd));
+        Ok(ProcessEngine{ client: client })
+    }
+}
+
+impl Responder for ProcessEngine {
+    fn respond(&mut self, command: &str) -> IoResult<String> {
+        // the client numbers the commands itself,
+        // the id of the suite is given back in the response
+        let (id, line) = match parsing::parse_command(command) {
+            Some(parsing::GTPCommand{id: id, command: name, args: args}) => {
+                let id = id.map(|i| format!("{}", i)).unwrap_or(String::new());
+                (id, if args.is_empty() { name } else { format!("{} {}", name, args) })
+            },
+            None => return Ok(String::new())
+        };
+        match self.client.send(line.as_slice()) {
+            Ok(response) => Ok(format!("{}{} {}", if response.success { "=" } else { "?" }, id, response.text)),
+            Err(IoFailure(e)) => Err(e),
+            Err(CommandFailed(text)) | Err(MalformedResponse(text)) =>
+                Err(IoError{ kind: io::OtherIoError, desc: "malformed response", detail: Some(text) })
+        }
+    }
+}
+
+impl Drop for ProcessEngine {
+    fn drop(&mut self) {
+        let _ = self.client.quit();
+    }
+}
+
+/// The expected result of a test.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Expectation {
+    pub pattern: String,
+    pub negated: bool,
+    /// The test is known to fail.
+    pub expected_failure: bool
+}
+
+impl Expectation {
+    // parses a `#? [pattern]*` line
+    fn parse(line: &str) -> Option<Expectation> {
+        let text = line.slice_from(2).trim();
+        if !text.starts_with("[") {
+            return None;
+        }
+        let close = match text.rfind(']') {
+            Some(i) => i,
+            None => return None
+        };
+        let mut pattern = text.slice(1, close);
+        let negated = pattern.starts_with("!");
+        if negated {
+            pattern = pattern.slice_from(1);
+        }
+        Some(Expectation{
+            pattern: String::from_str(pattern),
+            negated: negated,
+            expected_failure: text.slice_from(close + 1).trim().starts_with("*")
+        })
+    }
+
+    /// Checks a response, without its `=id` prefix, against the pattern.
+    /// Patterns which are not valid regular expressions are compared literally.
+    pub fn matches(&self, result: &str) -> bool {
+        let matched = match Regex::new(format!("^(?:{})$", self.pattern).as_slice()) {
+            Ok(re) => re.is_match(result),
+            Err(_) => result == self.pattern.as_slice()
+        };
+        matched != self.negated
+    }
+}
+
+/// A command of a suite, with its expected result if it is a test.
+#[deriving(PartialEq, Show)]
+pub struct TestCommand {
+    pub line: String,
+    pub id: Option<u32>,
+    pub expected: Option<Expectation>
+}
+
+/// Reads the commands of a suite.
+pub fn parse_suite(text: &str) -> Vec<TestCommand> {
+    let mut commands: Vec<TestCommand> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("#?") {
+            // applies to the previous command, if it has an id
+            match commands.mut_last() {
+                Some(cmd) if cmd.id.is_some() => cmd.expected = Expectation::parse(line),
+                _ => {}
+            }
+            continue;
+        }
+        match parsing::parse_command(line) {
+            Some(cmd) => commands.push(TestCommand{
+                line: String::from_str(line),
+                id: cmd.id,
+                expected: None
+            }),
+            None => {}
+        }
+    }
+    commands
+}
+
+#[deriving(PartialEq, Show)]
+pub enum Outcome {
+    Pass,
+    Failure,
+    ExpectedFailure,
+    UnexpectedPass
+}
+
+pub struct TestResult {
+    pub id: u32,
+    pub command: String,
+    pub expected: Expectation,
+    /// The response, without its `=id` prefix.
+    pub actual: String,
+    pub outcome: Outcome,
+    pub time_ns: u64
+}
+
+/// The results of a suite.
+pub struct Report {
+    pub name: String,
+    pub results: Vec<TestResult>,
+    pub time_ns: u64
+}
+
+impl Report {
+    pub fn count(&self, outcome: Outcome) -> uint {
+        self.results.iter().filter(|r| r.outcome == outcome).count()
+    }
+
+    /// True if no test failed unexpectedly.
+    pub fn success(&self) -> bool {
+        self.count(Failure) == 0
+    }
+}
+
+impl fmt::Show for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for r in self.results.iter() {
+            let pattern = format!("{}{}", if r.expected.negated { "!" } else { "" }, r.expected.pattern);
+            match r.outcome {
+                Failure => try!(write!(f, "{} {} unexpected FAIL: Correct '{}', got '{}'\n",
+                                       self.name, r.id, pattern, r.actual)),
+                UnexpectedPass => try!(write!(f, "{} {} unexpected PASS!\n", self.name, r.id)),
+                _ => {}
+            }
+        }
+        write!(f, "{}: {} tests, {} passed, {} failed, {} expected failures, {} unexpected passes ({:.2f}s)\n",
+               self.name, self.results.len(), self.count(Pass), self.count(Failure),
+               self.count(ExpectedFailure), self.count(UnexpectedPass), self.time_ns as f64 / 1e9)
+    }
+}
+
+// splits "=12 C3" into (true, "C3")
+fn split_response(response: &str) -> (bool, String) {
+    let success = response.starts_with("=");
+    let text = response.slice_from(if response.len() > 0 { 1 } else { 0 });
+    let text = text.trim_left_chars(|c: char| c.is_digit()).trim();
+    (success, String::from_str(text))
+}
+
+/// Runs the commands of a suite, checking the results of the tests.
+pub fn run_suite<R: Responder>(engine: &mut R, name: &str, commands: &[TestCommand]) -> IoResult<Report> {
+    let start = time::precise_time_ns();
+    let mut results = Vec::new();
+    for cmd in commands.iter() {
+        let cmd_start = time::precise_time_ns();
+        let response = try!(engine.respond(cmd.line.as_slice()));
+        let elapsed = time::precise_time_ns() - cmd_start;
+        match (cmd.id, &cmd.expected) {
+            (Some(id), &Some(ref expected)) => {
+                let (success, actual) = split_response(response.as_slice());
+                let passed = success && expected.matches(actual.as_slice());
+                results.push(TestResult{
+                    id: id,
+                    command: cmd.line.clone(),
+                    expected: expected.clone(),
+                    actual: actual,
+                    outcome: match (passed, expected.expected_failure) {
+                        (true, false) => Pass,
+                        (true, true) => UnexpectedPass,
+                        (false, false) => Failure,
+                        (false, true) => ExpectedFailure
+                    },
+                    time_ns: elapsed
+                });
+            },
+            _ => {}
+        }
+    }
+    Ok(Report{ name: String::from_str(name), results: results, time_ns: time::precise_time_ns() - start })
+}
+
+/// Runs a `.tst` file, the suite is named after the file.
+pub fn run_file<R: Responder>(engine: &mut R, path: &Path) -> IoResult<Report> {
+    let text = try!(File::open(path).read_to_string());
+    let name = path.filestem_str().unwrap_or("suite");
+    run_suite(engine, name, parse_suite(text.as_slice()).as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_suite() {
+        let suite = super::parse_suite("# a comment\nboardsize 9\n#? [ignored]\n\
+                                        1 reg_genmove white\n#? [C3|D4]\n2 reg_genmove black\n#? [!pass]*\n");
+        assert_eq!(suite.len(), 3);
+        assert_eq!(suite[0].expected, None);
+        assert_eq!(suite[1].id, Some(1));
+        assert_eq!(suite[1].expected, Some(super::Expectation{
+            pattern: String::from_str("C3|D4"), negated: false, expected_failure: false }));
+        assert_eq!(suite[2].expected, Some(super::Expectation{
+            pattern: String::from_str("pass"), negated: true, expected_failure: true }));
+    }
+
+    #[test]
+    fn expectation_matches() {
+        let alternatives = super::Expectation::parse("#? [C3|D4]").unwrap();
+        assert!(alternatives.matches("D4"));
+        assert!(!alternatives.matches("D45"));
+        let negated = super::Expectation::parse("#? [!pass]").unwrap();
+        assert!(negated.matches("C3"));
+        assert!(!negated.matches("pass"));
+    }
+
+    #[test]
+    fn split_response() {
+        assert_eq!(super::split_response("=12 C3"), (true, String::from_str("C3")));
+        assert_eq!(super::split_response("? unknown command"), (false, String::from_str("unknown command")));
+    }
+}
@@ -0,0 +1,196 @@
+//! Replays transcripts recorded with `GTPRUST_TRANSCRIPT` into a bot,
+//! to check that a new version of it still answers the same way.
+//!
+//! A typical regression test looks like:
+//!
+//! ```ignore
+//! let mut bot = MyBot::new();
+//! let mismatches = gtprust::replay::replay_transcript(&mut bot,
+//!     &Path::new("tests/kgs-game.transcript"), replay::DEFAULT_NONDETERMINISTIC).unwrap();
+//! for m in mismatches.iter() { println!("{}", m); }
+//! assert!(mismatches.is_empty());
+//! ```
+//!
+//! Nondeterministic responses still have to keep their status, id and
+//! number of lines: only their words are masked. The words of a response
+//! can also be masked one by one in the transcript, by changing them
+//! into `*`. Streamed responses, as the one of `lz-analyze`, are
+//! recorded and compared up to their first line.
+
+use std::fmt;
+use std::io::{IoResult, File, BufferedReader, Buffer};
+use api;
+use bothandler;
+use transcript;
+
+/// Commands whose responses are only compared on their status, id and
+/// number of lines by default, as they legitimately change between
+/// versions of a bot.
+pub static DEFAULT_NONDETERMINISTIC: &'static [&'static str] = &["genmove", "version", "lz-analyze"];
+
+/// A response of the bot which differs from the recorded one.
+pub struct Mismatch {
+    /// Position of the exchange in the transcript, starting at 1.
+    pub index: uint,
+    /// The raw input line.
+    pub input: String,
+    /// The response in the transcript.
+    pub expected: String,
+    /// The response given by the bot.
+    pub actual: String
+}
+
+impl fmt::Show for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "exchange {}: {}\n", self.index, self.input.as_slice().trim_right()));
+        for line in self.expected.as_slice().lines() {
+            try!(write!(f, "- {}\n", line));
+        }
+        for line in self.actual.as_slice().lines() {
+            try!(write!(f, "+ {}\n", line));
+        }
+        Ok(())
+    }
+}
+
+// the status and id, ex: "=12", and the number of lines
+fn same_shape(expected: &str, actual: &str) -> bool {
+    expected.words().next() == actual.words().next()
+        && expected.trim_right().lines().count() == actual.trim_right().lines().count()
+}
+
+// a line with words marked `*` is compared word by word,
+// trailing whitespace is not significant
+fn same_line(expected: &str, actual: &str) -> bool {
+    if !expected.words().any(|w| w == "*") {
+        return expected.trim_right() == actual.trim_right();
+    }
+    let expected_words: Vec<&str> = expected.words().collect();
+    let actual_words: Vec<&str> = actual.words().collect();
+    expected_words.len() == actual_words.len()
+        && expected_words.iter().zip(actual_words.iter()).all(|(e, a)| *e == "*" || e == a)
+}
+
+fn same_response(expected: &str, actual: &str) -> bool {
+    let expected_lines: Vec<&str> = expected.trim_right().lines().collect();
+    let actual_lines: Vec<&str> = actual.trim_right().lines().collect();
+    expected_lines.len() == actual_lines.len()
+        && expected_lines.iter().zip(actual_lines.iter()).all(|(e, a)| same_line(*e, *a))
+}
+
+/// Feeds every recorded input line to the bot through the same handler
+/// as `main_loop`, and returns the responses that differ from the transcript.
+/// Responses marked `out*` in the transcript, and responses to the commands
+/// listed in `nondeterministic`, are only compared on their status, id
+/// and number of lines.
+/// Stops at the first `quit`.
+pub fn replay_transcript<T: api::GoBot>(bot: &mut T, path: &Path,
+                                        nondeterministic: &[&str]) -> IoResult<Vec<Mismatch>> {
+    replay(bot, &mut BufferedReader::new(try!(File::open(path))), nondeterministic)
+}
+
+/// Same as `replay_transcript`, reading the transcript from `transcript`.
+pub fn replay<T: api::GoBot, B: Buffer>(bot: &mut T, transcript: &mut B,
+                                        nondeterministic: &[&str]) -> IoResult<Vec<Mismatch>> {
+    let exchanges = try!(transcript::read_exchanges(transcript));
+    let handler = bothandler::BotHandler::from_bot(bot);
+    let mut mismatches = Vec::new();
+    for (i, exchange) in exchanges.iter().enumerate() {
+        // a streamed response is stopped after its first line,
+        // as it was recorded
+        let mut header: Option<String> = None;
+        let (continue_loop, result) = handler.handle_command_streaming(bot, exchange.input.as_slice(), |line| {
+            header = Some(String::from_str(line));
+            false
+        });
+        let result = header.unwrap_or(result);
+        let masked = exchange.nondeterministic || match exchange.command {
+            Some(ref cmd) => nondeterministic.iter().any(|&c| c == cmd.as_slice()),
+            None => false
+        };
+        let same = if masked {
+            same_shape(exchange.response.as_slice(), result.as_slice())
+        } else {
+            same_response(exchange.response.as_slice(), result.as_slice())
+        };
+        if !same {
+            mismatches.push(Mismatch{
+                index: i + 1,
+                input: exchange.input.clone(),
+                expected: exchange.response.clone(),
+                actual: result
+            });
+        }
+        if !continue_loop {
+            break;
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use api;
+
+    // analyses until it is interrupted
+    struct Stub;
+
+    impl api::GoBot for Stub {
+        fn gtp_name(&self) -> String { String::from_str("Stub") }
+        fn gtp_version(&self) -> String { String::from_str("2.0") }
+        fn gtp_clear_board(&mut self) {}
+        fn gtp_komi(&mut self, _: f32) {}
+        fn gtp_boardsize(&mut self, _: uint) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_play(&mut self, _: api::ColouredMove) -> Result<(), api::GTPError> { Ok(()) }
+        fn gtp_genmove(&mut self, _: api::Colour) -> api::Move { api::Pass }
+        fn gtp_analyze(&mut self, _: api::Colour, report: |&str| -> bool) -> Result<(), api::GTPError> {
+            while report("info move D4 visits 1") {}
+            Ok(())
+        }
+        fn can_analyze(&self) -> bool { true }
+    }
+
+    // the records of an exchange, `out` being `out` or `out*`
+    fn record(id: uint, command: &str, args: &str, out: &str, response: &str) -> String {
+        format!("in\t2014-10-01T12:00:00Z\t{} {} {}\ncmd\t{}\t{}\t{}\n{}\t100\t{}\n",
+                id, command, args, id, command, args, out, response)
+    }
+
+    fn replay(text: &str, nondeterministic: &[&str]) -> Vec<super::Mismatch> {
+        super::replay(&mut Stub, &mut BufReader::new(text.as_bytes()), nondeterministic).unwrap()
+    }
+
+    #[test]
+    fn matching() {
+        let text = record(1, "name", "", "out", "=1 Stub")
+            .append(record(2, "play", "black D4", "out", "=2").as_slice())
+            .append(record(3, "lz-analyze", "black 10", "out", "=3 ").as_slice())
+            .append(record(4, "version", "", "out", "=4 1.0").as_slice());
+        assert!(replay(text.as_slice(), super::DEFAULT_NONDETERMINISTIC).is_empty());
+    }
+
+    #[test]
+    fn mismatch() {
+        let text = record(1, "name", "", "out", "=1 Other")
+            .append(record(2, "boardsize", "9", "out", "=2").as_slice());
+        let mismatches = replay(text.as_slice(), super::DEFAULT_NONDETERMINISTIC);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].index, 1);
+        assert_eq!(mismatches[0].actual.as_slice(), "=1 Stub");
+    }
+
+    #[test]
+    fn nondeterministic() {
+        let genmove = record(1, "genmove", "black", "out", "=1 D4");
+        assert!(replay(genmove.as_slice(), super::DEFAULT_NONDETERMINISTIC).is_empty());
+        assert_eq!(replay(genmove.as_slice(), []).len(), 1);
+        // marked words, or a marked response
+        assert!(replay(record(1, "genmove", "black", "out", "=1 *").as_slice(), []).is_empty());
+        assert!(replay(record(1, "genmove", "black", "out*", "=1 D4").as_slice(), []).is_empty());
+        // the status is still compared
+        assert_eq!(replay(record(1, "genmove", "black", "out*", "?1 illegal move").as_slice(), []).len(), 1);
+        assert_eq!(replay(record(1, "genmove", "black", "out", "?1 illegal move").as_slice(),
+                          super::DEFAULT_NONDETERMINISTIC).len(), 1);
+    }
+}
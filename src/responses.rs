@@ -0,0 +1,356 @@
+//! Parsers for the text of standard responses, turning engine answers
+//! back into `api` types. They accept what the library itself outputs
+//! as well as the usual variants of other engines (lowercase vertices,
+//! GNU Go boards), and return `None` for malformed answers.
+//!
+//! The `format_*` functions give the output of the library, the
+//! parsers accept it back.
+
+use std::ascii::StrAsciiExt;
+use api;
+use parsing;
+
+/// Parses a colour, `b`, `black`, `w` or `white`.
+pub fn parse_colour(text: &str) -> Option<api::Colour> {
+    parsing::arg_parse_colour(text.trim())
+}
+
+/// Parses a vertex, case insensitive (ex: `D4` or `d4`).
+pub fn parse_vertex(text: &str) -> Option<api::Vertex> {
+    parsing::arg_parse_vertex(text.trim().to_ascii_upper().as_slice())
+}
+
+/// Parses a move, as returned by `genmove`.
+pub fn parse_move(text: &str) -> Option<api::Move> {
+    parsing::arg_parse_move(text.trim().to_ascii_upper().as_slice())
+}
+
+/// Parses a stone status, `alive`, `dead` or `seki`.
+pub fn parse_stone_status(text: &str) -> Option<api::StoneStatus> {
+    parsing::arg_parse_stone_status(text.trim())
+}
+
+/// Parses the answer to `known_command`.
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None
+    }
+}
+
+/// Parses a list of vertices separated by spaces or newlines,
+/// as returned by `fixed_handicap` or `final_status_list`.
+/// An empty list is valid.
+pub fn parse_vertex_list(text: &str) -> Option<Vec<api::Vertex>> {
+    let mut vertices = Vec::new();
+    for word in text.words() {
+        match parse_vertex(word) {
+            Some(v) => vertices.push(v),
+            None => return None
+        }
+    }
+    Some(vertices)
+}
+
+/// Formats a list of vertices, separated by spaces.
+pub fn format_vertex_list(vertices: &[api::Vertex]) -> String {
+    let words: Vec<String> = vertices.iter().map(|v| v.to_string()).collect();
+    words.as_slice().connect(" ")
+}
+
+/// Parses the answer to `final_score` (ex: `W+3.5`, `B+12`).
+/// A draw is `0`, returned as `(0.0, White)`.
+pub fn parse_score(text: &str) -> Option<(f32, api::Colour)> {
+    let text = text.trim();
+    if text == "0" {
+        return Some((0.0, api::White));
+    }
+    let mut parts = text.splitn('+', 1);
+    let winner = match parts.next().unwrap().to_ascii_upper().as_slice() {
+        "B" => api::Black,
+        "W" => api::White,
+        _ => return None
+    };
+    match parts.next().and_then(|s| from_str::<f32>(s)) {
+        Some(x) if x.is_finite() && x >= 0.0 => Some((x, winner)),
+        _ => None
+    }
+}
+
+/// Formats a score the way `final_score` answers it.
+pub fn format_score(score: (f32, api::Colour)) -> String {
+    match score {
+        (0.0, _) => String::from_str("0"),
+        (x, api::White) => format!("W+{}", x),
+        (x, api::Black) => format!("B+{}", x)
+    }
+}
+
+/// A board, as described by `showboard`.
+#[deriving(PartialEq, Show)]
+pub struct Board {
+    pub size: uint,
+    pub black: Vec<api::Vertex>,
+    pub white: Vec<api::Vertex>,
+    /// Black stones captured by white, if the board tells it.
+    pub black_captured: Option<uint>,
+    /// White stones captured by black, if the board tells it.
+    pub white_captured: Option<uint>
+}
+
+impl Board {
+    /// The colour of the stone on `vertex`, if any.
+    pub fn at(&self, vertex: api::Vertex) -> Option<api::Colour> {
+        if self.black.contains(&vertex) {
+            Some(api::Black)
+        } else if self.white.contains(&vertex) {
+            Some(api::White)
+        } else {
+            None
+        }
+    }
+
+    /// The board as rows of intersections, from the top one (the
+    /// highest number) to the bottom one, each from A to the right.
+    pub fn grid(&self) -> Vec<Vec<Option<api::Colour>>> {
+        range(0, self.size).map(|n| {
+            range(0, self.size).map(|l| {
+                let vertex = api::Vertex::from_coords((l + 1) as u8, (self.size - n) as u8).unwrap();
+                self.at(vertex)
+            }).collect()
+        }).collect()
+    }
+}
+
+// "Captured stones : 3 by white and 2 by black." as drawn by the library,
+// "WHITE (O) has captured 3 stones" as drawn by GNU Go
+fn parse_captures(text: &str, board: &mut Board) {
+    for line in text.lines() {
+        let words: Vec<&str> = line.words().collect();
+        match words.as_slice().iter().position(|&w| w == "Captured") {
+            Some(i) if words.len() >= i + 9 => {
+                board.black_captured = from_str::<uint>(words[i + 3]);
+                board.white_captured = from_str::<uint>(words[i + 7]);
+            },
+            _ => {}
+        }
+        match words.as_slice().iter().position(|&w| w == "captured") {
+            Some(i) if i >= 3 && words.len() > i + 1 => {
+                let count = from_str::<uint>(words[i + 1]);
+                match words[i - 3] {
+                    "WHITE" => board.black_captured = count,
+                    "BLACK" => board.white_captured = count,
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Parses the answer to `showboard`: a row of column letters,
+/// and a row per line starting with its number, `X`/`B` being
+/// black stones, `O`/`W` white ones and `.`/`+` empty intersections.
+pub fn parse_board(text: &str) -> Option<Board> {
+    // the size is given by the letters row
+    let size = match text.lines().map(|l| l.words().collect::<Vec<&str>>())
+                         .find(|w| w.len() > 0 && w[0] == "A" && w.iter().all(|c| c.len() == 1)) {
+        Some(letters) => letters.len(),
+        None => return None
+    };
+    if size > 25 {
+        return None;
+    }
+    let mut board = Board{ size: size, black: Vec::new(), white: Vec::new(),
+                           black_captured: None, white_captured: None };
+    let mut seen = [false, ..25];
+    for line in text.lines() {
+        let words: Vec<&str> = line.words().collect();
+        let n = match words.as_slice().head().and_then(|w| from_str::<uint>(*w)) {
+            Some(n) => n,
+            None => continue
+        };
+        if n < 1 || n > size || seen[n - 1] || words.len() < size + 1 {
+            return None;
+        }
+        seen[n - 1] = true;
+        for (l, cell) in words.slice(1, size + 1).iter().enumerate() {
+            let vertex = api::Vertex::from_coords((l + 1) as u8, n as u8).unwrap();
+            match *cell {
+                "X" | "B" => board.black.push(vertex),
+                "O" | "W" => board.white.push(vertex),
+                "." | "+" => {},
+                _ => return None
+            }
+        }
+    }
+    if seen.slice_to(size).iter().any(|s| !*s) {
+        return None;
+    }
+    parse_captures(text, &mut board);
+    Some(board)
+}
+
+/// A candidate move of an analysis line, from `lz-analyze` or
+/// `kata-analyze`.
+#[deriving(PartialEq, Show)]
+pub struct MoveInfo {
+    pub move: api::Move,
+    pub visits: uint,
+    /// Win rate of the player to move, between 0 and 1.
+    pub winrate: f32,
+    /// Policy prior, between 0 and 1.
+    pub prior: Option<f32>,
+    /// Expected lead in points of the player to move (KataGo only).
+    pub score_lead: Option<f32>,
+    pub order: Option<uint>,
+    /// Principal variation, starting with the move.
+    pub pv: Vec<api::Move>
+}
+
+// win rates are fractions for KataGo, and in 1/10000 for Leela Zero
+fn parse_rate(text: &str) -> Option<f32> {
+    from_str::<f32>(text).map(|x| if text.contains(".") || x == 0.0 { x } else { x / 10000.0 })
+}
+
+/// Parses an analysis line, made of `info` entries (ex: `info move D4
+/// visits 120 winrate 5342 prior 1210 lcb 5100 order 0 pv D4 Q16`).
+/// Keys the parser does not know are skipped, and what follows
+/// `ownership` or `rootInfo` is ignored. Entries without a move,
+/// visits or win rate make the line invalid.
+pub fn parse_analysis(line: &str) -> Option<Vec<MoveInfo>> {
+    let words: Vec<&str> = line.words().collect();
+    let mut infos = Vec::new();
+    let mut i = 0;
+    while i < words.len() && words[i] == "info" {
+        i += 1;
+        let (mut move, mut visits, mut winrate) = (None, None, None);
+        let mut info = MoveInfo{ move: api::Pass, visits: 0, winrate: 0.0, prior: None, score_lead: None,
+                                 order: None, pv: Vec::new() };
+        while i < words.len() && words[i] != "info" {
+            let key = words[i];
+            i += 1;
+            if key == "ownership" || key == "ownershipStdev" || key == "rootInfo" || key == "movesOwnership" {
+                i = words.len();
+                break;
+            }
+            if key == "pv" {
+                while i < words.len() {
+                    match parse_move(words[i]) {
+                        Some(m) => info.pv.push(m),
+                        None => break
+                    }
+                    i += 1;
+                }
+                continue;
+            }
+            let value = match words.get(i) {
+                Some(v) => *v,
+                None => return None
+            };
+            match key {
+                "move" => move = parse_move(value),
+                "visits" => visits = from_str::<uint>(value),
+                "winrate" => winrate = parse_rate(value),
+                "prior" => info.prior = parse_rate(value),
+                "scoreLead" => info.score_lead = from_str::<f32>(value),
+                "scoreMean" if info.score_lead.is_none() => info.score_lead = from_str::<f32>(value),
+                "order" => info.order = from_str::<uint>(value),
+                _ => {
+                    // other keys, with any number of values
+                    while i < words.len() && from_str::<f64>(words[i]).is_some() {
+                        i += 1;
+                    }
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        match (move, visits, winrate) {
+            (Some(m), Some(v), Some(w)) => {
+                info.move = m;
+                info.visits = v;
+                info.winrate = w;
+            },
+            _ => return None
+        }
+        infos.push(info);
+    }
+    if i < words.len() {
+        return None;
+    }
+    Some(infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use api;
+    use boarddrawer;
+
+    fn v(text: &str) -> api::Vertex {
+        api::Vertex::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn parse_score() {
+        assert_eq!(super::parse_score("W+3.5"), Some((3.5, api::White)));
+        assert_eq!(super::parse_score("b+12"), Some((12.0, api::Black)));
+        assert_eq!(super::parse_score("0"), Some((0.0, api::White)));
+        assert_eq!(super::parse_score("W+"), None);
+        assert_eq!(super::parse_score("W+-2"), None);
+        assert_eq!(super::parse_score("X+2"), None);
+        assert_eq!(super::parse_score("W+R"), None);
+        for &score in [(0.0, api::Black), (6.5, api::White), (1.0, api::Black)].iter() {
+            let (x, c) = score;
+            let parsed = super::parse_score(super::format_score(score).as_slice()).unwrap();
+            assert!(parsed.val0() == x && (x == 0.0 || parsed.val1() == c));
+        }
+    }
+
+    #[test]
+    fn parse_vertex_list() {
+        assert_eq!(super::parse_vertex_list(""), Some(Vec::new()));
+        assert_eq!(super::parse_vertex_list("C3 g7\nD5"), Some(vec!(v("C3"), v("G7"), v("D5"))));
+        assert_eq!(super::parse_vertex_list("C3 pass"), None);
+        let list = [v("A1"), v("T19")];
+        assert_eq!(super::parse_vertex_list(super::format_vertex_list(list).as_slice()),
+                   Some(Vec::from_slice(list)));
+    }
+
+    #[test]
+    fn parse_board() {
+        let drawn = boarddrawer::draw_board(9, [v("C3"), v("J9")], [v("D4")], 2, 1);
+        let board = super::parse_board(drawn.as_slice()).unwrap();
+        assert_eq!(board, super::Board{ size: 9, black: vec!(v("J9"), v("C3")), white: vec!(v("D4")),
+                                        black_captured: Some(2), white_captured: Some(1) });
+        assert_eq!(board.at(v("D4")), Some(api::White));
+        assert_eq!(board.grid()[0][8], Some(api::Black));
+        let gnugo = "\n   A B C D E\n 5 . . . . . 5\n 4 . X . . . 4     WHITE (O) has captured 0 stones\n\
+                     \x20 3 . . + . . 3     BLACK (X) has captured 4 stones\n 2 . . O . . 2\n 1 . . . . . 1\n   A B C D E";
+        let board = super::parse_board(gnugo).unwrap();
+        assert_eq!(board.black, vec!(v("B4")));
+        assert_eq!(board.white, vec!(v("C2")));
+        assert_eq!((board.black_captured, board.white_captured), (Some(0), Some(4)));
+        // missing row, unknown cell
+        assert_eq!(super::parse_board("   A B\n 2 . .\n   A B"), None);
+        assert_eq!(super::parse_board("   A B\n 2 . ?\n 1 . .\n   A B"), None);
+    }
+
+    #[test]
+    fn parse_analysis() {
+        let lz = super::parse_analysis("info move D4 visits 120 winrate 5342 prior 1210 lcb 5100 order 0 pv D4 Q16 \
+                                        info move pass visits 3 winrate 100 order 1 pv pass").unwrap();
+        assert_eq!(lz.len(), 2);
+        assert_eq!(lz[0].move, api::Stone(v("D4")));
+        assert_eq!((lz[0].visits, lz[0].winrate, lz[0].order), (120, 0.5342, Some(0)));
+        assert_eq!(lz[0].pv, vec!(api::Stone(v("D4")), api::Stone(v("Q16"))));
+        assert_eq!(lz[1].move, api::Pass);
+        let kata = super::parse_analysis("info move C3 visits 7 utility -0.1 winrate 0.25 scoreMean -3.5 \
+                                          scoreLead -3.0 prior 0.5 order 0 pv C3 pvVisits 7 ownership 0.1 0.2").unwrap();
+        assert_eq!((kata[0].winrate, kata[0].prior, kata[0].score_lead), (0.25, Some(0.5), Some(-3.0)));
+        assert_eq!(super::parse_analysis(""), Some(Vec::new()));
+        assert_eq!(super::parse_analysis("info visits 3 winrate 0.5"), None);
+        assert_eq!(super::parse_analysis("= D4"), None);
+    }
+}
@@ -0,0 +1,298 @@
+//! Self-play games, generating training data.
+//!
+//! A single bot plays both colours. An engine can take part through
+//! `proxy::Proxy`. Each game starts with a few random moves, then each
+//! move is analysed with `gtp_analyze` before `gtp_genmove` is asked.
+//! Settings such as the temperature or the noise of the search are
+//! given as custom commands, sent before each game (ex: `kata-set-param
+//! chosenMoveTemperature 0.8`).
+//!
+//! Games are written as SGF, with a compact record of the analysed
+//! moves, one line per move:
+//!
+//! ```text
+//! B D4 0.5342 -1 D4:0.750 Q16:0.250
+//! ```
+//!
+//! giving the player and its move, the win rate of the player estimated
+//! by the search (`-` without analysis), the outcome for the player (1
+//! won, -1 lost, 0 draw or no result), and the share of the visits of
+//! each analysed move, the policy target.
+
+use std::io::{File, IoResult, Writer};
+use std::rand::{task_rng, Rng};
+use time;
+use api;
+use matchrunner::{GameResult, Resignation, Unknown};
+use responses;
+use sgf::SgfGame;
+
+/// The settings of self-play.
+#[deriving(Clone)]
+pub struct Settings {
+    pub boardsize: uint,
+    pub komi: f32,
+    /// Each game opens with a random number of random moves,
+    /// up to this one.
+    pub random_moves: uint,
+    /// Custom commands sent before each game, with their arguments.
+    pub commands: Vec<String>,
+    /// Analysis time of each move, in milliseconds, 0 to disable
+    /// the analysis.
+    pub analysis_time: u64,
+    /// Games reaching this number of moves have no result.
+    pub max_moves: uint,
+    pub games: uint,
+    /// Directory where the games are written.
+    pub output: Option<Path>
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings{ boardsize: 19, komi: 7.5, random_moves: 4, commands: Vec::new(), analysis_time: 1000,
+                  max_moves: 1000, games: 1, output: None }
+    }
+}
+
+/// A move chosen by the bot, with the statistics of its analysis.
+#[deriving(PartialEq, Show)]
+pub struct MoveRecord {
+    pub move: api::ColouredMove,
+    /// The analysed moves with their share of the visits.
+    pub policy: Vec<(api::Move, f32)>,
+    /// Win rate of the player of the move, estimated by the search.
+    pub value: Option<f32>,
+    /// 1 if the player of the move won, -1 if it lost, 0 otherwise.
+    pub outcome: int
+}
+
+impl MoveRecord {
+    /// The record as a line, see the module documentation.
+    pub fn to_line(&self) -> String {
+        let colour = match self.move.player { api::Black => "B", api::White => "W" };
+        let value = match self.value {
+            Some(v) => format!("{:.4f}", v),
+            None => String::from_str("-")
+        };
+        let mut line = format!("{} {} {} {}", colour, self.move.move.to_string(), value, self.outcome);
+        for &(move, share) in self.policy.iter() {
+            line.push_str(format!(" {}:{:.3f}", move.to_string(), share).as_slice());
+        }
+        line
+    }
+}
+
+/// A game of self-play.
+pub struct Game {
+    pub result: GameResult,
+    pub record: SgfGame,
+    /// The moves chosen by the bot, random moves excluded.
+    pub moves: Vec<MoveRecord>
+}
+
+fn opponent(colour: api::Colour) -> api::Colour {
+    match colour {
+        api::Black => api::White,
+        api::White => api::Black
+    }
+}
+
+// the latest analysis of the bot, until the deadline
+fn analyze<T: api::GoBot>(bot: &mut T, player: api::Colour, time_ms: u64) -> Vec<responses::MoveInfo> {
+    let deadline = time::precise_time_ns() + time_ms * 1000000;
+    let mut latest = Vec::new();
+    // bots without analysis give no statistics
+    let _ = bot.gtp_analyze(player, |line| {
+        match responses::parse_analysis(line) {
+            Some(infos) => latest = infos,
+            None => {}
+        }
+        time::precise_time_ns() < deadline
+    });
+    latest
+}
+
+// the visit distribution and the win rate of the best move
+fn statistics(infos: &[responses::MoveInfo]) -> (Vec<(api::Move, f32)>, Option<f32>) {
+    let total = infos.iter().fold(0, |sum, i| sum + i.visits);
+    let policy = if total == 0 {
+        Vec::new()
+    } else {
+        infos.iter().map(|i| (i.move, i.visits as f32 / total as f32)).collect()
+    };
+    let best = infos.iter().find(|i| i.order == Some(0)).or(infos.get(0));
+    (policy, best.map(|i| i.winrate))
+}
+
+// random moves on points not played yet, the bot refusing illegal ones
+fn play_opening<T: api::GoBot, R: Rng>(bot: &mut T, settings: &Settings, rng: &mut R, record: &mut SgfGame) {
+    let count = rng.gen_range(0, settings.random_moves + 1);
+    let mut to_play = api::Black;
+    for _ in range(0, count) {
+        let mut played = false;
+        for _ in range(0u, 10) {
+            let x = rng.gen_range(1, settings.boardsize + 1) as u8;
+            let y = rng.gen_range(1, settings.boardsize + 1) as u8;
+            let move = api::Stone(api::Vertex::from_coords(x, y).unwrap());
+            if record.moves.iter().any(|m| m.move.move == move) {
+                continue;
+            }
+            let coloured = api::ColouredMove{ player: to_play, move: move };
+            if bot.gtp_play(coloured).is_ok() {
+                record.push(coloured, Some(String::from_str("random opening move")));
+                played = true;
+                break;
+            }
+        }
+        if !played {
+            return;
+        }
+        to_play = opponent(to_play);
+    }
+}
+
+// the bot plays until the end of the game
+fn play_moves<T: api::GoBot>(bot: &mut T, settings: &Settings, record: &mut SgfGame,
+                             moves: &mut Vec<MoveRecord>) -> GameResult {
+    let mut to_play = if record.moves.len() % 2 == 0 { api::Black } else { api::White };
+    let mut last_was_pass = false;
+    loop {
+        if record.moves.len() >= settings.max_moves {
+            return Unknown;
+        }
+        let (policy, value) = if settings.analysis_time > 0 {
+            statistics(analyze(bot, to_play, settings.analysis_time).as_slice())
+        } else {
+            (Vec::new(), None)
+        };
+        let move = bot.gtp_genmove(to_play);
+        let coloured = api::ColouredMove{ player: to_play, move: move };
+        record.push(coloured, value.map(|v| format!("win rate {:.4f}", v)));
+        moves.push(MoveRecord{ move: coloured, policy: policy, value: value, outcome: 0 });
+        if move == api::Resign {
+            return Resignation(opponent(to_play));
+        }
+        if move == api::Pass && last_was_pass {
+            return match bot.gtp_final_score() {
+                Ok(score) => GameResult::from_score(score),
+                Err(_) => Unknown
+            };
+        }
+        last_was_pass = move == api::Pass;
+        to_play = opponent(to_play);
+    }
+}
+
+/// Plays a game. The bot must have been started (`on_start`).
+pub fn play_game<T: api::GoBot, R: Rng>(bot: &mut T, settings: &Settings, rng: &mut R) -> Result<Game, String> {
+    match bot.gtp_boardsize(settings.boardsize) {
+        Ok(()) => {},
+        Err(_) => return Err(format!("board size {} refused", settings.boardsize))
+    }
+    bot.gtp_clear_board();
+    bot.on_new_game();
+    bot.gtp_komi(settings.komi);
+    for line in settings.commands.iter() {
+        let mut parts = line.as_slice().splitn(' ', 1);
+        let command = parts.next().unwrap();
+        let args = parts.next().unwrap_or("");
+        match bot.gtp_custom_command(command, args) {
+            (true, _) => {},
+            (false, message) => return Err(format!("{} failed: {}", line, message))
+        }
+    }
+    let mut record = SgfGame::new(settings.boardsize, settings.komi);
+    let name = format!("{} {}", bot.gtp_name(), bot.gtp_version());
+    record.black_name = Some(String::from_str(name.as_slice().trim()));
+    record.white_name = record.black_name.clone();
+    play_opening(bot, settings, rng, &mut record);
+
+    let mut moves = Vec::new();
+    let result = play_moves(bot, settings, &mut record, &mut moves);
+    bot.on_game_over();
+    let winner = result.winner();
+    for i in range(0, moves.len()) {
+        let m = moves.get_mut(i);
+        m.outcome = match winner {
+            Some(c) if c == m.move.player => 1,
+            Some(_) => -1,
+            None => 0
+        };
+    }
+    record.result = Some(result.to_string());
+    Ok(Game{ result: result, record: record, moves: moves })
+}
+
+fn write_game(dir: &Path, number: uint, game: &Game) -> IoResult<()> {
+    try!(File::create(&dir.join(format!("game-{:05u}.sgf", number).as_slice()))
+         .write_str(game.record.to_sgf().as_slice()));
+    let mut file = try!(File::create(&dir.join(format!("game-{:05u}.moves", number).as_slice())));
+    for m in game.moves.iter() {
+        try!(file.write_line(m.to_line().as_slice()));
+    }
+    Ok(())
+}
+
+/// Starts the bot and plays the games, writing them to `game-NNNNN.sgf`
+/// and `game-NNNNN.moves` in the output directory, if any.
+/// `report` is called after each game, with its number.
+pub fn run<T: api::GoBot>(bot: &mut T, settings: &Settings, report: |uint, &Game|) -> Result<(), String> {
+    bot.on_start();
+    let mut rng = task_rng();
+    for i in range(0, settings.games) {
+        let mut game = try!(play_game(bot, settings, &mut rng));
+        game.record.date = Some(time::strftime("%Y-%m-%d", &time::now()));
+        match settings.output {
+            Some(ref dir) => try!(write_game(dir, i + 1, &game)
+                                  .map_err(|e| format!("cannot write game {}: {}", i + 1, e))),
+            None => {}
+        }
+        report(i + 1, &game);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rand::{SeedableRng, XorShiftRng};
+    use api;
+    use api::GoBot;
+    use matchrunner;
+    use mock;
+
+    fn stone(text: &str) -> api::Move {
+        api::Stone(api::Vertex::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn to_line() {
+        let record = super::MoveRecord{ move: api::ColouredMove{ player: api::White, move: stone("D4") },
+                                        policy: vec!((stone("D4"), 0.75), (api::Pass, 0.25)),
+                                        value: Some(0.53421), outcome: -1 };
+        assert_eq!(record.to_line().as_slice(), "W D4 0.5342 -1 D4:0.750 pass:0.250");
+    }
+
+    #[test]
+    fn play_game() {
+        let script = "genmove = D4\ngenmove = Q16\ngenmove = pass\nfinal_score = W+3.5\nlz-setoption = \n\
+                      lz-analyze = info move D4 visits 30 winrate 0.6 order 0 pv D4 \
+                      info move Q16 visits 10 winrate 0.5 order 1 pv Q16\n";
+        let mut bot = mock::MockEngine::new(mock::Script::parse(script).unwrap());
+        bot.on_start();
+        let mut settings = super::Settings::new();
+        settings.boardsize = 9;
+        settings.random_moves = 0;
+        settings.commands = vec!(String::from_str("lz-setoption name temperature value 1"));
+        let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+        let game = super::play_game(&mut bot, &settings, &mut rng).unwrap();
+        assert_eq!(game.result, matchrunner::Score(api::White, 3.5));
+        assert_eq!(game.record.moves.len(), 4);
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.moves[0], super::MoveRecord{ move: api::ColouredMove{ player: api::Black, move: stone("D4") },
+                                                     policy: vec!((stone("D4"), 0.75), (stone("Q16"), 0.25)),
+                                                     value: Some(0.6), outcome: -1 });
+        assert_eq!(game.moves[3].outcome, 1);
+        settings.commands = vec!(String::from_str("unknown-command"));
+        assert!(super::play_game(&mut bot, &settings, &mut rng).is_err());
+    }
+}
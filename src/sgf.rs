@@ -0,0 +1,329 @@
+//! Games in the SGF format (FF[4]), as written by the match runner
+//! and read by the opening book.
+
+use api;
+
+/// A move of a game, with an optional comment.
+#[deriving(PartialEq, Show)]
+pub struct SgfMove {
+    pub move: api::ColouredMove,
+    pub comment: Option<String>
+}
+
+/// A game without variations.
+#[deriving(PartialEq, Show)]
+pub struct SgfGame {
+    pub size: uint,
+    pub komi: f32,
+    /// Black stones placed before the first move.
+    pub handicap: Vec<api::Vertex>,
+    pub black_name: Option<String>,
+    pub white_name: Option<String>,
+    /// Ex: `B+3.5`, `W+R`, `0`, `Void`.
+    pub result: Option<String>,
+    /// Ex: `2014-09-21`.
+    pub date: Option<String>,
+    /// Comment of the root node.
+    pub comment: Option<String>,
+    pub moves: Vec<SgfMove>
+}
+
+// `]` and `\` must be escaped in property values
+fn escape(text: &str) -> String {
+    let mut output = String::new();
+    for c in text.chars() {
+        if c == ']' || c == '\\' {
+            output.push_char('\\');
+        }
+        output.push_char(c);
+    }
+    output
+}
+
+// columns from the left, rows from the top
+fn point(vertex: api::Vertex, size: uint) -> String {
+    let (x, y) = vertex.to_coords();
+    let row = size as u8 - y;
+    format!("{:c}{:c}", ('a' as u8 + x - 1) as char, ('a' as u8 + row) as char)
+}
+
+// the values of the properties of a node
+type Node = Vec<(String, Vec<String>)>;
+
+struct Parser {
+    chars: Vec<char>,
+    position: uint
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<char> {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() {
+            self.position += 1;
+        }
+        self.chars.as_slice().get(self.position).map(|c| *c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}', found the end of the file", expected))
+        }
+    }
+
+    // the main line of a game tree, following the first variations
+    fn tree(&mut self) -> Result<Vec<Node>, String> {
+        try!(self.expect('('));
+        let mut nodes = Vec::new();
+        while self.peek() == Some(';') {
+            self.position += 1;
+            nodes.push(try!(self.node()));
+        }
+        let mut first = true;
+        while self.peek() == Some('(') {
+            let variation = try!(self.tree());
+            if first {
+                nodes.push_all(variation.as_slice());
+                first = false;
+            }
+        }
+        try!(self.expect(')'));
+        Ok(nodes)
+    }
+
+    fn node(&mut self) -> Result<Node, String> {
+        let mut properties = Vec::new();
+        loop {
+            match self.peek() {
+                Some(c) if c.is_uppercase() => {},
+                _ => return Ok(properties)
+            }
+            // lower case letters of FF[3] names are ignored
+            let mut name = String::new();
+            while self.position < self.chars.len() && self.chars[self.position].is_alphabetic() {
+                if self.chars[self.position].is_uppercase() {
+                    name.push_char(self.chars[self.position]);
+                }
+                self.position += 1;
+            }
+            let mut values = Vec::new();
+            while self.peek() == Some('[') {
+                values.push(try!(self.value()));
+            }
+            if values.is_empty() {
+                return Err(format!("property {} without value", name));
+            }
+            properties.push((name, values));
+        }
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        self.position += 1;
+        let mut text = String::new();
+        loop {
+            match self.chars.as_slice().get(self.position) {
+                None => return Err(String::from_str("unterminated property value")),
+                Some(&']') => {
+                    self.position += 1;
+                    return Ok(text);
+                },
+                Some(&'\\') => {
+                    self.position += 1;
+                    match self.chars.as_slice().get(self.position) {
+                        Some(&c) => text.push_char(c),
+                        None => {}
+                    }
+                },
+                Some(&c) => text.push_char(c)
+            }
+            self.position += 1;
+        }
+    }
+}
+
+// the inverse of `point`, empty values and `tt` being passes
+fn parse_point(text: &str, size: uint) -> Option<api::Move> {
+    let bytes = text.as_bytes();
+    if bytes.is_empty() || (text == "tt" && size <= 19) {
+        return Some(api::Pass);
+    }
+    if bytes.len() != 2 || bytes[0] < ('a' as u8) || bytes[1] < ('a' as u8) {
+        return None;
+    }
+    let (column, row) = ((bytes[0] - 'a' as u8) as uint, (bytes[1] - 'a' as u8) as uint);
+    if column >= size || row >= size {
+        return None;
+    }
+    api::Vertex::from_coords(column as u8 + 1, (size - row) as u8).map(|v| api::Stone(v))
+}
+
+fn game_of(nodes: &[Node]) -> Result<SgfGame, String> {
+    let mut game = SgfGame::new(19, 0.0);
+    let root = match nodes.get(0) {
+        Some(node) => node,
+        None => return Err(String::from_str("game without nodes"))
+    };
+    for &(ref name, ref values) in root.iter() {
+        let value = values[0].as_slice();
+        match name.as_slice() {
+            "SZ" => match from_str::<uint>(value.splitn(':', 1).next().unwrap().trim()) {
+                Some(size) if size >= 1 && size <= 25 => game.size = size,
+                _ => return Err(format!("unsupported board size {}", value))
+            },
+            "KM" => game.komi = from_str::<f32>(value.trim()).unwrap_or(0.0),
+            "PB" => game.black_name = Some(String::from_str(value)),
+            "PW" => game.white_name = Some(String::from_str(value)),
+            "RE" => game.result = Some(String::from_str(value)),
+            "DT" => game.date = Some(String::from_str(value)),
+            "C" => game.comment = Some(String::from_str(value)),
+            _ => {}
+        }
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        let mut comment = None;
+        let mut moves = Vec::new();
+        for &(ref name, ref values) in node.iter() {
+            let player = match name.as_slice() {
+                "B" => api::Black,
+                "W" => api::White,
+                "C" if i > 0 => {
+                    comment = Some(values[0].clone());
+                    continue;
+                },
+                "AB" if i == 0 => {
+                    for value in values.iter() {
+                        match parse_point(value.as_slice(), game.size) {
+                            Some(api::Stone(v)) => game.handicap.push(v),
+                            _ => return Err(format!("invalid point {}", value))
+                        }
+                    }
+                    continue;
+                },
+                _ => continue
+            };
+            match parse_point(values[0].as_slice(), game.size) {
+                Some(move) => moves.push(api::ColouredMove{ player: player, move: move }),
+                None => return Err(format!("invalid move {}[{}]", name, values[0]))
+            }
+        }
+        for &m in moves.iter() {
+            game.push(m, comment.clone());
+        }
+    }
+    Ok(game)
+}
+
+/// Reads the games of an SGF collection, following the first variation
+/// of each. Setup properties other than black stones in the root node
+/// (handicap) are ignored.
+pub fn read_collection(text: &str) -> Result<Vec<SgfGame>, String> {
+    let mut parser = Parser{ chars: text.chars().collect(), position: 0 };
+    let mut games = Vec::new();
+    while parser.peek() == Some('(') {
+        let nodes = try!(parser.tree());
+        games.push(try!(game_of(nodes.as_slice())));
+    }
+    match parser.peek() {
+        Some(c) => Err(format!("unexpected '{}' after the games", c)),
+        None if games.is_empty() => Err(String::from_str("no game found")),
+        None => Ok(games)
+    }
+}
+
+impl SgfGame {
+    /// An empty game.
+    pub fn new(size: uint, komi: f32) -> SgfGame {
+        SgfGame{ size: size, komi: komi, handicap: Vec::new(), black_name: None, white_name: None,
+                 result: None, date: None, comment: None, moves: Vec::new() }
+    }
+
+    /// Adds a move at the end of the game.
+    pub fn push(&mut self, move: api::ColouredMove, comment: Option<String>) {
+        self.moves.push(SgfMove{ move: move, comment: comment });
+    }
+
+    /// The game in SGF, ending with a newline.
+    pub fn to_sgf(&self) -> String {
+        let mut output = format!("(;FF[4]GM[1]CA[UTF-8]AP[gtp-rust]SZ[{}]KM[{}]", self.size, self.komi);
+        let properties = [("PB", &self.black_name), ("PW", &self.white_name), ("RE", &self.result),
+                          ("DT", &self.date), ("C", &self.comment)];
+        for &(name, value) in properties.iter() {
+            match *value {
+                Some(ref text) => output.push_str(format!("{}[{}]", name, escape(text.as_slice())).as_slice()),
+                None => {}
+            }
+        }
+        if self.handicap.len() > 0 {
+            output.push_str(format!("HA[{}]AB", self.handicap.len()).as_slice());
+            for &v in self.handicap.iter() {
+                output.push_str(format!("[{}]", point(v, self.size)).as_slice());
+            }
+        }
+        output.push_char('\n');
+        for m in self.moves.iter() {
+            let colour = match m.move.player { api::Black => "B", api::White => "W" };
+            let position = match m.move.move {
+                api::Stone(v) => point(v, self.size),
+                api::Pass => String::new(),
+                // resignations are only given by the result
+                api::Resign => continue
+            };
+            output.push_str(format!(";{}[{}]", colour, position).as_slice());
+            match m.comment {
+                Some(ref text) => output.push_str(format!("C[{}]", escape(text.as_slice())).as_slice()),
+                None => {}
+            }
+            output.push_char('\n');
+        }
+        output.push_str(")\n");
+        output
+    }
+
+    /// Reads the first game of an SGF collection, see `read_collection`.
+    pub fn from_sgf(text: &str) -> Result<SgfGame, String> {
+        read_collection(text).map(|mut games| games.remove(0).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api;
+
+    #[test]
+    fn to_sgf() {
+        let mut game = super::SgfGame::new(9, 6.5);
+        game.black_name = Some(String::from_str("GNU Go"));
+        game.result = Some(String::from_str("W+R"));
+        game.handicap = vec!(api::Vertex::from_str("C3").unwrap());
+        game.push(api::ColouredMove{ player: api::White, move: api::Stone(api::Vertex::from_str("J9").unwrap()) },
+                  Some(String::from_str("a [comment]")));
+        game.push(api::ColouredMove{ player: api::Black, move: api::Pass }, None);
+        game.push(api::ColouredMove{ player: api::White, move: api::Resign }, None);
+        assert_eq!(game.to_sgf().as_slice(),
+                   "(;FF[4]GM[1]CA[UTF-8]AP[gtp-rust]SZ[9]KM[6.5]PB[GNU Go]RE[W+R]HA[1]AB[cg]\n\
+                    ;W[ia]C[a [comment\\]]\n;B[]\n)\n");
+    }
+
+    #[test]
+    fn read_collection() {
+        let mut game = super::SgfGame::new(9, 6.5);
+        game.white_name = Some(String::from_str("Leela [Zero]"));
+        game.handicap = vec!(api::Vertex::from_str("C3").unwrap(), api::Vertex::from_str("G7").unwrap());
+        game.push(api::ColouredMove{ player: api::White, move: api::Stone(api::Vertex::from_str("J9").unwrap()) },
+                  Some(String::from_str("first")));
+        game.push(api::ColouredMove{ player: api::Black, move: api::Pass }, None);
+        assert_eq!(super::SgfGame::from_sgf(game.to_sgf().as_slice()), Ok(game));
+        let games = super::read_collection("(;SZ[19]\n;B[pd](;W[dp];B[tt])(;W[dd]))\n(;GM[1]FF[3]SiZe[13];W[aa])")
+            .unwrap();
+        assert_eq!(games.len(), 2);
+        let moves: Vec<String> = games[0].moves.iter().map(|m| m.move.to_string()).collect();
+        assert_eq!(moves, vec!(String::from_str("blackQ16"), String::from_str("whiteD4"),
+                               String::from_str("blackpass")));
+        assert_eq!(games[1].size, 13);
+        assert!(super::read_collection("(;SZ[9];B[zz])").is_err());
+        assert!(super::read_collection("(;C[unterminated)").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+// Catches SIGINT and SIGTERM, so that the main loop can
+// run the normal quit path instead of being killed.
+// The handler only sets a flag, the loop polls it.
+
+use std::sync::atomic::{AtomicInt, INIT_ATOMIC_INT, SeqCst};
+
+// number of the last signal received, 0 if none
+static RECEIVED: AtomicInt = INIT_ATOMIC_INT;
+
+#[cfg(unix)]
+mod imp {
+    use libc::{c_int, size_t};
+    use std::sync::atomic::SeqCst;
+
+    static SIGINT: c_int = 2;
+    static SIGTERM: c_int = 15;
+
+    extern {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> size_t;
+    }
+
+    extern "C" fn on_signal(signum: c_int) {
+        super::RECEIVED.store(signum as int, SeqCst);
+    }
+
+    pub fn install() {
+        unsafe {
+            signal(SIGINT, on_signal);
+            signal(SIGTERM, on_signal);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub fn install() {}
+}
+
+pub fn install_handlers() {
+    imp::install();
+}
+
+// the number of the termination signal received, if any
+pub fn termination_requested() -> Option<int> {
+    match RECEIVED.load(SeqCst) {
+        0 => None,
+        signum => Some(signum)
+    }
+}
@@ -0,0 +1,222 @@
+//! Sequential probability ratio test between two engines, as fishtest does.
+//!
+//! A candidate plays a baseline, alternating colours, until the test
+//! decides between H0 (the candidate is `elo0` stronger than the
+//! baseline) and H1 (it is `elo1` stronger). With `elo0 = 0` and
+//! `elo1 > 0`, accepting H1 means the candidate is an improvement; with
+//! `elo0 < 0 = elo1`, accepting H1 means it is not a regression.
+//!
+//! The log-likelihood ratio is the generalized one of fishtest, from
+//! the mean and variance of the game scores (1 for a win, 0.5 for a
+//! draw), with logistic Elo. Half a draw is added to the games, so that
+//! a candidate winning or losing all its games still gets a verdict.
+//! The test stops when it leaves the bounds `ln(beta / (1 - alpha))`
+//! and `ln((1 - beta) / alpha)`.
+
+use std::io::{File, Buffer, Writer};
+use api;
+use client::{ClientResult, IoFailure};
+use matchrunner;
+
+/// The hypotheses and error rates of a test.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Settings {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// Probability of accepting H1 when H0 holds.
+    pub alpha: f64,
+    /// Probability of accepting H0 when H1 holds.
+    pub beta: f64,
+    /// Games after which the test stops without a verdict, if any.
+    pub max_games: Option<uint>
+}
+
+impl Settings {
+    /// elo0 = 0, elo1 = 5, alpha = beta = 0.05, without game limit.
+    pub fn new() -> Settings {
+        Settings{ elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05, max_games: None }
+    }
+
+    /// The lower and upper bounds of the log-likelihood ratio.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+/// The result of a test.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Verdict {
+    /// H1 accepted: the candidate passes.
+    AcceptH1,
+    /// H0 accepted: the candidate fails.
+    AcceptH0,
+    /// The game limit was reached first.
+    Inconclusive
+}
+
+/// Games counted from the point of view of the candidate.
+/// Void games are not counted.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Status {
+    pub wins: uint,
+    pub draws: uint,
+    pub losses: uint,
+    pub llr: f64
+}
+
+// expected score of a player `elo` stronger than its opponent
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10.0f64.powf(-elo / 400.0))
+}
+
+/// The log-likelihood ratio of H1 against H0 after these games.
+pub fn llr(wins: uint, draws: uint, losses: uint, elo0: f64, elo1: f64) -> f64 {
+    let n = (wins + draws + losses) as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    // half a draw more, or the variance is 0 while all the games have
+    // the same result
+    let total = n + 0.5;
+    let (w, d, l) = (wins as f64 / total, (draws as f64 + 0.5) / total, losses as f64 / total);
+    let mean = w + d / 2.0;
+    let variance = w * (1.0 - mean).powi(2) + d * (0.5 - mean).powi(2) + l * mean.powi(2);
+    if variance == 0.0 {
+        // only draws
+        return 0.0;
+    }
+    let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+    n * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+}
+
+impl Status {
+    pub fn new() -> Status {
+        Status{ wins: 0, draws: 0, losses: 0, llr: 0.0 }
+    }
+
+    pub fn games(&self) -> uint {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Counts a game of the candidate `id` and updates the ratio.
+    pub fn add(&mut self, game: &matchrunner::Game, id: uint, settings: &Settings) {
+        let winner = match game.result {
+            matchrunner::Unknown => return,
+            _ => match game.result.winner() {
+                Some(api::Black) => Some(game.black),
+                Some(api::White) => Some(game.white),
+                None => None
+            }
+        };
+        match winner {
+            None => self.draws += 1,
+            Some(w) if w == id => self.wins += 1,
+            Some(_) => self.losses += 1
+        }
+        self.llr = llr(self.wins, self.draws, self.losses, settings.elo0, settings.elo1);
+    }
+
+    /// The verdict, if the ratio left the bounds or the game limit is reached.
+    pub fn verdict(&self, settings: &Settings) -> Option<Verdict> {
+        let (lower, upper) = settings.bounds();
+        if self.llr >= upper {
+            Some(AcceptH1)
+        } else if self.llr <= lower {
+            Some(AcceptH0)
+        } else if settings.max_games.map_or(false, |m| self.games() >= m) {
+            Some(Inconclusive)
+        } else {
+            None
+        }
+    }
+}
+
+/// Plays games between `candidate` and `baseline` until a verdict,
+/// the candidate taking black in the odd games. `report` is called
+/// after each game, with its number and the updated status.
+/// Games are written to `game-NNN.sgf` in the output directory, if any;
+/// the number of games and the colour alternation of the match settings
+/// are ignored.
+pub fn run<R: Buffer, W: Writer>(candidate: &mut matchrunner::Player<R, W>, baseline: &mut matchrunner::Player<R, W>,
+                                 mut referee: Option<&mut matchrunner::Player<R, W>>,
+                                 settings: &matchrunner::Settings, test: &Settings,
+                                 report: |uint, &matchrunner::Game, &Status|) -> ClientResult<(Verdict, Status)> {
+    let mut status = Status::new();
+    let mut number = 0u;
+    loop {
+        match status.verdict(test) {
+            Some(v) => return Ok((v, status)),
+            None => {}
+        }
+        let referee = match referee {
+            Some(ref mut r) => Some(&mut **r),
+            None => None
+        };
+        let game = if number % 2 == 1 {
+            try!(matchrunner::play_game(baseline, candidate, referee, settings))
+        } else {
+            try!(matchrunner::play_game(candidate, baseline, referee, settings))
+        };
+        number += 1;
+        match settings.output {
+            Some(ref dir) => {
+                let path = dir.join(format!("game-{:03u}.sgf", number));
+                try!(File::create(&path).write_str(game.record.to_sgf().as_slice()).map_err(IoFailure));
+            },
+            None => {}
+        }
+        status.add(&game, candidate.id, test);
+        report(number, &game, &status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn bounds() {
+        let (lower, upper) = super::Settings::new().bounds();
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+    }
+
+    #[test]
+    fn llr() {
+        assert_eq!(super::llr(0, 0, 0, 0.0, 5.0), 0.0);
+        assert!(super::llr(3, 0, 0, 0.0, 5.0) > 0.0);
+        assert!(super::llr(0, 0, 3, 0.0, 5.0) < 0.0);
+        assert_eq!(super::llr(0, 4, 0, 0.0, 5.0), 0.0);
+        // even results favour H0, better ones H1
+        assert!(super::llr(50, 20, 50, 0.0, 5.0) < 0.0);
+        assert!(super::llr(60, 20, 40, 0.0, 5.0) > 0.0);
+        // symmetric hypotheses around even results
+        assert!(super::llr(50, 0, 50, -5.0, 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn verdict() {
+        let mut settings = super::Settings::new();
+        settings.max_games = Some(100);
+        let mut status = super::Status{ wins: 620, draws: 0, losses: 380, llr: 0.0 };
+        status.llr = super::llr(status.wins, status.draws, status.losses, settings.elo0, settings.elo1);
+        assert_eq!(status.verdict(&settings), Some(super::AcceptH1));
+        status = super::Status{ wins: 400, draws: 0, losses: 600, llr: 0.0 };
+        status.llr = super::llr(status.wins, status.draws, status.losses, settings.elo0, settings.elo1);
+        assert_eq!(status.verdict(&settings), Some(super::AcceptH0));
+        status = super::Status{ wins: 5, draws: 0, losses: 5, llr: 0.0 };
+        assert_eq!(status.verdict(&settings), None);
+        status.wins = 95;
+        assert_eq!(status.verdict(&settings), Some(super::Inconclusive));
+    }
+
+    #[test]
+    fn same_results() {
+        // draws are rare in Go: all wins or all losses must conclude
+        let settings = super::Settings::new();
+        let mut status = super::Status{ wins: 10, draws: 0, losses: 0, llr: 0.0 };
+        status.llr = super::llr(status.wins, status.draws, status.losses, settings.elo0, settings.elo1);
+        assert_eq!(status.verdict(&settings), Some(super::AcceptH1));
+        status = super::Status{ wins: 0, draws: 0, losses: 10, llr: 0.0 };
+        status.llr = super::llr(status.wins, status.draws, status.losses, settings.elo0, settings.elo1);
+        assert_eq!(status.verdict(&settings), Some(super::AcceptH0));
+    }
+}
@@ -0,0 +1,465 @@
+//! Supervision of an engine: command timeouts, detection of crashes
+//! and malformed output, automatic restart.
+//!
+//! A `Supervisor` keeps track of the game given to its engine (board
+//! size, komi, time settings, handicap and moves). When a command times
+//! out, when the engine exits or answers something which is not a GTP
+//! response, the engine is killed and started again, the game is
+//! replayed into it, and the command is sent once more. Refused
+//! commands (`?` responses) are returned as is.
+//!
+//! Engines are usually processes (`Supervisor::spawn`), any `Starter`
+//! can be given to `Supervisor::new` instead.
+//!
+//! ```ignore
+//! let mut engine = try!(Supervisor::spawn("gnugo", ["--mode".to_string(), "gtp".to_string()]));
+//! engine.timeout = Some(5000);
+//! engine.set_command_timeout("genmove", 60000);
+//! try!(engine.command("boardsize 9"));
+//! let move = try!(engine.command("genmove black"));
+//! ```
+
+use std::comm::Select;
+use std::cmp;
+use std::io;
+use std::io::{IoError, IoResult, Timer};
+use std::io::pipe::PipeStream;
+use std::io::process::{Command, InheritFd};
+use api;
+use client::{Client, ClientResult, CommandFailed, IoFailure};
+use diagnostics;
+use responses;
+
+/// A reader of the output of an engine, failing with `io::TimedOut`
+/// when nothing comes for `timeout` milliseconds.
+/// The stream is read by a task of its own.
+pub struct TimedReader {
+    rx: Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    position: uint,
+    timer: Timer,
+    /// `None` waits forever.
+    pub timeout: Option<u64>
+}
+
+impl TimedReader {
+    pub fn new<R: Reader + Send>(reader: R) -> IoResult<TimedReader> {
+        let (tx, rx) = channel();
+        spawn(proc() {
+            let mut reader = reader;
+            let mut chunk = [0u8, ..4096];
+            loop {
+                match reader.read(chunk) {
+                    Ok(n) => if tx.send_opt(Vec::from_slice(chunk.slice_to(n))).is_err() {
+                        break;
+                    },
+                    Err(_) => break
+                }
+            }
+        });
+        Ok(TimedReader{ rx: rx, buffer: Vec::new(), position: 0, timer: try!(Timer::new()), timeout: None })
+    }
+
+    fn receive(&mut self) -> IoResult<Vec<u8>> {
+        let ms = match self.timeout {
+            Some(ms) => ms,
+            None => return self.rx.recv_opt().map_err(|_| io::standard_error(io::EndOfFile))
+        };
+        let expiry = self.timer.oneshot(ms);
+        let select = Select::new();
+        let mut data = select.handle(&self.rx);
+        let mut expired = select.handle(&expiry);
+        unsafe {
+            data.add();
+            expired.add();
+        }
+        if select.wait() == data.id() {
+            data.recv_opt().map_err(|_| io::standard_error(io::EndOfFile))
+        } else {
+            Err(IoError{ kind: io::TimedOut, desc: "no response from the engine", detail: None })
+        }
+    }
+}
+
+impl Reader for TimedReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        let n = {
+            let available = try!(self.fill_buf());
+            let n = cmp::min(buf.len(), available.len());
+            for i in range(0, n) {
+                buf[i] = available[i];
+            }
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl Buffer for TimedReader {
+    fn fill_buf<'a>(&'a mut self) -> IoResult<&'a [u8]> {
+        while self.position >= self.buffer.len() {
+            self.buffer = try!(self.receive());
+            self.position = 0;
+        }
+        Ok(self.buffer.slice_from(self.position))
+    }
+
+    fn consume(&mut self, amount: uint) {
+        self.position += amount;
+    }
+}
+
+/// The game given to an engine, to be replayed into a new one.
+#[deriving(PartialEq, Show)]
+pub struct GameState {
+    pub boardsize: Option<uint>,
+    pub komi: Option<f32>,
+    pub time_settings: Option<(uint, uint, uint)>,
+    /// The command placing the handicap stones,
+    /// ex: `set_free_handicap D4 Q16`.
+    pub handicap: Option<String>,
+    pub moves: Vec<api::ColouredMove>
+}
+
+impl GameState {
+    pub fn new() -> GameState {
+        GameState{ boardsize: None, komi: None, time_settings: None, handicap: None, moves: Vec::new() }
+    }
+
+    /// Updates the state after `command` succeeded with `response`.
+    pub fn record(&mut self, command: &str, response: &str) {
+        let mut words = command.words();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        match (name, args.as_slice()) {
+            ("boardsize", [size]) => {
+                self.boardsize = from_str::<uint>(size);
+                self.handicap = None;
+                self.moves.clear();
+            },
+            ("clear_board", _) => {
+                self.handicap = None;
+                self.moves.clear();
+            },
+            ("komi", [komi]) => self.komi = from_str::<f32>(komi),
+            ("time_settings", [main, byoyomi, stones]) => {
+                self.time_settings = match (from_str(main), from_str(byoyomi), from_str(stones)) {
+                    (Some(m), Some(b), Some(s)) => Some((m, b, s)),
+                    _ => None
+                };
+            },
+            ("fixed_handicap", _) | ("set_free_handicap", _) => self.handicap = Some(String::from_str(command.trim())),
+            ("place_free_handicap", _) => {
+                self.handicap = responses::parse_vertex_list(response).map(|stones| {
+                    format!("set_free_handicap {}", responses::format_vertex_list(stones.as_slice()))
+                });
+            },
+            ("play", [colour, move]) => match (responses::parse_colour(colour), responses::parse_move(move)) {
+                (Some(c), Some(m)) => self.moves.push(api::ColouredMove{ player: c, move: m }),
+                _ => {}
+            },
+            ("genmove", [colour]) | ("kgs-genmove_cleanup", [colour]) =>
+                match (responses::parse_colour(colour), responses::parse_move(response)) {
+                    (Some(_), Some(api::Resign)) => {},
+                    (Some(c), Some(m)) => self.moves.push(api::ColouredMove{ player: c, move: m }),
+                    _ => {}
+                },
+            ("undo", _) => { self.moves.pop(); },
+            ("loadsgf", _) => diagnostics::warning("positions loaded with loadsgf cannot be replayed"),
+            _ => {}
+        }
+    }
+
+    /// The commands bringing a new engine to this state.
+    pub fn replay(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+        match self.boardsize {
+            Some(size) => commands.push(format!("boardsize {}", size)),
+            None => {}
+        }
+        commands.push(String::from_str("clear_board"));
+        match self.komi {
+            Some(komi) => commands.push(format!("komi {}", komi)),
+            None => {}
+        }
+        match self.time_settings {
+            Some((main, byoyomi, stones)) => commands.push(format!("time_settings {} {} {}", main, byoyomi, stones)),
+            None => {}
+        }
+        match self.handicap {
+            Some(ref command) => commands.push(command.clone()),
+            None => {}
+        }
+        for m in self.moves.iter() {
+            commands.push(format!("play {} {}", m.player.to_string(), m.move.to_string()));
+        }
+        commands
+    }
+}
+
+/// A client of an engine whose responses are read with a timeout.
+pub type TimedClient<W> = Client<TimedReader, W>;
+
+/// Starts the engines of a `Supervisor`, the first one and those
+/// replacing it.
+pub trait Starter<W> {
+    fn start(&mut self) -> IoResult<TimedClient<W>>;
+}
+
+/// An engine process.
+pub struct Program {
+    program: String,
+    args: Vec<String>
+}
+
+impl Program {
+    pub fn new(program: &str, args: &[String]) -> Program {
+        Program{ program: String::from_str(program), args: Vec::from_slice(args) }
+    }
+}
+
+impl Starter<PipeStream> for Program {
+    fn start(&mut self) -> IoResult<TimedClient<PipeStream>> {
+        let mut process = try!(Command::new(self.program.as_slice()).args(self.args.as_slice())
+                               .stderr(InheritFd(2)).spawn());
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+        let mut client = Client::new(try!(TimedReader::new(stdout)), stdin);
+        client.attach(process);
+        Ok(client)
+    }
+}
+
+/// A supervised engine, see the module documentation.
+pub struct Supervisor<W> {
+    // the name of the engine in the warnings
+    name: String,
+    starter: Box<Starter<W> + 'static>,
+    client: TimedClient<W>,
+    state: GameState,
+    /// Timeout of the commands in milliseconds, `None` to wait forever.
+    pub timeout: Option<u64>,
+    // timeouts of specific commands
+    timeouts: Vec<(String, u64)>,
+    /// Number of restarts allowed, 3 by default.
+    pub max_restarts: uint,
+    restarts: uint
+}
+
+impl Supervisor<PipeStream> {
+    /// Starts `program` with `args`, without timeout.
+    pub fn spawn(program: &str, args: &[String]) -> IoResult<Supervisor<PipeStream>> {
+        Supervisor::new(program, box Program::new(program, args))
+    }
+}
+
+impl<W: Writer> Supervisor<W> {
+    /// Starts an engine with `starter`, without timeout.
+    pub fn new(name: &str, starter: Box<Starter<W> + 'static>) -> IoResult<Supervisor<W>> {
+        let mut starter = starter;
+        let client = try!(starter.start());
+        Ok(Supervisor{ name: String::from_str(name), starter: starter, client: client,
+                       state: GameState::new(), timeout: None, timeouts: Vec::new(), max_restarts: 3, restarts: 0 })
+    }
+
+    /// Sets the timeout of a command, overriding `timeout`
+    /// (ex: a longer one for `genmove`).
+    pub fn set_command_timeout(&mut self, command: &str, milliseconds: u64) {
+        self.timeouts.retain(|&(ref c, _)| c.as_slice() != command);
+        self.timeouts.push((String::from_str(command), milliseconds));
+    }
+
+    /// The number of restarts so far.
+    pub fn restarts(&self) -> uint {
+        self.restarts
+    }
+
+    /// The game given to the engine.
+    pub fn state<'a>(&'a self) -> &'a GameState {
+        &self.state
+    }
+
+    fn timeout_of(&self, line: &str) -> Option<u64> {
+        let name = line.words().next().unwrap_or("");
+        match self.timeouts.iter().find(|&&(ref c, _)| c.as_slice() == name) {
+            Some(&(_, ms)) => Some(ms),
+            None => self.timeout
+        }
+    }
+
+    // sends a command once, with its timeout
+    fn try_command(&mut self, line: &str) -> ClientResult<String> {
+        self.client.get_mut_reader().timeout = self.timeout_of(line);
+        self.client.command(line)
+    }
+
+    /// Sends a command, restarting the engine if it fails to answer.
+    /// Refused commands are not sent again.
+    pub fn command(&mut self, line: &str) -> ClientResult<String> {
+        let mut restarted = false;
+        loop {
+            match self.try_command(line) {
+                Ok(text) => {
+                    self.state.record(line, text.as_slice());
+                    return Ok(text);
+                },
+                Err(CommandFailed(message)) => return Err(CommandFailed(message)),
+                Err(e) => {
+                    if restarted || self.restarts >= self.max_restarts {
+                        return Err(e);
+                    }
+                    diagnostics::warning(format!("{} failed on {}: {}, restarting it",
+                                                 self.name, line.trim(), e).as_slice());
+                    try!(self.restart());
+                    restarted = true;
+                }
+            }
+        }
+    }
+
+    /// Kills the engine and starts it again, replaying the game into it.
+    pub fn restart(&mut self) -> ClientResult<()> {
+        let _ = self.client.kill();
+        self.restarts += 1;
+        self.client = try!(self.starter.start().map_err(IoFailure));
+        for command in self.state.replay().iter() {
+            try!(self.try_command(command.as_slice()));
+        }
+        Ok(())
+    }
+
+    /// Asks the engine to stop, without restarting it if it fails.
+    pub fn quit(&mut self) -> ClientResult<()> {
+        self.try_command("quit").map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::{IoResult, BufferedReader, ChanReader, ChanWriter, MemReader};
+    use api;
+    use client::Client;
+    use mock;
+    use parsing;
+
+    // a writer telling what it writes
+    struct Tap {
+        writer: ChanWriter,
+        log: Sender<Vec<u8>>
+    }
+
+    impl Writer for Tap {
+        fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+            let _ = self.log.send_opt(Vec::from_slice(buf));
+            self.writer.write(buf)
+        }
+
+        fn flush(&mut self) -> IoResult<()> {
+            self.writer.flush()
+        }
+    }
+
+    // mock engines running in their own tasks, one script per start
+    struct Mocks {
+        scripts: Vec<mock::Script>,
+        log: Sender<Vec<u8>>
+    }
+
+    impl super::Starter<Tap> for Mocks {
+        fn start(&mut self) -> IoResult<super::TimedClient<Tap>> {
+            let script = self.scripts.remove(0).unwrap();
+            let (command_tx, command_rx) = channel();
+            let (response_tx, response_rx) = channel();
+            spawn(proc() {
+                let engine = mock::MockEngine::new(script);
+                let _ = engine.run(&mut BufferedReader::new(ChanReader::new(command_rx)),
+                                   &mut ChanWriter::new(response_tx));
+            });
+            let reader = try!(super::TimedReader::new(ChanReader::new(response_rx)));
+            Ok(Client::new(reader, Tap{ writer: ChanWriter::new(command_tx), log: self.log.clone() }))
+        }
+    }
+
+    fn supervise(scripts: Vec<mock::Script>) -> (super::Supervisor<Tap>, Receiver<Vec<u8>>) {
+        let (log_tx, log_rx) = channel();
+        let supervisor = super::Supervisor::new("mock", box Mocks{ scripts: scripts, log: log_tx }).unwrap();
+        (supervisor, log_rx)
+    }
+
+    // what the engines were sent so far
+    fn sent(log: &Receiver<Vec<u8>>) -> String {
+        let mut bytes = Vec::new();
+        loop {
+            match log.try_recv() {
+                Ok(chunk) => bytes.push_all(chunk.as_slice()),
+                Err(_) => break
+            }
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn timed_reader() {
+        let mut reader = super::TimedReader::new(MemReader::new(Vec::from_slice(b"=1 foo\n\n"))).unwrap();
+        reader.timeout = Some(1000);
+        assert_eq!(parsing::read_line_lossy(&mut reader).unwrap().as_slice(), "=1 foo\n");
+        assert_eq!(parsing::read_line_lossy(&mut reader).unwrap().as_slice(), "\n");
+        assert_eq!(parsing::read_line_lossy(&mut reader).unwrap_err().kind, io::EndOfFile);
+        let (_tx, rx) = channel::<Vec<u8>>();
+        let mut reader = super::TimedReader::new(ChanReader::new(rx)).unwrap();
+        reader.timeout = Some(10);
+        assert_eq!(parsing::read_line_lossy(&mut reader).unwrap_err().kind, io::TimedOut);
+    }
+
+    #[test]
+    fn game_state() {
+        let mut state = super::GameState::new();
+        state.record("boardsize 9", "");
+        state.record("komi 6.5", "");
+        state.record("place_free_handicap 2", "C3 G7");
+        state.record("play black D4", "");
+        state.record("genmove white", "E5");
+        state.record("genmove black", "resign");
+        state.record("play white pass", "");
+        state.record("undo", "");
+        assert_eq!(state.moves, vec!(
+            api::ColouredMove{ player: api::Black, move: api::Stone(api::Vertex::from_str("D4").unwrap()) },
+            api::ColouredMove{ player: api::White, move: api::Stone(api::Vertex::from_str("E5").unwrap()) }));
+        let replay: Vec<String> = vec!("boardsize 9", "clear_board", "komi 6.5", "set_free_handicap C3 G7",
+                                       "play black D4", "play white E5").iter().map(|s| String::from_str(*s)).collect();
+        assert_eq!(state.replay(), replay);
+        state.record("clear_board", "");
+        assert_eq!(state.replay().len(), 3);
+    }
+
+    #[test]
+    fn restart_after_crash() {
+        let (mut supervisor, log) = supervise(vec!(mock::Script::parse("crash after 3").unwrap(),
+                                                   mock::Script::parse("genmove = E5").unwrap()));
+        assert!(supervisor.command("boardsize 9").is_ok());
+        assert!(supervisor.command("komi 6.5").is_ok());
+        assert!(supervisor.command("play black D4").is_ok());
+        assert_eq!(supervisor.command("genmove white").unwrap().as_slice(), "E5");
+        assert_eq!(supervisor.restarts(), 1);
+        assert_eq!(sent(&log).as_slice(),
+                   "1 boardsize 9\n2 komi 6.5\n3 play black D4\n4 genmove white\n\
+                    1 boardsize 9\n2 clear_board\n3 komi 6.5\n4 play black D4\n5 genmove white\n");
+        assert_eq!(supervisor.state().moves.len(), 2);
+    }
+
+    #[test]
+    fn restart_after_timeout() {
+        let (mut supervisor, log) = supervise(vec!(mock::Script::parse("delay genmove 1000").unwrap(),
+                                                   mock::Script::parse("genmove = E5").unwrap()));
+        supervisor.timeout = Some(100);
+        assert!(supervisor.command("boardsize 9").is_ok());
+        assert_eq!(supervisor.command("genmove black").unwrap().as_slice(), "E5");
+        assert_eq!(sent(&log).as_slice(), "1 boardsize 9\n2 genmove black\n\
+                                           1 boardsize 9\n2 clear_board\n3 genmove black\n");
+        // refused commands are not retried
+        assert!(supervisor.command("foo").is_err());
+        assert_eq!(supervisor.restarts(), 1);
+    }
+}
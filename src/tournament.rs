@@ -0,0 +1,515 @@
+//! Tournaments between a pool of engines, with rating estimates.
+//!
+//! Games are scheduled as a round robin, or as Swiss rounds where
+//! engines with close scores meet, without rematches when possible.
+//! They run in parallel, each with its own engine processes, up to a
+//! concurrency limit.
+//!
+//! Progress is kept in a state file, one line per finished game, so that
+//! an interrupted tournament resumes where it stopped when run again
+//! with the same engines and state file.
+//!
+//! Ratings are maximum likelihood Elo estimates (Bradley-Terry model),
+//! with 95% confidence intervals.
+
+use std::cmp;
+use std::f64;
+use std::fmt;
+use std::io;
+use std::io::{IoResult, File, Writer};
+use std::task;
+use std::uint;
+use client::ClientError;
+use diagnostics;
+use matchrunner;
+
+/// An engine of the pool.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Entrant {
+    pub name: String,
+    /// Command line starting the engine, split on spaces.
+    pub command: String
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum Format {
+    /// Every engine meets every other one this number of times,
+    /// with alternating colours.
+    RoundRobin(uint),
+    /// This number of rounds, each engine playing at most once per round.
+    Swiss(uint)
+}
+
+/// A game of the schedule, engines being given by their index.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Pairing {
+    /// Unique number of the game in the tournament.
+    pub number: uint,
+    pub round: uint,
+    pub black: uint,
+    pub white: uint
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum Outcome {
+    BlackWins,
+    WhiteWins,
+    Draw,
+    /// Void games do not count.
+    NoResult
+}
+
+/// A finished game.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Record {
+    pub pairing: Pairing,
+    pub outcome: Outcome,
+    /// The result in the SGF notation (ex: `B+R`).
+    pub result: String
+}
+
+impl Record {
+    // the points of an engine in this game, if it played it
+    fn points(&self, engine: uint) -> Option<f64> {
+        let (black, white) = match self.outcome {
+            BlackWins => (1.0, 0.0),
+            WhiteWins => (0.0, 1.0),
+            Draw => (0.5, 0.5),
+            NoResult => return None
+        };
+        if self.pairing.black == engine {
+            Some(black)
+        } else if self.pairing.white == engine {
+            Some(white)
+        } else {
+            None
+        }
+    }
+
+    // a line of the state file
+    fn to_line(&self) -> String {
+        format!("game\t{}\t{}\t{}\t{}\t{}", self.pairing.number, self.pairing.round,
+                self.pairing.black, self.pairing.white, self.result)
+    }
+
+    fn from_line(line: &str) -> Option<Record> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 6 || fields[0] != "game" {
+            return None;
+        }
+        let numbers: Vec<Option<uint>> = fields.slice(1, 5).iter().map(|f| from_str::<uint>(*f)).collect();
+        match numbers.as_slice() {
+            [Some(number), Some(round), Some(black), Some(white)] => Some(Record{
+                pairing: Pairing{ number: number, round: round, black: black, white: white },
+                outcome: outcome_of(fields[5]),
+                result: String::from_str(fields[5])
+            }),
+            _ => None
+        }
+    }
+}
+
+// reads the winner from the SGF notation
+fn outcome_of(result: &str) -> Outcome {
+    if result == "0" {
+        Draw
+    } else if result.starts_with("B+") {
+        BlackWins
+    } else if result.starts_with("W+") {
+        WhiteWins
+    } else {
+        NoResult
+    }
+}
+
+/// The schedule of a round robin between `engines` engines.
+pub fn round_robin(engines: uint, cycles: uint) -> Vec<Pairing> {
+    let mut pairings = Vec::new();
+    for cycle in range(0, cycles) {
+        for i in range(0, engines) {
+            for j in range(i + 1, engines) {
+                let (black, white) = if cycle % 2 == 0 { (i, j) } else { (j, i) };
+                pairings.push(Pairing{ number: pairings.len() + 1, round: cycle + 1, black: black, white: white });
+            }
+        }
+    }
+    pairings
+}
+
+fn points(engine: uint, records: &[Record]) -> f64 {
+    records.iter().filter_map(|r| r.points(engine)).fold(0.0, |a, b| a + b)
+}
+
+fn games(engine: uint, records: &[Record]) -> uint {
+    records.iter().filter(|r| r.pairing.black == engine || r.pairing.white == engine).count()
+}
+
+fn blacks(engine: uint, records: &[Record]) -> uint {
+    records.iter().filter(|r| r.pairing.black == engine).count()
+}
+
+fn played(a: uint, b: uint, records: &[Record]) -> bool {
+    records.iter().any(|r| (r.pairing.black == a && r.pairing.white == b) ||
+                           (r.pairing.black == b && r.pairing.white == a))
+}
+
+/// The pairings of a Swiss round, from the results of the previous ones.
+/// Engines are ranked by points, and each one meets the best ranked
+/// engine it has not met yet. With an odd number of engines, the one
+/// having played the most games sits out. Pairings only depend on the
+/// results, so that an interrupted round is paired the same way again.
+pub fn swiss_round(engines: uint, round: uint, records: &[Record]) -> Vec<Pairing> {
+    let previous: Vec<Record> = records.iter().filter(|r| r.pairing.round < round).map(|r| r.clone()).collect();
+    let previous = previous.as_slice();
+    let mut ranking: Vec<uint> = range(0, engines).collect();
+    ranking.sort_by(|&a, &b| match points(b, previous).partial_cmp(&points(a, previous)) {
+        Some(cmp::Equal) | None => a.cmp(&b),
+        Some(order) => order
+    });
+    if engines % 2 == 1 {
+        // the lowest ranked among those who played the most
+        let most = ranking.iter().map(|&e| games(e, previous)).max().unwrap_or(0);
+        let sitting = *ranking.iter().rev().find(|&&e| games(e, previous) == most).unwrap();
+        ranking.retain(|&e| e != sitting);
+    }
+    let first_number = engines / 2 * (round - 1);
+    let mut pairings = Vec::new();
+    while ranking.len() >= 2 {
+        let a = ranking.remove(0).unwrap();
+        let index = ranking.iter().position(|&b| !played(a, b, previous)).unwrap_or(0);
+        let b = ranking.remove(index).unwrap();
+        let (black, white) = if blacks(b, previous) < blacks(a, previous) { (b, a) } else { (a, b) };
+        pairings.push(Pairing{ number: first_number + pairings.len() + 1, round: round,
+                               black: black, white: white });
+    }
+    pairings
+}
+
+/// The rating estimate of an engine.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Rating {
+    pub engine: uint,
+    /// Elo, relative to the average of the pool.
+    pub elo: f64,
+    /// Half width of the 95% confidence interval.
+    pub margin: f64,
+    pub games: uint,
+    /// Fraction of the points won.
+    pub score: f64
+}
+
+// natural units to Elo
+static ELO_SCALE: f64 = 173.717792761; // 400 / ln(10)
+
+/// Estimates the ratings from the results, best first. Each engine gets
+/// a virtual draw against an average opponent, which keeps the ratings
+/// of engines without wins or losses finite.
+pub fn ratings(engines: uint, records: &[Record]) -> Vec<Rating> {
+    // wins[i][j]: points of i against j
+    let mut wins = Vec::from_elem(engines, Vec::from_elem(engines, 0.0f64));
+    let mut games = Vec::from_elem(engines, Vec::from_elem(engines, 0u));
+    for r in records.iter() {
+        let (b, w) = (r.pairing.black, r.pairing.white);
+        match (r.points(b), r.points(w)) {
+            (Some(pb), Some(pw)) => {
+                *wins.get_mut(b).get_mut(w) += pb;
+                *wins.get_mut(w).get_mut(b) += pw;
+                *games.get_mut(b).get_mut(w) += 1;
+                *games.get_mut(w).get_mut(b) += 1;
+            },
+            _ => {}
+        }
+    }
+    // minorization-maximization (Hunter, 2004), the virtual opponent
+    // has a strength of 1
+    let mut gamma = Vec::from_elem(engines, 1.0f64);
+    for _ in range(0u, 1000) {
+        let mut next = gamma.clone();
+        for i in range(0, engines) {
+            let mut total = 0.5;
+            let mut denominator = 1.0 / (gamma[i] + 1.0);
+            for j in range(0, engines) {
+                total += wins[i][j];
+                if games[i][j] > 0 {
+                    denominator += games[i][j] as f64 / (gamma[i] + gamma[j]);
+                }
+            }
+            *next.get_mut(i) = total / denominator;
+        }
+        gamma = next;
+    }
+    let elos: Vec<f64> = gamma.iter().map(|g| g.ln() * ELO_SCALE).collect();
+    let mean = elos.iter().fold(0.0, |a, &b| a + b) / (if engines > 0 { engines as f64 } else { 1.0 });
+    let mut result: Vec<Rating> = range(0, engines).map(|i| {
+        // inverse of the Fisher information of the rating
+        let mut information = 0.0;
+        let mut played = 0u;
+        let mut won = 0.0;
+        for j in range(0, engines) {
+            let p = gamma[i] / (gamma[i] + gamma[j]);
+            information += games[i][j] as f64 * p * (1.0 - p);
+            played += games[i][j];
+            won += wins[i][j];
+        }
+        Rating{
+            engine: i,
+            elo: elos[i] - mean,
+            margin: if information > 0.0 { 1.96 * ELO_SCALE / information.sqrt() } else { f64::INFINITY },
+            games: played,
+            score: if played > 0 { won / played as f64 } else { 0.0 }
+        }
+    }).collect();
+    result.sort_by(|a, b| b.elo.partial_cmp(&a.elo).unwrap_or(cmp::Equal));
+    result
+}
+
+/// A tournament, see the module documentation.
+pub struct Tournament {
+    pub entrants: Vec<Entrant>,
+    pub format: Format,
+    /// Settings of the games, the number of games and the colour
+    /// alternation are given by the format.
+    pub settings: matchrunner::Settings,
+    /// Command of a referee engine, started for each game.
+    pub referee: Option<String>,
+    /// Maximum number of games played at the same time.
+    pub concurrency: uint,
+    /// File keeping the progress.
+    pub state: Path
+}
+
+// the state file starts with the engines, to check it belongs to
+// this tournament
+fn header(entrants: &[Entrant]) -> String {
+    let lines: Vec<String> = entrants.iter().enumerate().map(|(i, e)| {
+        format!("engine\t{}\t{}\t{}\n", i, e.name, e.command)
+    }).collect();
+    lines.concat()
+}
+
+// the id of the referee, which is not an entrant
+static REFEREE: uint = uint::MAX;
+
+fn invalid_state(detail: &str) -> io::IoError {
+    io::IoError{ kind: io::InvalidInput, desc: "invalid tournament state file",
+                 detail: Some(String::from_str(detail)) }
+}
+
+fn failure(pairing: &Pairing, error: ClientError) -> String {
+    format!("game {}: {}", pairing.number, error)
+}
+
+// plays a game with new engine processes
+fn play(pairing: &Pairing, black: &str, white: &str, referee: Option<String>,
+        settings: &matchrunner::Settings) -> Result<matchrunner::Game, String> {
+    let mut black = try!(matchrunner::Player::spawn(pairing.black, black).map_err(|e| failure(pairing, e)));
+    let mut white = try!(matchrunner::Player::spawn(pairing.white, white).map_err(|e| failure(pairing, e)));
+    let mut referee = match referee {
+        Some(command) => Some(try!(matchrunner::Player::spawn(REFEREE, command.as_slice())
+                                       .map_err(|e| failure(pairing, e)))),
+        None => None
+    };
+    let game = try!(matchrunner::play_game(&mut black, &mut white, referee.as_mut(), settings)
+                               .map_err(|e| failure(pairing, e)));
+    let _ = black.client.quit();
+    let _ = white.client.quit();
+    match referee {
+        Some(ref mut r) => { let _ = r.client.quit(); },
+        None => {}
+    }
+    Ok(game)
+}
+
+// the pairings not played yet
+fn remaining(pairings: Vec<Pairing>, records: &[Record]) -> Vec<Pairing> {
+    pairings.iter().filter(|p| !records.iter().any(|r| r.pairing.number == p.number))
+                   .map(|p| p.clone()).collect()
+}
+
+impl Tournament {
+    /// The games finished so far, from the state file.
+    pub fn load(&self) -> IoResult<Vec<Record>> {
+        let text = match File::open(&self.state) {
+            Err(ref e) if e.kind == io::FileNotFound => return Ok(Vec::new()),
+            file => try!(file.read_to_string())
+        };
+        let expected = header(self.entrants.as_slice());
+        if !text.as_slice().starts_with(expected.as_slice()) {
+            return Err(invalid_state("the engines differ from the ones of the state file"));
+        }
+        let mut records = Vec::new();
+        for line in text.as_slice().slice_from(expected.len()).lines() {
+            match Record::from_line(line) {
+                Some(r) => records.push(r),
+                None => return Err(invalid_state(line))
+            }
+        }
+        Ok(records)
+    }
+
+    // plays the games in parallel, appending them to the state file
+    fn play_all<W: Writer>(&self, pairings: &[Pairing], state: &mut W, records: &mut Vec<Record>,
+                           report: |&Record|) -> IoResult<()> {
+        let (tx, rx) = channel();
+        let mut pending = pairings.iter();
+        let mut running = 0u;
+        loop {
+            while running < cmp::max(self.concurrency, 1) {
+                let pairing = match pending.next() {
+                    Some(p) => p.clone(),
+                    None => break
+                };
+                let black = self.entrants[pairing.black].command.clone();
+                let white = self.entrants[pairing.white].command.clone();
+                let referee = self.referee.clone();
+                let settings = self.settings.clone();
+                let tx = tx.clone();
+                spawn(proc() {
+                    let p = pairing.clone();
+                    // a failing game must not stop the tournament
+                    let result = match task::try(proc() {
+                        play(&p, black.as_slice(), white.as_slice(), referee, &settings)
+                    }) {
+                        Ok(r) => r,
+                        Err(_) => Err(format!("game {} failed", pairing.number))
+                    };
+                    tx.send((pairing, result));
+                });
+                running += 1;
+            }
+            if running == 0 {
+                return Ok(());
+            }
+            let (pairing, result) = rx.recv();
+            running -= 1;
+            let mut game = match result {
+                Ok(g) => g,
+                Err(e) => {
+                    diagnostics::warning(format!("{}, it will be played again on resume", e).as_slice());
+                    continue;
+                }
+            };
+            let record = Record{ pairing: pairing.clone(), outcome: outcome_of(game.result.to_string().as_slice()),
+                                 result: game.result.to_string() };
+            try!(state.write_line(record.to_line().as_slice()));
+            try!(state.flush());
+            match self.settings.output {
+                Some(ref dir) => {
+                    game.record.black_name = Some(self.entrants[pairing.black].name.clone());
+                    game.record.white_name = Some(self.entrants[pairing.white].name.clone());
+                    let path = dir.join(format!("round-{:02u}-game-{:04u}.sgf", pairing.round, pairing.number));
+                    try!(File::create(&path).write_str(game.record.to_sgf().as_slice()));
+                },
+                None => {}
+            }
+            report(&record);
+            records.push(record);
+        }
+    }
+
+    /// Plays the games which are not in the state file yet, `report`
+    /// being called after each one. Returns all the finished games.
+    /// In a Swiss tournament, no round is paired while games of the
+    /// previous one failed.
+    pub fn run(&self, report: |&Record|) -> IoResult<Vec<Record>> {
+        let mut records = try!(self.load());
+        let mut state = try!(File::open_mode(&self.state, io::Append, io::Write));
+        if try!(state.stat()).size == 0 {
+            try!(state.write_str(header(self.entrants.as_slice()).as_slice()));
+        }
+        let engines = self.entrants.len();
+        match self.format {
+            RoundRobin(cycles) => {
+                let todo = remaining(round_robin(engines, cycles), records.as_slice());
+                try!(self.play_all(todo.as_slice(), &mut state, &mut records, |r| report(r)));
+            },
+            Swiss(rounds) => {
+                for round in range(1, rounds + 1) {
+                    let pairings = swiss_round(engines, round, records.as_slice());
+                    let todo = remaining(pairings.clone(), records.as_slice());
+                    try!(self.play_all(todo.as_slice(), &mut state, &mut records, |r| report(r)));
+                    // the next round is paired on all the results of this one
+                    if !remaining(pairings, records.as_slice()).is_empty() {
+                        diagnostics::warning(format!("round {} is not finished, the next rounds are left to the resume",
+                                                     round).as_slice());
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// The standings, one line per engine, best first.
+pub struct Standings<'a> {
+    pub entrants: &'a [Entrant],
+    pub ratings: Vec<Rating>
+}
+
+impl<'a> fmt::Show for Standings<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{:4s} {:20s} {:>7s} {:>7s} {:>5s} {:>6s}\n", "RANK", "ENGINE", "ELO", "+/-", "GAMES", "SCORE"));
+        for (i, r) in self.ratings.iter().enumerate() {
+            try!(write!(f, "{:4u} {:20s} {:7.1f} {:7.1f} {:5u} {:5.1f}%\n", i + 1,
+                        self.entrants[r.engine].name, r.elo, r.margin, r.games, r.score * 100.0));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn record(number: uint, round: uint, black: uint, white: uint, result: &str) -> super::Record {
+        super::Record{ pairing: super::Pairing{ number: number, round: round, black: black, white: white },
+                       outcome: super::outcome_of(result), result: String::from_str(result) }
+    }
+
+    #[test]
+    fn round_robin() {
+        let pairings = super::round_robin(4, 2);
+        assert_eq!(pairings.len(), 12);
+        assert_eq!((pairings[0].black, pairings[0].white), (0, 1));
+        assert_eq!((pairings[6].black, pairings[6].white), (1, 0));
+        assert_eq!(pairings[11].number, 12);
+    }
+
+    #[test]
+    fn swiss_round() {
+        let first = super::swiss_round(4, 1, []);
+        assert_eq!(first.iter().map(|p| (p.black, p.white)).collect::<Vec<(uint, uint)>>(), vec!((0, 1), (2, 3)));
+        let records = [record(1, 1, 0, 1, "B+R"), record(2, 1, 2, 3, "W+3.5")];
+        let second = super::swiss_round(4, 2, records);
+        // winners meet, colours are balanced
+        assert_eq!(second.iter().map(|p| (p.black, p.white)).collect::<Vec<(uint, uint)>>(), vec!((3, 0), (1, 2)));
+        assert_eq!(second[0].number, 3);
+        // odd number of engines: one sits out, no rematch
+        let third = super::swiss_round(3, 2, [record(1, 1, 0, 1, "B+R")]);
+        assert_eq!(third.len(), 1);
+        assert!(third[0].black == 2 || third[0].white == 2);
+    }
+
+    #[test]
+    fn record_line() {
+        let r = record(12, 3, 4, 0, "W+0.5");
+        assert_eq!(super::Record::from_line(r.to_line().as_slice()), Some(r));
+        assert_eq!(super::Record::from_line("game\t1\t2"), None);
+    }
+
+    #[test]
+    fn ratings() {
+        let mut records = Vec::new();
+        for i in range(0u, 20) {
+            // 0 wins 3 games out of 4 against 1, 1 and 2 are even
+            records.push(record(i, 1, 0, 1, if i % 4 == 0 { "W+R" } else { "B+R" }));
+            records.push(record(i, 1, 1, 2, if i % 2 == 0 { "W+R" } else { "B+R" }));
+        }
+        let ratings = super::ratings(3, records.as_slice());
+        assert_eq!(ratings[0].engine, 0);
+        assert!(ratings[0].elo > 100.0 && ratings[0].elo < 200.0);
+        assert!((ratings[1].elo - ratings[2].elo).abs() < 40.0);
+        assert!(ratings[0].margin > 0.0 && ratings[0].margin < 300.0);
+        assert_eq!(ratings[0].games, 20);
+        assert_eq!(ratings[0].score, 0.75);
+    }
+}
@@ -0,0 +1,164 @@
+// Session transcripts, recording every exchange between
+// the controller and the bot.
+//
+// A transcript is a text file, one record per line, fields separated
+// by tabs, with `\`, tabs and newlines escaped:
+//
+//     in   <rfc3339 timestamp>  <raw input line>
+//     cmd  <id or ->            <command>  <args>
+//     out  <latency in µs>      <response>
+//
+// `cmd` is absent when the line contained no command,
+// `out` always closes an exchange. Nondeterministic parts of a response
+// can be marked by hand for replays: a word of the response changed
+// into `*` matches any word, and an `out` record changed into `out*`
+// masks all the words of its response, only its status, id and number
+// of lines being compared.
+
+use std::io;
+use std::io::{IoResult, File, Buffer, BufferedReader, BufferedWriter};
+use std::os;
+use time;
+use parsing;
+
+// environment variable enabling transcripts in main_loop
+// and websocket_loop, its value is the path of the file
+pub static TRANSCRIPT_ENV_VAR: &'static str = "GTPRUST_TRANSCRIPT";
+
+pub fn escape(text: &str) -> String {
+    let mut output = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            '\t' => output.push_str("\\t"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            _ => output.push_char(c)
+        }
+    }
+    output
+}
+
+pub fn unescape(text: &str) -> String {
+    let mut output = String::new();
+    let mut escaped = false;
+    for c in text.chars() {
+        if escaped {
+            output.push_char(match c {
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                other => other
+            });
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else {
+            output.push_char(c);
+        }
+    }
+    output
+}
+
+pub struct Transcript {
+    output: BufferedWriter<File>
+}
+
+impl Transcript {
+    pub fn create(path: &Path) -> IoResult<Transcript> {
+        let file = try!(File::create(path));
+        let mut transcript = Transcript{ output: BufferedWriter::new(file) };
+        try!(transcript.output.write_line("# gtprust transcript"));
+        try!(transcript.output.flush());
+        Ok(transcript)
+    }
+
+    // opens the transcript requested through the environment, if any
+    // failing to open it is reported but not fatal
+    pub fn from_env() -> Option<Transcript> {
+        match os::getenv(TRANSCRIPT_ENV_VAR) {
+            Some(path) => match Transcript::create(&Path::new(path.as_slice())) {
+                Ok(t) => Some(t),
+                Err(e) => {
+                    let _ = ::std::io::stdio::stderr().write_line(
+                        format!("gtprust: cannot open transcript {}: {}", path, e).as_slice());
+                    None
+                }
+            },
+            None => None
+        }
+    }
+
+    // records a full exchange, flushing it right away so that
+    // nothing is lost if the bot crashes
+    pub fn record(&mut self, raw_input: &str, response: &str, latency_ns: u64) -> IoResult<()> {
+        try!(self.output.write_line(format!("in\t{}\t{}",
+            time::now().rfc3339(), escape(raw_input)).as_slice()));
+        match parsing::parse_command(raw_input) {
+            Some(cmd) => try!(self.output.write_line(format!("cmd\t{}\t{}\t{}",
+                match cmd.id { Some(i) => format!("{}", i), None => String::from_str("-") },
+                escape(cmd.command.as_slice()),
+                escape(cmd.args.as_slice())).as_slice())),
+            None => {}
+        }
+        try!(self.output.write_line(format!("out\t{}\t{}",
+            latency_ns / 1000, escape(response)).as_slice()));
+        self.output.flush()
+    }
+}
+
+// an exchange read back from a transcript
+pub struct Exchange {
+    pub input: String,
+    pub command: Option<String>,
+    pub response: String,
+    pub nondeterministic: bool
+}
+
+pub fn read_transcript(path: &Path) -> IoResult<Vec<Exchange>> {
+    read_exchanges(&mut BufferedReader::new(try!(File::open(path))))
+}
+
+pub fn read_exchanges<B: Buffer>(reader: &mut B) -> IoResult<Vec<Exchange>> {
+    let mut exchanges = Vec::new();
+    let mut input: Option<String> = None;
+    let mut command: Option<String> = None;
+    for line in reader.lines() {
+        let line = try!(line);
+        let fields: Vec<&str> = line.as_slice().trim_right_chars('\n').split('\t').collect();
+        if fields.len() < 3 {
+            // comments and unknown lines
+            continue;
+        }
+        match fields[0] {
+            "in" => {
+                input = Some(unescape(fields[2]));
+                command = None;
+            },
+            "cmd" => {
+                command = Some(unescape(fields[2]));
+            },
+            "out" | "out*" => match input.take() {
+                Some(i) => exchanges.push(Exchange{
+                    input: i,
+                    command: command.take(),
+                    response: unescape(fields[2]),
+                    nondeterministic: fields[0] == "out*"
+                }),
+                None => return Err(io::standard_error(io::InvalidInput))
+            },
+            _ => {}
+        }
+    }
+    Ok(exchanges)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn escaping() {
+        let text = "= A1\tB2 \\ end\n\n";
+        assert_eq!(super::escape(text).as_slice(), "= A1\\tB2 \\\\ end\\n\\n");
+        assert_eq!(super::unescape(super::escape(text).as_slice()).as_slice(), text);
+    }
+}
@@ -0,0 +1,494 @@
+// WebSocket frontend (RFC 6455), so that browser-based GUIs
+// can talk to a bot without any hand-written adapter.
+// Each text frame received is a GTP command, each response is
+// sent back in its own text frame. The response of an analysis
+// is streamed: its first line, then each analysis line reported
+// by the bot in its own frame, and an empty frame once the next
+// command stopped it.
+
+use std::io;
+use std::io::{IoResult, BufferedReader, TcpListener, TcpStream, Timer, Listener, Acceptor};
+use std::ascii::StrAsciiExt;
+use std::comm::Select;
+use libc;
+use serialize::base64::{ToBase64, STANDARD};
+use api;
+use bothandler;
+use signals;
+use time;
+use transcript;
+
+static WEBSOCKET_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// no GTP command needs more than this, the limit applies to
+// whole messages, whatever the number of their fragments
+static MAX_PAYLOAD: u64 = 1 << 20;
+
+// frame opcodes
+static OP_CONTINUATION: u8 = 0x0;
+static OP_TEXT: u8 = 0x1;
+static OP_BINARY: u8 = 0x2;
+static OP_CLOSE: u8 = 0x8;
+static OP_PING: u8 = 0x9;
+static OP_PONG: u8 = 0xA;
+
+// close codes
+static CLOSE_PROTOCOL_ERROR: u16 = 1002;
+static CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+
+// what the reader task of a connection passes on
+enum Incoming {
+    Message(String),
+    Ping(Vec<u8>),
+    // the client closed the connection, with this payload
+    Close(Vec<u8>),
+    // the client broke the protocol, the connection is closed with this code
+    Refused(u16),
+    // the connection failed, or the client sent garbage
+    Failed
+}
+
+struct Connection {
+    writer: TcpStream,
+    incoming: Receiver<Incoming>,
+    // a message received while an analysis was running
+    pending: Option<Incoming>,
+    // to watch for signals while waiting for messages,
+    // the timer is kept alive for its ticks
+    _timer: Timer,
+    ticks: Receiver<()>
+}
+
+impl Connection {
+    // reads the HTTP upgrade request and answers it, the frames
+    // are then read in their own task
+    fn accept(stream: TcpStream) -> IoResult<Connection> {
+        let mut reader = BufferedReader::new(stream.clone());
+        let mut writer = stream;
+        let mut key: Option<String> = None;
+        loop {
+            let line = try!(reader.read_line());
+            let line = line.as_slice().trim();
+            if line.is_empty() {
+                break;
+            }
+            let mut parts = line.splitn(':', 1);
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("sec-websocket-key") => {
+                    key = Some(String::from_str(value.trim()));
+                },
+                _ => {}
+            }
+        }
+        match key {
+            Some(k) => {
+                try!(writer.write_str(format!(
+                    "HTTP/1.1 101 Switching Protocols\r\n\
+                     Upgrade: websocket\r\n\
+                     Connection: Upgrade\r\n\
+                     Sec-WebSocket-Accept: {}\r\n\r\n", accept_key(k.as_slice())).as_slice()));
+                try!(writer.flush());
+                let mut timer = try!(Timer::new());
+                let ticks = timer.periodic(100);
+                Ok(Connection{ writer: writer, incoming: spawn_frame_reader(reader), pending: None,
+                               _timer: timer, ticks: ticks })
+            },
+            None => {
+                try!(writer.write_str("HTTP/1.1 400 Bad Request\r\n\r\n"));
+                Err(io::standard_error(io::InvalidInput))
+            }
+        }
+    }
+
+    // waits for the client, a termination signal being handled as `quit`
+    fn receive(&mut self) -> Incoming {
+        loop {
+            let select = Select::new();
+            let mut incoming = select.handle(&self.incoming);
+            let mut tick = select.handle(&self.ticks);
+            unsafe {
+                incoming.add();
+                tick.add();
+            }
+            if select.wait() == incoming.id() {
+                return incoming.recv_opt().unwrap_or(Failed);
+            }
+            let _ = tick.recv_opt();
+            if signals::termination_requested().is_some() {
+                return Message(String::from_str("quit"));
+            }
+        }
+    }
+
+    // the next message, answering pings on the way
+    // returns None once the connection is over
+    fn read_message(&mut self) -> Option<String> {
+        loop {
+            let incoming = match self.pending.take() {
+                Some(i) => i,
+                None => self.receive()
+            };
+            match incoming {
+                Message(txt) => return Some(txt),
+                Ping(payload) => if self.write_frame(OP_PONG, payload.as_slice()).is_err() {
+                    return None;
+                },
+                Close(payload) => {
+                    let _ = self.write_frame(OP_CLOSE, payload.as_slice());
+                    return None;
+                },
+                Refused(code) => {
+                    let _ = self.write_frame(OP_CLOSE, [(code >> 8) as u8, code as u8]);
+                    return None;
+                },
+                Failed => return None
+            }
+        }
+    }
+
+    // true once a message came, the connection is over or a termination
+    // signal was received, answering pings on the way
+    fn interrupted(&mut self) -> bool {
+        if signals::termination_requested().is_some() {
+            return true;
+        }
+        while self.pending.is_none() {
+            match self.incoming.try_recv() {
+                Ok(Ping(payload)) => {
+                    let _ = self.write_frame(OP_PONG, payload.as_slice());
+                },
+                Ok(other) => self.pending = Some(other),
+                Err(_) => break
+            }
+        }
+        self.pending.is_some()
+    }
+
+    // server frames are never masked
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> IoResult<()> {
+        try!(self.writer.write_u8(0x80 | opcode));
+        let len = payload.len();
+        if len < 126 {
+            try!(self.writer.write_u8(len as u8));
+        } else if len < 65536 {
+            try!(self.writer.write_u8(126));
+            try!(self.writer.write_be_u16(len as u16));
+        } else {
+            try!(self.writer.write_u8(127));
+            try!(self.writer.write_be_u64(len as u64));
+        }
+        try!(self.writer.write(payload));
+        self.writer.flush()
+    }
+
+    fn write_text(&mut self, text: &str) -> IoResult<()> {
+        self.write_frame(OP_TEXT, text.as_bytes())
+    }
+}
+
+impl Drop for Connection {
+    // unblocks the reader task
+    fn drop(&mut self) {
+        let _ = self.writer.close_read();
+    }
+}
+
+// returns (fin, opcode, unmasked payload), or None on a frame the
+// client did not mask (RFC 6455 5.1), its payload being left unread
+// fails on data payloads longer than `max_len`
+fn read_frame<R: Reader>(reader: &mut R, max_len: u64) -> IoResult<Option<(bool, u8, Vec<u8>)>> {
+    let b0 = try!(reader.read_u8());
+    let b1 = try!(reader.read_u8());
+    if b1 & 0x80 == 0 {
+        return Ok(None);
+    }
+    let len = match b1 & 0x7F {
+        126 => try!(reader.read_be_u16()) as u64,
+        127 => try!(reader.read_be_u64()),
+        n => n as u64
+    };
+    // control frames (close, ping, pong) are never longer than 125 bytes
+    if len > if b0 & 0x08 != 0 { 125 } else { max_len } {
+        return Err(io::standard_error(io::InvalidInput));
+    }
+    let mask = try!(reader.read_exact(4));
+    let mut payload = try!(reader.read_exact(len as uint));
+    for (i, b) in payload.mut_iter().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok(Some((b0 & 0x80 != 0, b0 & 0x0F, payload)))
+}
+
+// reads a full message, control frames being passed on as they come
+// binary messages are refused, only text ones carry GTP commands
+fn read_incoming<R: Reader>(reader: &mut R, tx: &Sender<Incoming>) -> IoResult<Incoming> {
+    let mut message: Vec<u8> = Vec::new();
+    let mut first = true;
+    loop {
+        let (fin, opcode, payload) = match try!(read_frame(reader, MAX_PAYLOAD - message.len() as u64)) {
+            Some(frame) => frame,
+            None => return Ok(Refused(CLOSE_PROTOCOL_ERROR))
+        };
+        if opcode == OP_CLOSE {
+            return Ok(Close(payload));
+        } else if opcode == OP_PING {
+            let _ = tx.send_opt(Ping(payload));
+            continue;
+        } else if opcode == OP_PONG {
+            continue;
+        } else if opcode == OP_BINARY {
+            return Ok(Refused(CLOSE_UNSUPPORTED_DATA));
+        } else if opcode != if first { OP_TEXT } else { OP_CONTINUATION } {
+            return Ok(Refused(CLOSE_PROTOCOL_ERROR));
+        }
+        first = false;
+        message.push_all(payload.as_slice());
+        if fin {
+            break;
+        }
+    }
+    match String::from_utf8(message) {
+        Ok(txt) => Ok(Message(txt)),
+        Err(_) => Err(io::standard_error(io::InvalidInput))
+    }
+}
+
+// reads the frames of a connection in their own task, so that an
+// analysis can be stopped once the next message comes
+fn spawn_frame_reader(reader: BufferedReader<TcpStream>) -> Receiver<Incoming> {
+    let (tx, rx) = channel();
+    spawn(proc() {
+        let mut reader = reader;
+        loop {
+            let incoming = read_incoming(&mut reader, &tx).unwrap_or(Failed);
+            let over = match incoming {
+                Message(_) | Ping(_) => false,
+                Close(_) | Refused(_) | Failed => true
+            };
+            if tx.send_opt(incoming).is_err() || over {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn accept_key(key: &str) -> String {
+    let digest = sha1(String::from_str(key).append(WEBSOCKET_GUID).as_bytes());
+    digest.as_slice().to_base64(STANDARD)
+}
+
+fn rotl(x: u32, n: uint) -> u32 {
+    (x << n) | (x >> (32 - n))
+}
+
+// only used for the handshake, there is no SHA-1 in the standard library
+fn sha1(data: &[u8]) -> [u8, ..20] {
+    let mut h: [u32, ..5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut msg = Vec::from_slice(data);
+    let bit_len = (data.len() as u64) * 8;
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    for i in range(0u, 8).rev() {
+        msg.push((bit_len >> (i * 8)) as u8);
+    }
+    for chunk in msg.as_slice().chunks(64) {
+        let mut w = [0u32, ..80];
+        for i in range(0u, 16) {
+            w[i] = ((chunk[4*i] as u32) << 24) | ((chunk[4*i+1] as u32) << 16)
+                 | ((chunk[4*i+2] as u32) << 8) | (chunk[4*i+3] as u32);
+        }
+        for i in range(16u, 80) {
+            w[i] = rotl(w[i-3] ^ w[i-8] ^ w[i-14] ^ w[i-16], 1);
+        }
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for i in range(0u, 80) {
+            let (f, k) = if i < 20 {
+                ((b & c) | (!b & d), 0x5A827999u32)
+            } else if i < 40 {
+                (b ^ c ^ d, 0x6ED9EBA1u32)
+            } else if i < 60 {
+                ((b & c) | (b & d) | (c & d), 0x8F1BBCDCu32)
+            } else {
+                (b ^ c ^ d, 0xCA62C1D6u32)
+            };
+            let temp = rotl(a, 5) + f + e + k + w[i];
+            e = d;
+            d = c;
+            c = rotl(b, 30);
+            b = a;
+            a = temp;
+        }
+        h[0] += a;
+        h[1] += b;
+        h[2] += c;
+        h[3] += d;
+        h[4] += e;
+    }
+    let mut out = [0u8, ..20];
+    for i in range(0u, 5) {
+        out[4*i] = (h[i] >> 24) as u8;
+        out[4*i+1] = (h[i] >> 16) as u8;
+        out[4*i+2] = (h[i] >> 8) as u8;
+        out[4*i+3] = h[i] as u8;
+    }
+    out
+}
+
+// serves a client until it leaves
+// returns false if the bot received `quit`
+fn serve_connection<T: api::GoBot>(handler: &bothandler::BotHandler, bot: &mut T, stream: TcpStream,
+                                   transcript: &mut Option<transcript::Transcript>) -> bool {
+    let mut conn = match Connection::accept(stream) {
+        Ok(c) => c,
+        Err(_) => return true
+    };
+    loop {
+        let message = match conn.read_message() {
+            Some(txt) => txt,
+            None => return true
+        };
+        let start = time::precise_time_ns();
+        // a streamed response is sent as it comes, until the next message
+        let mut header: Option<String> = None;
+        let (continue_loop, result) = handler.handle_command_streaming(bot, message.as_slice(), |info| {
+            if header.is_none() {
+                header = Some(String::from_str(info));
+            }
+            conn.write_text(info).is_ok() && !conn.interrupted()
+        });
+        let streamed = header.is_some();
+        match *transcript {
+            Some(ref mut t) => {
+                let response = header.unwrap_or(result.clone());
+                let _ = t.record(message.as_slice(), response.as_slice(), time::precise_time_ns() - start);
+            },
+            None => {}
+        }
+        // empty lines and comments get no response,
+        // a streamed one ends with an empty frame
+        if (streamed || result.len() > 0) && conn.write_text(result.as_slice()).is_err() {
+            return true;
+        }
+        if !continue_loop {
+            let _ = conn.write_frame(OP_CLOSE, []);
+            return false;
+        }
+        // ponders until the next message
+        handler.ponder(bot, |_| !conn.interrupted());
+    }
+}
+
+/// Serves your bot over WebSocket instead of stdin/stdout.
+/// Clients are served one at a time, each text frame must contain
+/// a single GTP command and gets a single response frame.
+/// A client sending a binary frame is disconnected, with the close
+/// code 1003, as is one not masking its frames, with the code 1002.
+/// The response of `lz-analyze` is streamed: its first line, then the
+/// lines reported by `GoBot::gtp_analyze` are sent as their own frames,
+/// and an empty frame ends it once the next command came.
+/// Returns once a client sent `quit`. SIGINT and SIGTERM are handled
+/// as `quit`, as for `main_loop`.
+/// Transcripts are enabled by `GTPRUST_TRANSCRIPT`, as for `main_loop`.
+pub fn websocket_loop<T: api::GoBot>(bot: &mut T, address: &str, port: u16) {
+    let handler = bothandler::BotHandler::from_bot(bot);
+    serve(bot, &handler, transcript::Transcript::from_env(), address, port);
+}
+
+pub fn serve<T: api::GoBot>(bot: &mut T, handler: &bothandler::BotHandler,
+                            mut transcript: Option<transcript::Transcript>, address: &str, port: u16) {
+    let listener = match TcpListener::bind(address, port) {
+        Ok(l) => l,
+        Err(_) => fail!("Cannot bind websocket address.")
+    };
+    let mut acceptor = match listener.listen() {
+        Ok(a) => a,
+        Err(_) => fail!("Cannot listen on websocket address.")
+    };
+    signals::install_handlers();
+    loop {
+        // accepting times out, to watch for signals between clients
+        acceptor.set_timeout(Some(100));
+        match acceptor.accept() {
+            Ok(s) => if !serve_connection(handler, bot, s, &mut transcript) {
+                break;
+            },
+            Err(_) => match signals::termination_requested() {
+                Some(_) => {
+                    handler.handle_command(bot, "quit");
+                    break;
+                },
+                None => continue
+            }
+        }
+    }
+    // exit as a process killed by this signal would, as `main_loop` does
+    match signals::termination_requested() {
+        Some(signum) => unsafe { libc::exit(128 + signum as libc::c_int) },
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::MemReader;
+    use serialize::hex::ToHex;
+    use super::{Incoming, Message, Refused};
+
+    // the frames, masked with a null mask unless `masked` is false
+    fn frame(fin: bool, opcode: u8, payload: &str, masked: bool) -> Vec<u8> {
+        let mut bytes = vec![if fin { 0x80 | opcode } else { opcode }];
+        if masked {
+            bytes.push(0x80 | payload.len() as u8);
+            bytes.push_all([0, 0, 0, 0]);
+        } else {
+            bytes.push(payload.len() as u8);
+        }
+        bytes.push_all(payload.as_bytes());
+        bytes
+    }
+
+    fn incoming(bytes: Vec<u8>) -> Incoming {
+        let (tx, _rx) = channel();
+        super::read_incoming(&mut MemReader::new(bytes), &tx).unwrap()
+    }
+
+    #[test]
+    fn sha1() {
+        assert_eq!(super::sha1(b"abc").as_slice().to_hex().as_slice(),
+                   "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(super::sha1(b"").as_slice().to_hex().as_slice(),
+                   "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[test]
+    fn accept_key() {
+        // example from RFC 6455
+        assert_eq!(super::accept_key("dGhlIHNhbXBsZSBub25jZQ==").as_slice(),
+                   "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn fragmented_text() {
+        let mut bytes = frame(false, 0x1, "gen", true);
+        bytes.push_all(frame(true, 0x0, "move b", true).as_slice());
+        match incoming(bytes) {
+            Message(txt) => assert_eq!(txt.as_slice(), "genmove b"),
+            _ => fail!("not a message")
+        }
+    }
+
+    #[test]
+    fn refused_frames() {
+        let refused = |bytes: Vec<u8>| match incoming(bytes) {
+            Refused(code) => code,
+            _ => 0
+        };
+        assert_eq!(refused(frame(true, 0x1, "genmove b", false)), 1002);
+        assert_eq!(refused(frame(true, 0x2, "genmove b", true)), 1003);
+        assert_eq!(refused(frame(true, 0x0, "genmove b", true)), 1002);
+        assert_eq!(refused(frame(true, 0x3, "genmove b", true)), 1002);
+    }
+}